@@ -0,0 +1,45 @@
+//! Records the versions of the SSH and terminal crates that were locked in, and
+//! of the libssh2 C library being linked, so `--version` can report them.
+
+use std::{env, fs, path::Path};
+
+/// Crates reported by `--version`, with the variable each version is exposed as
+const REPORTED_CRATES: [(&str, &str); 2] = [("ssh2", "SSH2_VERSION"), ("ratatui", "RATATUI_VERSION")];
+
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    // Without a lock file (a git dependency, say) the versions are unknown
+    let lock = fs::read_to_string("Cargo.lock").unwrap_or_default();
+    for (name, variable) in REPORTED_CRATES {
+        println!("cargo:rustc-env={}={}", variable, locked_version(&lock, name).unwrap_or("unknown"));
+    }
+    // libssh2-sys exports the include directory of the libssh2 it builds or
+    // finds on the system, and the header there carries the C library version
+    println!("cargo:rerun-if-env-changed=DEP_SSH2_INCLUDE");
+    let header = env::var("DEP_SSH2_INCLUDE")
+        .map(|include| Path::new(&include).join("libssh2.h"))
+        .unwrap_or_else(|_| Path::new("/usr/include/libssh2.h").to_path_buf());
+    println!("cargo:rerun-if-changed={}", header.display());
+    let header = fs::read_to_string(header).unwrap_or_default();
+    println!("cargo:rustc-env=LIBSSH2_VERSION={}", header_version(&header).unwrap_or("unknown"));
+}
+
+/// The version line following `name = "<name>"` in Cargo.lock
+fn locked_version<'a>(lock: &'a str, name: &str) -> Option<&'a str> {
+    let mut lines = lock.lines();
+    lines.find(|line| *line == format!("name = \"{}\"", name))?;
+    lines
+        .next()?
+        .strip_prefix("version = \"")?
+        .strip_suffix('"')
+}
+
+/// The string in `#define LIBSSH2_VERSION "<version>"`
+fn header_version(header: &str) -> Option<&str> {
+    header.lines().find_map(|line| {
+        line.strip_prefix("#define LIBSSH2_VERSION ")?
+            .trim()
+            .strip_prefix('"')?
+            .strip_suffix('"')
+    })
+}
@@ -0,0 +1,194 @@
+use clap::{Parser, ValueEnum};
+use std::{net::IpAddr, path::PathBuf};
+use tracing::level_filters::LevelFilter;
+
+use crate::ssh::{MemoryMetric, StatsBackend};
+
+/// `--version` output: this crate, then the SSH and terminal libraries it was
+/// built against, as recorded by build.rs
+const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    "\nssh2 ",
+    env!("SSH2_VERSION"),
+    " (libssh2 ",
+    env!("LIBSSH2_VERSION"),
+    ")\nratatui ",
+    env!("RATATUI_VERSION"),
+);
+
+/// SSH Server User Monitor - Monitor CPU and RAM usage per user on remote servers
+#[derive(Parser, Debug)]
+#[command(author, version, long_version = LONG_VERSION, about, long_about = "SSH Server User Monitor - Monitor CPU and RAM usage per user on remote servers\n\nCan be run without any arguments for interactive configuration mode, or with arguments to connect directly.")]
+pub struct Cli {
+    /// SSH server hostname or IP address
+    #[arg(short = 's', long = "server", alias = "ip", visible_alias = "host")]
+    pub server: Option<String>,
+
+    /// SSH port
+    #[arg(short = 'P', long = "port")]
+    pub port: Option<u16>,
+
+    /// SSH username
+    #[arg(short = 'u', long = "user", alias = "username")]
+    pub user: Option<String>,
+
+    /// SSH password (if not using SSH key). Can also be set with SERVER_USERS_PASSWORD
+    #[arg(short = 'p', long = "password", alias = "pass")]
+    pub password: Option<String>,
+
+    /// Path to SSH private key (default: ~/.ssh/id_rsa)
+    #[arg(short = 'k', long = "key", alias = "ssh-key")]
+    pub ssh_key: Option<String>,
+
+    /// Use SSH key authentication instead of password
+    #[arg(long = "use-key")]
+    pub use_ssh_key: bool,
+
+    /// Authenticate with keys held by ssh-agent
+    #[arg(long = "use-agent", conflicts_with_all = ["password", "ssh_key", "use_ssh_key"])]
+    pub use_ssh_agent: bool,
+
+    /// Local IP address to bind the outgoing SSH connection to
+    #[arg(short = 'b', long = "bind")]
+    pub bind: Option<IpAddr>,
+
+    /// Where per-user CPU figures come from
+    #[arg(long = "backend", value_enum)]
+    pub backend: Option<StatsBackend>,
+
+    /// Which per-process memory figure to report
+    #[arg(long = "memory", value_enum)]
+    pub memory: Option<MemoryMetric>,
+
+    /// Don't warn when the server negotiates a deprecated or slow cipher
+    #[arg(long = "no-cipher-warning")]
+    pub no_cipher_warning: bool,
+
+    /// Show how much data the monitor itself sends and receives
+    #[arg(long = "show-traffic")]
+    pub show_traffic: bool,
+
+    /// Alert when a user's CPU usage goes over this percentage
+    #[arg(long = "cpu-alert", value_name = "PERCENT", value_parser = positive_number)]
+    pub cpu_alert: Option<f64>,
+
+    /// Alert when a user's memory usage goes over this many MB
+    #[arg(long = "ram-alert", value_name = "MB", value_parser = positive_number)]
+    pub ram_alert: Option<f64>,
+
+    /// Seconds between polls (minimum 1)
+    #[arg(long = "interval", value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..=3600))]
+    pub interval: Option<u64>,
+
+    /// Seconds between home directory size checks, which are much slower than the other stats
+    #[arg(long = "disk-interval", value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..=86400))]
+    pub disk_interval: Option<u64>,
+
+    /// Milliseconds to wait for a key before redrawing (default 50, clamped to
+    /// 10-500). Longer uses less CPU on slow machines but feels less responsive.
+    #[arg(long = "ui-poll-ms", value_name = "MS")]
+    pub ui_poll_ms: Option<u64>,
+
+    /// Milliseconds a remote command may take before the session is treated
+    /// as stuck and rebuilt (default 10000)
+    #[arg(long = "command-timeout-ms", value_name = "MS", value_parser = clap::value_parser!(u32).range(100..))]
+    pub command_timeout_ms: Option<u32>,
+
+    /// How many samples to keep for the charts (default 100)
+    #[arg(long = "max-history", value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=1_000_000))]
+    pub max_history: Option<usize>,
+
+    /// How many times to try reconnecting after a dropped connection before giving up
+    #[arg(long = "max-retries", value_name = "N", value_parser = clap::value_parser!(u32).range(0..=1000))]
+    pub max_retries: Option<u32>,
+
+    /// Fetch the stats once, print them as JSON and exit without starting the TUI
+    #[arg(long = "once")]
+    pub once: bool,
+
+    /// Run without the TUI, appending one JSON line per poll to this file
+    #[arg(long = "log-file", value_name = "PATH", conflicts_with = "once")]
+    pub log_file: Option<PathBuf>,
+
+    /// Move the log file to <PATH>.1 and start a new one once it reaches this many MB
+    #[arg(long = "log-rotate-mb", value_name = "N", requires = "log_file", value_parser = clap::value_parser!(u64).range(1..))]
+    pub log_rotate_mb: Option<u64>,
+
+    /// Serve the latest readings for Prometheus to scrape on this port
+    #[arg(long = "prometheus-port", value_name = "PORT", conflicts_with_all = ["once", "log_file"])]
+    pub prometheus_port: Option<u16>,
+
+    /// Address to serve the Prometheus metrics on (default: 127.0.0.1, use
+    /// 0.0.0.0 for every interface)
+    #[arg(long = "prometheus-bind", value_name = "ADDRESS", requires = "prometheus_port")]
+    pub prometheus_bind: Option<IpAddr>,
+
+    /// Log file on the server shown by `L` (default: /var/log/syslog)
+    #[arg(long = "log-path", value_name = "PATH")]
+    pub log_path: Option<String>,
+
+    /// Start with the user table filtered to names containing PATTERN, or
+    /// matching it as a glob when it has `*` or `?`
+    #[arg(long = "filter-user", value_name = "PATTERN")]
+    pub filter_user: Option<String>,
+
+    /// Show the monitoring screen filled with this many made-up users instead
+    /// of connecting anywhere, for working on the layout
+    #[arg(
+        long = "dry-run",
+        value_name = "USERS",
+        num_args = 0..=1,
+        default_missing_value = "8",
+        conflicts_with_all = ["once", "log_file"]
+    )]
+    pub dry_run: Option<usize>,
+
+    /// How much to write to the debug log in ~/.local/share/server-users/debug.log
+    #[arg(long = "log-level", value_enum, default_value_t = LogLevel::Off)]
+    pub log_level: LogLevel,
+}
+
+/// Verbosity of the debug log
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn filter(&self) -> LevelFilter {
+        match self {
+            LogLevel::Off => LevelFilter::OFF,
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// A threshold above zero, as the config file requires
+fn positive_number(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(v) if v > 0.0 => Ok(v),
+        Ok(v) => Err(format!("must be greater than 0, got {}", v)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Read when `--password` isn't given, so scripts can keep the password out of the process list
+const PASSWORD_ENV: &str = "SERVER_USERS_PASSWORD";
+
+impl Cli {
+    /// The password from `--password`, or failing that from the environment
+    pub fn password(&self) -> Option<String> {
+        self.password
+            .clone()
+            .or_else(|| std::env::var(PASSWORD_ENV).ok().filter(|password| !password.is_empty()))
+    }
+}
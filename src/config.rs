@@ -0,0 +1,602 @@
+use anyhow::Result;
+use std::{collections::BTreeMap, fmt, net::IpAddr, path::{Path, PathBuf}};
+
+use crate::ssh::{MemoryMetric, StatsBackend};
+
+/// Settings read from ~/.config/server-users/config.toml. Everything is
+/// optional; command-line arguments override whatever is set here.
+#[derive(Clone, Debug, Default)]
+pub struct FileConfig {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub use_ssh_key: Option<bool>,
+    pub use_ssh_agent: Option<bool>,
+    pub ssh_key_path: Option<String>,
+    pub bind: Option<IpAddr>,
+    pub backend: Option<StatsBackend>,
+    pub memory: Option<MemoryMetric>,
+    pub cipher_warning: Option<bool>,
+    pub show_traffic: Option<bool>,
+    pub cpu_alert: Option<f64>,
+    pub ram_alert_mb: Option<f64>,
+    pub max_retries: Option<u32>,
+    pub interval: Option<u64>,
+    pub disk_interval: Option<u64>,
+    /// Milliseconds to wait for a key before redrawing
+    pub ui_poll_ms: Option<u64>,
+    /// Milliseconds a remote command may take before the session is rebuilt
+    pub command_timeout_ms: Option<u32>,
+    pub history_max_age_days: Option<u32>,
+    pub log_path: Option<String>,
+    pub log_highlight: Option<regex::Regex>,
+    pub max_history: Option<usize>,
+    pub jump: Option<JumpConfig>,
+    pub exclude_users: Option<ExcludeUsersConfig>,
+    /// Named `[profiles.<name>]` tables, each holding the same keys as the top
+    /// level except `profiles`
+    pub profiles: BTreeMap<String, FileConfig>,
+}
+
+/// The `[jump]` table. Credentials left out fall back to the main ones.
+#[derive(Clone, Debug, Default)]
+pub struct JumpConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub ssh_key_path: Option<String>,
+    pub use_ssh_agent: Option<bool>,
+}
+
+/// The `[exclude_users]` table: accounts to keep out of the user table
+#[derive(Clone, Debug, Default)]
+pub struct ExcludeUsersConfig {
+    pub names: Vec<String>,
+    pub system_users: bool,
+}
+
+/// A problem with one key in the config file
+#[derive(Clone, Debug)]
+pub struct ConfigError {
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: {}", self.key, self.message)
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    PathBuf::from(std::env::var("HOME").unwrap_or_default())
+        .join(".config")
+        .join("server-users")
+        .join("config.toml")
+}
+
+/// Where data kept between runs, like chart history, is stored
+pub fn data_dir() -> PathBuf {
+    PathBuf::from(std::env::var("HOME").unwrap_or_default())
+        .join(".local")
+        .join("share")
+        .join("server-users")
+}
+
+/// Where `--log-level` sends its output
+pub fn debug_log_path() -> PathBuf {
+    data_dir().join("debug.log")
+}
+
+/// Load the config file if there is one, failing with every invalid key listed
+pub fn load() -> Result<FileConfig> {
+    let path = config_path();
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(FileConfig::default()),
+        Err(e) => return Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+    };
+
+    parse(&contents).map_err(|errors| {
+        let lines: Vec<String> = errors.iter().map(|e| format!("  {}", e)).collect();
+        anyhow::anyhow!("Invalid config file {}:\n{}", path.display(), lines.join("\n"))
+    })
+}
+
+/// The config file as it is now, so saving can keep the keys it doesn't
+/// touch. Refuses rather than returning an empty table when the file exists
+/// but can't be read or parsed, since writing that back would wipe it.
+fn read_for_update(path: &Path) -> Result<toml::Table> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(toml::Table::new()),
+        Err(e) => return Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+    };
+    contents.parse().map_err(|e: toml::de::Error| {
+        anyhow::anyhow!("Not saving, {} is not valid TOML: {}", path.display(), e.message())
+    })
+}
+
+/// Write the given keys to the config file, keeping any other keys already in
+/// it. A `None` value removes the key.
+pub fn save(updates: Vec<(&str, Option<toml::Value>)>) -> Result<()> {
+    let path = config_path();
+    let mut table = read_for_update(&path)?;
+
+    for (key, value) in updates {
+        match value {
+            Some(value) => {
+                table.insert(key.to_string(), value);
+            }
+            None => {
+                table.remove(key);
+            }
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, toml::to_string(&table)?)?;
+    Ok(())
+}
+
+/// Store connection settings as `[profiles.<name>]`, replacing any profile
+/// of that name and leaving the rest of the file alone
+pub fn save_profile(name: &str, values: Vec<(&str, Option<toml::Value>)>) -> Result<()> {
+    let path = config_path();
+    let mut table = read_for_update(&path)?;
+
+    let profile: toml::Table = values
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.to_string(), value?)))
+        .collect();
+    let profiles = table
+        .entry("profiles")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    let Some(profiles) = profiles.as_table_mut() else {
+        anyhow::bail!("`profiles` in {} is not a table", path.display());
+    };
+    profiles.insert(name.to_string(), toml::Value::Table(profile));
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, toml::to_string(&table)?)?;
+    Ok(())
+}
+
+/// Parse and validate config file contents, collecting an error for each bad key
+/// rather than stopping at the first one
+pub fn parse(contents: &str) -> std::result::Result<FileConfig, Vec<ConfigError>> {
+    let table: toml::Table = contents.parse().map_err(|e: toml::de::Error| {
+        vec![ConfigError {
+            key: "<file>".to_string(),
+            message: e.message().to_string(),
+        }]
+    })?;
+    parse_table(&table, "", true)
+}
+
+/// Parse one level of settings, naming errors `<prefix><key>`. Profiles can
+/// only be defined at the top level.
+fn parse_table(
+    table: &toml::Table,
+    prefix: &str,
+    allow_profiles: bool,
+) -> std::result::Result<FileConfig, Vec<ConfigError>> {
+    let mut config = FileConfig::default();
+    let mut errors = Vec::new();
+
+    for (key, value) in table {
+        let result = match key.as_str() {
+            "host" => non_empty_string(value).map(|v| config.host = Some(v)),
+            "port" => integer_in_range(value, 1, 65535).map(|v| config.port = Some(v as u16)),
+            "username" => non_empty_string(value).map(|v| config.username = Some(v)),
+            "use_ssh_key" => boolean(value).map(|v| config.use_ssh_key = Some(v)),
+            "use_ssh_agent" => boolean(value).map(|v| config.use_ssh_agent = Some(v)),
+            "ssh_key_path" => non_empty_string(value).map(|v| config.ssh_key_path = Some(v)),
+            "bind" => string(value).and_then(|v| {
+                v.parse::<IpAddr>()
+                    .map(|ip| config.bind = Some(ip))
+                    .map_err(|_| format!("expected an IP address, got \"{}\"", v))
+            }),
+            "backend" => string(value).and_then(|v| {
+                config.backend = Some(match v.as_str() {
+                    "ps" => StatsBackend::Ps,
+                    "top" => StatsBackend::Top,
+                    _ => return Err(format!("expected \"ps\" or \"top\", got \"{}\"", v)),
+                });
+                Ok(())
+            }),
+            "memory" => string(value).and_then(|v| {
+                config.memory = Some(match v.as_str() {
+                    "rss" => MemoryMetric::Rss,
+                    "vsz" => MemoryMetric::Vsz,
+                    _ => return Err(format!("expected \"rss\" or \"vsz\", got \"{}\"", v)),
+                });
+                Ok(())
+            }),
+            "cipher_warning" => boolean(value).map(|v| config.cipher_warning = Some(v)),
+            "show_traffic" => boolean(value).map(|v| config.show_traffic = Some(v)),
+            "cpu_alert" => positive_number(value).map(|v| config.cpu_alert = Some(v)),
+            "ram_alert_mb" => positive_number(value).map(|v| config.ram_alert_mb = Some(v)),
+            "interval" => integer_in_range(value, 1, 3600).map(|v| config.interval = Some(v as u64)),
+            "disk_interval" => {
+                integer_in_range(value, 1, 86400).map(|v| config.disk_interval = Some(v as u64))
+            }
+            // Out-of-range values are clamped with a warning rather than refused
+            "ui_poll_ms" => integer_in_range(value, 1, i64::MAX).map(|v| config.ui_poll_ms = Some(v as u64)),
+            "command_timeout_ms" => integer_in_range(value, 100, u32::MAX as i64)
+                .map(|v| config.command_timeout_ms = Some(v as u32)),
+            "jump" => match value.as_table() {
+                Some(jump) => {
+                    match parse_jump(jump, prefix) {
+                        Ok(jump) => config.jump = Some(jump),
+                        Err(jump_errors) => errors.extend(jump_errors),
+                    }
+                    Ok(())
+                }
+                None => Err(format!("expected a table, got {}", value.type_str())),
+            },
+            "exclude_users" => match value.as_table() {
+                Some(exclude) => {
+                    match parse_exclude_users(exclude, prefix) {
+                        Ok(exclude) => config.exclude_users = Some(exclude),
+                        Err(exclude_errors) => errors.extend(exclude_errors),
+                    }
+                    Ok(())
+                }
+                None => Err(format!("expected a table, got {}", value.type_str())),
+            },
+            "log_path" => non_empty_string(value).map(|v| config.log_path = Some(v)),
+            "log_highlight" => string(value).and_then(|v| {
+                regex::Regex::new(&v)
+                    .map(|re| config.log_highlight = Some(re))
+                    .map_err(|e| format!("invalid pattern: {}", e))
+            }),
+            "history_max_age_days" => {
+                integer_in_range(value, 1, 3650).map(|v| config.history_max_age_days = Some(v as u32))
+            }
+            "max_history" => {
+                integer_in_range(value, 1, 1_000_000).map(|v| config.max_history = Some(v as usize))
+            }
+            "max_retries" => integer_in_range(value, 0, 1000).map(|v| config.max_retries = Some(v as u32)),
+            "profiles" if allow_profiles => match value.as_table() {
+                Some(profiles) => {
+                    for (name, profile) in profiles {
+                        let profile_prefix = format!("profiles.{}.", name);
+                        match profile.as_table() {
+                            Some(profile) => match parse_table(profile, &profile_prefix, false) {
+                                Ok(profile) => {
+                                    config.profiles.insert(name.clone(), profile);
+                                }
+                                Err(profile_errors) => errors.extend(profile_errors),
+                            },
+                            None => errors.push(ConfigError {
+                                key: format!("profiles.{}", name),
+                                message: format!("expected a table, got {}", profile.type_str()),
+                            }),
+                        }
+                    }
+                    Ok(())
+                }
+                None => Err(format!("expected a table, got {}", value.type_str())),
+            },
+            "profiles" => Err("profiles can't be nested".to_string()),
+            _ => Err("unknown key".to_string()),
+        };
+
+        if let Err(message) = result {
+            errors.push(ConfigError {
+                key: format!("{}{}", prefix, key),
+                message,
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(config)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parse the `[jump]` table, naming errors `<prefix>jump.<key>`
+fn parse_jump(table: &toml::Table, prefix: &str) -> std::result::Result<JumpConfig, Vec<ConfigError>> {
+    let mut jump = JumpConfig::default();
+    let mut errors = Vec::new();
+    let mut has_host = false;
+
+    for (key, value) in table {
+        let result = match key.as_str() {
+            "host" => non_empty_string(value).map(|v| {
+                jump.host = v;
+                has_host = true;
+            }),
+            "port" => integer_in_range(value, 1, 65535).map(|v| jump.port = Some(v as u16)),
+            "username" => non_empty_string(value).map(|v| jump.username = Some(v)),
+            "ssh_key_path" => non_empty_string(value).map(|v| jump.ssh_key_path = Some(v)),
+            "use_ssh_agent" => boolean(value).map(|v| jump.use_ssh_agent = Some(v)),
+            _ => Err("unknown key".to_string()),
+        };
+
+        if let Err(message) = result {
+            errors.push(ConfigError {
+                key: format!("{}jump.{}", prefix, key),
+                message,
+            });
+        }
+    }
+
+    if !has_host && errors.is_empty() {
+        errors.push(ConfigError {
+            key: format!("{}jump.host", prefix),
+            message: "is required".to_string(),
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(jump)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parse the `[exclude_users]` table, naming errors `<prefix>exclude_users.<key>`
+fn parse_exclude_users(
+    table: &toml::Table,
+    prefix: &str,
+) -> std::result::Result<ExcludeUsersConfig, Vec<ConfigError>> {
+    let mut exclude = ExcludeUsersConfig::default();
+    let mut errors = Vec::new();
+
+    for (key, value) in table {
+        let result = match key.as_str() {
+            "names" => match value.as_array() {
+                Some(names) => names
+                    .iter()
+                    .map(non_empty_string)
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map(|names| exclude.names = names),
+                None => Err(format!("expected an array of names, got {}", value.type_str())),
+            },
+            "system_users" => boolean(value).map(|v| exclude.system_users = v),
+            _ => Err("unknown key".to_string()),
+        };
+
+        if let Err(message) = result {
+            errors.push(ConfigError {
+                key: format!("{}exclude_users.{}", prefix, key),
+                message,
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(exclude)
+    } else {
+        Err(errors)
+    }
+}
+
+fn string(value: &toml::Value) -> std::result::Result<String, String> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("expected a string, got {}", value.type_str()))
+}
+
+fn non_empty_string(value: &toml::Value) -> std::result::Result<String, String> {
+    let v = string(value)?;
+    if v.trim().is_empty() {
+        Err("must not be empty".to_string())
+    } else {
+        Ok(v)
+    }
+}
+
+fn boolean(value: &toml::Value) -> std::result::Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("expected true or false, got {}", value.type_str()))
+}
+
+fn positive_number(value: &toml::Value) -> std::result::Result<f64, String> {
+    let v = match value {
+        toml::Value::Integer(v) => *v as f64,
+        toml::Value::Float(v) => *v,
+        _ => return Err(format!("expected a number, got {}", value.type_str())),
+    };
+    if v > 0.0 {
+        Ok(v)
+    } else {
+        Err(format!("must be greater than 0, got {}", v))
+    }
+}
+
+fn integer_in_range(value: &toml::Value, min: i64, max: i64) -> std::result::Result<i64, String> {
+    match value.as_integer() {
+        Some(v) if (min..=max).contains(&v) => Ok(v),
+        Some(v) => Err(format!("must be between {} and {}, got {}", min, max, v)),
+        None => Err(format!("expected an integer, got {}", value.type_str())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The key and message of each error, sorted by key
+    fn errors(contents: &str) -> Vec<(String, String)> {
+        let mut errors: Vec<_> = parse(contents)
+            .expect_err("config should be rejected")
+            .into_iter()
+            .map(|e| (e.key, e.message))
+            .collect();
+        errors.sort();
+        errors
+    }
+
+    fn error(key: &str, message: &str) -> (String, String) {
+        (key.to_string(), message.to_string())
+    }
+
+    #[test]
+    fn parses_a_valid_file() {
+        let config = parse(
+            r#"
+            host = "example.com"
+            port = 2222
+            use_ssh_agent = true
+            backend = "top"
+            cpu_alert = 80
+            ram_alert_mb = 1024.5
+            interval = 5
+            max_retries = 0
+
+            [jump]
+            host = "bastion.example.com"
+
+            [exclude_users]
+            names = ["root", "nobody"]
+            system_users = true
+
+            [profiles.work]
+            host = "work.example.com"
+            username = "deploy"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.host.as_deref(), Some("example.com"));
+        assert_eq!(config.port, Some(2222));
+        assert_eq!(config.use_ssh_agent, Some(true));
+        assert_eq!(config.backend, Some(StatsBackend::Top));
+        assert_eq!(config.cpu_alert, Some(80.0));
+        assert_eq!(config.ram_alert_mb, Some(1024.5));
+        assert_eq!(config.interval, Some(5));
+        assert_eq!(config.max_retries, Some(0));
+        assert_eq!(config.jump.unwrap().host, "bastion.example.com");
+        let exclude = config.exclude_users.unwrap();
+        assert_eq!(exclude.names, ["root", "nobody"]);
+        assert!(exclude.system_users);
+        let work = &config.profiles["work"];
+        assert_eq!(work.host.as_deref(), Some("work.example.com"));
+        assert_eq!(work.username.as_deref(), Some("deploy"));
+    }
+
+    #[test]
+    fn an_empty_file_sets_nothing() {
+        let config = parse("").unwrap();
+        assert!(config.host.is_none());
+        assert!(config.profiles.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let errors = errors(
+            r#"
+            hots = "example.com"
+
+            [jump]
+            host = "bastion.example.com"
+            pot = 22
+
+            [profiles.work]
+            colour = "red"
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                error("hots", "unknown key"),
+                error("jump.pot", "unknown key"),
+                error("profiles.work.colour", "unknown key"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_types() {
+        let errors = errors(
+            r#"
+            port = "22"
+            use_ssh_key = "yes"
+            cpu_alert = "high"
+            jump = "bastion"
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                error("cpu_alert", "expected a number, got string"),
+                error("jump", "expected a table, got string"),
+                error("port", "expected an integer, got string"),
+                error("use_ssh_key", "expected true or false, got string"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        let errors = errors(
+            r#"
+            port = 0
+            interval = 0
+            max_retries = 1001
+            ram_alert_mb = -5
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                error("interval", "must be between 1 and 3600, got 0"),
+                error("max_retries", "must be between 0 and 1000, got 1001"),
+                error("port", "must be between 1 and 65535, got 0"),
+                error("ram_alert_mb", "must be greater than 0, got -5"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        let errors = errors(
+            r#"
+            host = " "
+            backend = "htop"
+            bind = "localhost"
+
+            [jump]
+            port = 22
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                error("backend", "expected \"ps\" or \"top\", got \"htop\""),
+                error("bind", "expected an IP address, got \"localhost\""),
+                error("host", "must not be empty"),
+                error("jump.host", "is required"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_nested_profiles() {
+        let errors = errors(
+            r#"
+            [profiles.work.profiles.home]
+            host = "home.example.com"
+            "#,
+        );
+        assert_eq!(errors, [error("profiles.work.profiles", "profiles can't be nested")]);
+    }
+
+    #[test]
+    fn reports_invalid_toml_against_the_whole_file() {
+        let errors = errors("host = ");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "<file>");
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::ssh::UserStats;
+use crate::ui::Alert;
+
+/// Write the users to a CSV file, one row each, with a header
+pub fn export_csv(users: &[UserStats], path: &Path) -> Result<()> {
+    let mut csv = String::from("username,cpu_percent,ram_mb,process_count,session_count,last_updated\n");
+    for user in users {
+        csv.push_str(&format!(
+            "{},{:.2},{:.2},{},{},{}\n",
+            csv_field(&user.username),
+            user.cpu_percent,
+            user.ram_mb,
+            user.process_count,
+            user.session_count,
+            user.last_updated.to_rfc3339(),
+        ));
+    }
+    std::fs::write(path, csv)?;
+    Ok(())
+}
+
+/// Write the alerts to a JSON file as an array, oldest first
+pub fn export_alerts_json(alerts: &[Alert], path: &Path) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(alerts)?)?;
+    Ok(())
+}
+
+/// Quote a field if it contains anything CSV treats specially
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
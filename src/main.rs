@@ -1,64 +1,820 @@
+mod cli;
+mod config;
+mod export;
+mod metrics;
 mod ssh;
 mod ui;
 
 use anyhow::Result;
+use chrono::{DateTime, Local};
 use clap::Parser;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers, MouseEvent, MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
     backend::CrosstermBackend,
+    layout::Rect,
     Terminal,
 };
 use std::{
-    io,
-    sync::{Arc, Mutex},
-    time::Duration,
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
-use ui::{App, AppState};
+use cli::{Cli, LogLevel};
+use ssh2::Session;
+use ssh::{ConnectionStage, HostInfo, MemoryMetric, Snapshot, StatsBackend, SystemStats, UserStats};
+use ui::{App, AppState, ConfigField, ConfigScreen, ServerConfig};
 
-/// SSH Server User Monitor - Monitor CPU and RAM usage per user on remote servers
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = "SSH Server User Monitor - Monitor CPU and RAM usage per user on remote servers\n\nCan be run without any arguments for interactive configuration mode, or with arguments to connect directly.")]
-struct Args {
-    /// SSH server hostname or IP address
-    #[arg(short = 's', long = "server", alias = "ip")]
-    server: Option<String>,
+/// Messages from the UI thread to the worker
+enum WorkerCommand {
+    Stop,
+    PollNow,
+    /// The user's answer to a host key prompt
+    TrustHost(bool),
+    /// Run a command typed by the user on the session
+    RunCommand(String),
+    /// Signal a process picked from the process list
+    Kill(u32, ssh::KillSignal),
+    /// Fetch the boot time again
+    RefreshBootTime,
+    /// Fetch the processes of the user just selected in the table
+    FetchProcesses(String),
+    /// Start reconnecting again after giving up
+    Retry,
+}
+
+/// Handle to the background thread that owns the SSH session
+struct Worker {
+    command_tx: mpsc::Sender<WorkerCommand>,
+    stage_rx: mpsc::Receiver<ConnectionStage>,
+    handle: JoinHandle<()>,
+    /// Set while a PollNow is queued, so repeated requests collapse into one poll
+    poll_pending: Arc<AtomicBool>,
+}
+
+impl Worker {
+    /// Ask the worker to stop without waiting for it
+    fn stop(&self) {
+        let _ = self.command_tx.send(WorkerCommand::Stop);
+    }
+
+    fn fetch_processes(&self, username: String) {
+        let _ = self.command_tx.send(WorkerCommand::FetchProcesses(username));
+    }
+
+    /// Ask the worker to poll right away instead of waiting for the next interval
+    fn poll_now(&self) {
+        if !self.poll_pending.swap(true, Ordering::SeqCst) {
+            let _ = self.command_tx.send(WorkerCommand::PollNow);
+        }
+    }
+
+    /// Start another round of reconnect attempts on a server that was given up on
+    fn retry(&self) {
+        let _ = self.command_tx.send(WorkerCommand::Retry);
+    }
+
+    /// Pass on whether the user trusts the host key the worker is waiting on
+    fn answer_host_key(&self, trusted: bool) {
+        let _ = self.command_tx.send(WorkerCommand::TrustHost(trusted));
+    }
+
+    fn run_command(&self, command: String) {
+        let _ = self.command_tx.send(WorkerCommand::RunCommand(command));
+    }
+
+    fn refresh_boot_time(&self) {
+        let _ = self.command_tx.send(WorkerCommand::RefreshBootTime);
+    }
+
+    fn kill(&self, pid: u32, signal: ssh::KillSignal) {
+        let _ = self.command_tx.send(WorkerCommand::Kill(pid, signal));
+    }
+
+    /// Stop the worker and wait for it to disconnect the session
+    fn shutdown(self) {
+        self.stop();
+        let _ = self.handle.join();
+    }
+}
+
+/// Whether the worker has been told to stop, skipping over queued poll requests
+fn stop_requested(command_rx: &mpsc::Receiver<WorkerCommand>) -> bool {
+    loop {
+        match command_rx.try_recv() {
+            Ok(WorkerCommand::PollNow)
+            | Ok(WorkerCommand::TrustHost(_))
+            | Ok(WorkerCommand::RunCommand(_))
+            | Ok(WorkerCommand::Kill(..))
+            | Ok(WorkerCommand::RefreshBootTime)
+            | Ok(WorkerCommand::FetchProcesses(_))
+            | Ok(WorkerCommand::Retry) => continue,
+            Ok(WorkerCommand::Stop) | Err(TryRecvError::Disconnected) => return true,
+            Err(TryRecvError::Empty) => return false,
+        }
+    }
+}
+
+/// How long the check before connecting waits on each server
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Each server's handshake time from `preflight`, or the first failure
+type PreflightResult = std::result::Result<Vec<Option<Duration>>, String>;
+
+/// Check that every server can be reached before leaving the config screen,
+/// all at once, so a dead host is reported on the form rather than behind the
+/// loading screen. Servers behind a jump host or bound to a local address are
+/// left to the full connect.
+fn preflight(servers: &[ServerConfig]) -> PreflightResult {
+    let results: Vec<_> = std::thread::scope(|scope| {
+        let checks: Vec<_> = servers
+            .iter()
+            .map(|server| {
+                scope.spawn(move || {
+                    if server.jump.is_some() || server.bind_address.is_some() {
+                        return Ok(None);
+                    }
+                    ssh::test_connection(&server.host, server.port, PREFLIGHT_TIMEOUT)
+                        .map(Some)
+                        .map_err(|e| format!("{}: {}", server.label(), e))
+                })
+            })
+            .collect();
+        checks.into_iter().map(|check| check.join().unwrap_or(Ok(None))).collect()
+    });
+    results.into_iter().collect()
+}
+
+/// Connect to every server in the list at once, one background worker each
+fn start_connections(app_guard: &mut App, app: Arc<Mutex<App>>, servers: Vec<ServerConfig>) -> Vec<Worker> {
+    app_guard.start_servers(servers);
+    (0..app_guard.servers.len())
+        .map(|index| start_connection(app_guard, app.clone(), index))
+        .collect()
+}
+
+/// Connect and take the first reading, so a session only counts as up once it
+/// has produced data
+fn open_session(
+    server: &ServerConfig,
+    memory_metric: MemoryMetric,
+    handlers: &ssh::ConnectHandlers,
+) -> Result<(Session, Snapshot, Option<HostInfo>)> {
+    let sess = ssh::connect(
+        &server.host,
+        server.port,
+        &server.username,
+        &server.auth,
+        server.bind_address,
+        server.jump.as_ref(),
+        handlers,
+    )?;
+    let _ = handlers.stages.send(ConnectionStage::FetchingData);
+    let snapshot = ssh::get_user_stats(&sess, server.backend, memory_metric)?;
+    // Only used for the title bar, so a server that won't say isn't an error
+    let host_info = ssh::get_host_info(&sess).ok();
+    let _ = handlers.stages.send(ConnectionStage::Done);
+    Ok((sess, snapshot, host_info))
+}
+
+/// What the summary panel shows about how `sess` to `server` was set up
+fn connection_info(sess: &Session, server: &ServerConfig) -> ssh::ConnectionInfo {
+    ssh::connection_info(sess, &server.host, server.port, &server.auth, server.jump.as_ref())
+}
+
+/// Fetch the server's boot time and store it, leaving it unknown if the
+/// server can't tell us. Returns false if the server is no longer monitored.
+fn update_boot_time(app: &Arc<Mutex<App>>, sess: &Session, generation: u64, index: usize) -> bool {
+    let boot_time = ssh::get_boot_time(sess).ok();
+    let mut app_guard = app.lock().unwrap();
+    match app_guard.session_mut(generation, index) {
+        Some(server) => {
+            server.boot_time = boot_time;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Put a host key prompt up for the server in slot `index` and wait for the
+/// user to answer it. Being stopped counts as a no.
+fn confirm_host_key(
+    app: &Arc<Mutex<App>>,
+    generation: u64,
+    index: usize,
+    command_rx: &mpsc::Receiver<WorkerCommand>,
+    host_key: &ssh::UnknownHostKey,
+) -> bool {
+    {
+        let mut app_guard = app.lock().unwrap();
+        let Some(server) = app_guard.session_mut(generation, index) else {
+            return false;
+        };
+        server.host_key_prompt = Some(host_key.clone());
+        if app_guard.active_tab == index && app_guard.state != AppState::Config {
+            app_guard.show_active_tab();
+        }
+    }
+
+    loop {
+        match command_rx.recv() {
+            Ok(WorkerCommand::TrustHost(trusted)) => return trusted,
+            Ok(WorkerCommand::PollNow)
+            | Ok(WorkerCommand::RunCommand(_))
+            | Ok(WorkerCommand::Kill(..))
+            | Ok(WorkerCommand::RefreshBootTime)
+            | Ok(WorkerCommand::FetchProcesses(_))
+            | Ok(WorkerCommand::Retry) => continue,
+            Ok(WorkerCommand::Stop) | Err(_) => return false,
+        }
+    }
+}
 
-    /// SSH username
-    #[arg(short = 'u', long = "user", alias = "username")]
-    user: Option<String>,
+/// Connect to the server in slot `index` in a background thread, then keep
+/// polling it at the refresh interval over the same session until stopped,
+/// rebuilding the session if it fails.
+fn start_connection(app_guard: &mut App, app: Arc<Mutex<App>>, index: usize) -> Worker {
+    let generation = app_guard.generation;
+    // Counted before spawning so repeated Enter presses can't start a second attempt
+    app_guard.connects_in_flight += 1;
+    let server = &mut app_guard.servers[index];
+    server.connecting_in_progress = true;
+    server.loading = ui::LoadingScreen::new();
+
+    let server_config = server.server.clone();
+    let memory_metric = app_guard.config.memory_metric;
+    let command_timeout_ms = app_guard.config.command_timeout_ms;
+    let max_age_days = app_guard.history_max_age_days;
+    let max_history = app_guard.max_history;
+
+    let (command_tx, command_rx) = mpsc::channel();
+    let (stage_tx, stage_rx) = mpsc::channel();
+    let poll_pending = Arc::new(AtomicBool::new(false));
+    let worker_poll_pending = Arc::clone(&poll_pending);
+
+    // Try to connect in a background thread
+    let handle = std::thread::spawn(move || {
+        let confirm = |host_key: &ssh::UnknownHostKey| confirm_host_key(&app, generation, index, &command_rx, host_key);
+        let handlers = ssh::ConnectHandlers {
+            stages: &stage_tx,
+            confirm_host_key: &confirm,
+            command_timeout_ms,
+        };
+        let connected = open_session(&server_config, memory_metric, &handlers);
+
+        let mut sess = match connected {
+            Ok((sess, snapshot, host_info)) => {
+                let mut app_guard = app.lock().unwrap();
+                app_guard.connects_in_flight -= 1;
+                // The user may have cancelled while we were connecting
+                let Some(server) = app_guard
+                    .session_mut(generation, index)
+                    .filter(|_| !stop_requested(&command_rx))
+                else {
+                    drop(app_guard);
+                    ssh::disconnect(&sess);
+                    return;
+                };
+                server.connecting_in_progress = false;
+                server.connected = true;
+                server.set_connection_info(connection_info(&sess, &server_config));
+                server.host_info = host_info;
+                // Carry on the charts from the last run against this server
+                let load_result = server.load_history(max_age_days, max_history);
+                app_guard.apply_snapshot(generation, index, snapshot);
+                if app_guard.active_tab == index && app_guard.state == AppState::Connecting {
+                    app_guard.state = AppState::Monitoring;
+                }
+                app_guard.config.error_message = load_result
+                    .err()
+                    .map(|e| format!("Failed to load history for {}: {}", server_config.label(), e));
+                sess
+            }
+            Err(e) => {
+                let mut app_guard = app.lock().unwrap();
+                app_guard.connects_in_flight -= 1;
+                if stop_requested(&command_rx) {
+                    return;
+                }
+                if let Some(server) = app_guard.session_mut(generation, index) {
+                    // Stay on the loading screen so the attempt can be retried in place
+                    server.connecting_in_progress = false;
+                    server.loading.error = Some(e.to_string());
+                    app_guard.config.error_message =
+                        Some(format!("Connection to {} failed: {}", server_config.label(), e));
+                }
+                return;
+            }
+        };
+
+        // The boot time rarely changes, so it is only fetched per connection
+        if !update_boot_time(&app, &sess, generation, index) {
+            ssh::disconnect(&sess);
+            return;
+        }
 
-    /// SSH password (if not using SSH key)
-    #[arg(short = 'p', long = "password", alias = "pass")]
-    password: Option<String>,
+        // Poll until stopped; if the session breaks, swap in a new one and carry on
+        while let Some(error) = poll_session(
+            &app,
+            &sess,
+            generation,
+            index,
+            server_config.backend,
+            &command_rx,
+            &worker_poll_pending,
+        ) {
+            // The old socket may be half dead, so drop it rather than waiting on a disconnect
+            drop(sess);
+            match reconnect(&app, generation, index, &server_config, &command_rx, &handlers, error) {
+                // The server may have rebooted while we were away
+                Some(new_sess) if update_boot_time(&app, &new_sess, generation, index) => sess = new_sess,
+                Some(new_sess) => {
+                    ssh::disconnect(&new_sess);
+                    return;
+                }
+                None => return,
+            }
+        }
 
-    /// Path to SSH private key (default: ~/.ssh/id_rsa)
-    #[arg(short = 'k', long = "key", alias = "ssh-key")]
-    ssh_key: Option<String>,
+        ssh::disconnect(&sess);
+    });
 
-    /// Use SSH key authentication instead of password
-    #[arg(long = "use-key")]
-    use_ssh_key: bool,
+    Worker {
+        command_tx,
+        stage_rx,
+        handle,
+        poll_pending,
+    }
 }
 
+/// Feed made-up stats to the only server slot at the refresh interval, for
+/// `--dry-run`. Takes the place of `start_connection`, so the rest of the UI
+/// can't tell the difference.
+fn start_dry_run(app_guard: &mut App, app: Arc<Mutex<App>>, n_users: usize) -> Worker {
+    let generation = app_guard.generation;
+    if let Some(server) = app_guard.servers.first_mut() {
+        server.connected = true;
+    }
+    app_guard.state = AppState::Monitoring;
+
+    let (command_tx, command_rx) = mpsc::channel();
+    let (_, stage_rx) = mpsc::channel();
+    let poll_pending = Arc::new(AtomicBool::new(false));
+    let worker_poll_pending = Arc::clone(&poll_pending);
 
+    let handle = std::thread::spawn(move || {
+        let mut tick = 0;
+        loop {
+            let started = Instant::now();
+            let (users, ram_total_mb) = ssh::generate_fake_stats(n_users, tick);
+            let ram_used_mb = users.iter().map(|u| u.ram_mb).sum::<f64>().min(ram_total_mb);
+            let load = users.iter().map(|u| u.cpu_percent).sum::<f64>() / 100.0;
+            let snapshot = Snapshot {
+                users,
+                system: SystemStats {
+                    load_average: [load, load * 0.9, load * 0.8],
+                    core_count: 8,
+                    ram_total_mb,
+                    ram_used_mb,
+                    ram_free_mb: ram_total_mb - ram_used_mb,
+                    cpu_times: None,
+                },
+                traffic: ssh::Traffic::default(),
+                network: HashMap::new(),
+                disks: HashMap::new(),
+                poll_duration: started.elapsed(),
+                cpu_ticks: HashMap::new(),
+                clock_ticks_per_sec: 100,
+                io_bytes: HashMap::new(),
+            };
 
-fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: Arc<Mutex<App>>) -> Result<()> {
-    let _data_thread: Option<std::thread::JoinHandle<()>> = None;
+            let interval = {
+                let mut app_guard = app.lock().unwrap();
+                if !app_guard.paused {
+                    if !app_guard.apply_snapshot(generation, 0, snapshot) {
+                        return;
+                    }
+                    tick += 1;
+                }
+                app_guard.refresh_interval_secs
+            };
+
+            match command_rx.recv_timeout(Duration::from_secs(interval)) {
+                Ok(WorkerCommand::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+                // There is nothing to run commands on or kill, so everything
+                // else just brings the next reading forward
+                _ => worker_poll_pending.store(false, Ordering::SeqCst),
+            }
+        }
+    });
+
+    Worker {
+        command_tx,
+        stage_rx,
+        handle,
+        poll_pending,
+    }
+}
+
+/// Keep collecting data over the same session until asked to stop, which
+/// returns None, or until fetching the stats fails, which returns the error
+fn poll_session(
+    app: &Arc<Mutex<App>>,
+    sess: &Session,
+    generation: u64,
+    index: usize,
+    backend: StatsBackend,
+    command_rx: &mpsc::Receiver<WorkerCommand>,
+    poll_pending: &AtomicBool,
+) -> Option<anyhow::Error> {
+    // du walks every file, so it runs on a channel of its own and is picked up
+    // by a later poll once it has finished, rather than holding up the stats
+    let mut disk_check: Option<ssh::DiskUsageCheck> = None;
 
     loop {
+        // The interval can be changed while monitoring and applies from the next wait
+        let interval = app.lock().unwrap().refresh_interval_secs;
+        match command_rx.recv_timeout(Duration::from_secs(interval)) {
+            Ok(WorkerCommand::Stop) | Err(RecvTimeoutError::Disconnected) => return None,
+            Ok(WorkerCommand::RunCommand(command)) => {
+                let result = ssh::run_remote_command(sess, &command).map_err(|e| e.to_string());
+                let mut app_guard = app.lock().unwrap();
+                app_guard.session_mut(generation, index)?;
+                // Only if the panel is still waiting on this command
+                if let Some(run) = app_guard.command_run.as_mut()
+                    && run.command == command
+                    && run.result.is_none()
+                {
+                    run.result = Some(result);
+                }
+                continue;
+            }
+            Ok(WorkerCommand::RefreshBootTime) => {
+                if !update_boot_time(app, sess, generation, index) {
+                    return None;
+                }
+                continue;
+            }
+            Ok(WorkerCommand::FetchProcesses(username)) => {
+                update_selected_user_processes(app, sess, generation, index, &username);
+                continue;
+            }
+            // Falls through to a poll so the process list shows the outcome
+            Ok(WorkerCommand::Kill(pid, signal)) => {
+                let result = ssh::kill_process(sess, pid, signal);
+                let mut app_guard = app.lock().unwrap();
+                app_guard.session_mut(generation, index)?;
+                match result {
+                    Ok(()) => app_guard.show_toast(format!("Sent {} to PID {}", signal.name(), pid), false),
+                    Err(e) => app_guard.show_toast(format!("Failed to kill PID {}: {}", pid, e), true),
+                }
+            }
+            _ => {}
+        }
+
+        // The memory metric can be switched while monitoring
+        let (memory_metric, process_list_user, preview_user, paused, disk_due, log_path, show_affinity, login_user) = {
+            let mut app_guard = app.lock().unwrap();
+            let memory_metric = app_guard.config.memory_metric;
+            let show_affinity = app_guard.show_process_affinity;
+            let paused = app_guard.paused;
+            let disk_interval = Duration::from_secs(app_guard.disk_interval_secs);
+            // Only tail the log while it is on screen
+            let log_path = (app_guard.state == AppState::LogTail && app_guard.active_tab == index)
+                .then(|| app_guard.log_path.clone());
+            let showing_detail = app_guard.state == AppState::UserDetail && app_guard.active_tab == index;
+            let server = app_guard.session_mut(generation, index)?;
+            // One du at a time, however long it takes; the time of the last one
+            // survives reconnects so a new session doesn't start another at once
+            let disk_due = !paused
+                && disk_check.is_none()
+                && server.disk_checked_at.is_none_or(|at| at.elapsed() >= disk_interval);
+            if disk_due {
+                server.disk_checked_at = Some(Instant::now());
+            }
+            (
+                memory_metric,
+                server.process_list_user.clone(),
+                server.selected_user_processes_for.clone(),
+                paused,
+                disk_due,
+                log_path,
+                show_affinity,
+                // A login doesn't change while someone is looking, so once per user is enough
+                server
+                    .process_list_user
+                    .clone()
+                    .filter(|username| showing_detail && server.last_login_for.as_ref() != Some(username)),
+            )
+        };
+
+        // Leave the display frozen while paused
+        if paused {
+            continue;
+        }
+
+        // Cleared before polling, so a request made during this poll gets one of its own
+        poll_pending.store(false, Ordering::SeqCst);
+        match ssh::get_user_stats(sess, backend, memory_metric) {
+            Ok(snapshot) => {
+                let mut app_guard = app.lock().unwrap();
+                if !app_guard.apply_snapshot(generation, index, snapshot) {
+                    return None;
+                }
+            }
+            // A command failing doesn't mean the connection has; only rebuild
+            // the session when it has stopped answering. A timed out command
+            // may still hold the session, so that is rebuilt even if it answers.
+            Err(e) if !e.is_timeout() && ssh::is_alive(sess) => {
+                tracing::warn!("Failed to fetch stats: {:#}", e);
+                let mut app_guard = app.lock().unwrap();
+                app_guard.session_mut(generation, index)?;
+                app_guard.show_toast(format!("Failed to fetch stats: {}", e), true);
+                continue;
+            }
+            Err(e) => {
+                tracing::warn!("Connection lost: {:#}", e);
+                return Some(e.into());
+            }
+        }
+
+        // A failed du only leaves the column stale; lost connections are
+        // caught by the main stats above
+        if let Some(check) = &mut disk_check {
+            match check.poll(sess) {
+                Ok(Some(usage)) => {
+                    disk_check = None;
+                    let mut app_guard = app.lock().unwrap();
+                    if !app_guard.apply_disk_usage(generation, index, usage) {
+                        return None;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    disk_check = None;
+                    tracing::warn!("Failed to fetch home directory sizes: {:#}", e);
+                }
+            }
+        }
+        if disk_due {
+            match ssh::DiskUsageCheck::start(sess) {
+                Ok(check) => disk_check = Some(check),
+                Err(e) => tracing::warn!("Failed to start checking home directory sizes: {:#}", e),
+            }
+        }
+
+        // Refresh the drill-down list while it is open
+        if let Some(username) = process_list_user {
+            match ssh::fetch_processes_for_user(sess, &username) {
+                Ok(mut processes) => {
+                    // Leaves the column at N/A rather than losing the list
+                    if show_affinity && let Err(e) = ssh::fill_cpu_affinity(sess, &mut processes) {
+                        tracing::warn!("Failed to fetch CPU affinity: {:#}", e);
+                    }
+                    let mut app_guard = app.lock().unwrap();
+                    if let Some(server) = app_guard.session_mut(generation, index)
+                        && server.process_list_user.as_deref() == Some(username.as_str())
+                    {
+                        server.set_processes(processes);
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to fetch processes for {}: {:#}", username, e),
+            }
+        } else if let Some(username) = preview_user {
+            // Keep the summary's process preview as fresh as the table
+            update_selected_user_processes(app, sess, generation, index, &username);
+        }
+
+        if let Some(username) = login_user {
+            match ssh::last_login(sess, &username) {
+                Ok(login) => {
+                    let mut app_guard = app.lock().unwrap();
+                    if let Some(server) = app_guard.session_mut(generation, index) {
+                        server.last_login = login;
+                        server.last_login_for = Some(username);
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to fetch last login for {}: {:#}", username, e),
+            }
+        }
+
+        if let Some(path) = log_path {
+            let result = ssh::tail_log(sess, &path).map_err(|e| e.to_string());
+            let mut app_guard = app.lock().unwrap();
+            if let Some(server) = app_guard.session_mut(generation, index) {
+                server.set_log_tail(result);
+            }
+        }
+    }
+}
+
+/// Fetch a user's processes for the summary panel, keeping them only if that
+/// user is still the one selected
+fn update_selected_user_processes(
+    app: &Arc<Mutex<App>>,
+    sess: &Session,
+    generation: u64,
+    index: usize,
+    username: &str,
+) {
+    match ssh::fetch_processes_for_user(sess, username) {
+        Ok(processes) => {
+            let mut app_guard = app.lock().unwrap();
+            if let Some(server) = app_guard.session_mut(generation, index)
+                && server.selected_user_processes_for.as_deref() == Some(username)
+            {
+                server.selected_user_processes = processes;
+            }
+        }
+        Err(e) => tracing::warn!("Failed to fetch processes for {}: {:#}", username, e),
+    }
+}
+
+/// Delay before reconnect attempt `attempt` (from 1): 2s, 4s, 8s, ... capped at 60s
+fn reconnect_delay(attempt: u32) -> Duration {
+    Duration::from_secs((2u64 << (attempt - 1).min(5)).min(60))
+}
+
+/// Rebuild a failed session from scratch, backing off between attempts. Gives
+/// up after the configured number of retries: a lone server goes back to the
+/// config screen, while one of several waits on its tab to be retried so the
+/// others carry on. Returns None if stopped or sent back to the config screen.
+fn reconnect(
+    app: &Arc<Mutex<App>>,
+    generation: u64,
+    index: usize,
+    server_config: &ServerConfig,
+    command_rx: &mpsc::Receiver<WorkerCommand>,
+    handlers: &ssh::ConnectHandlers,
+    error: anyhow::Error,
+) -> Option<Session> {
+    let mut last_error = error.to_string();
+
+    loop {
+        let max_retries = app.lock().unwrap().max_retries;
+        if let Some(sess) = reconnect_attempts(app, generation, index, server_config, command_rx, handlers, &mut last_error)
+        {
+            return Some(sess);
+        }
+
+        let mut app_guard = app.lock().unwrap();
+        let lost = format!(
+            "Lost connection to {} after {} retries: {}",
+            server_config.label(),
+            max_retries,
+            last_error
+        );
+        app_guard.session_mut(generation, index)?;
+        if app_guard.servers.len() == 1 {
+            app_guard.config.error_message = None;
+            app_guard.disconnect_all();
+            // Keep a history save failure from disconnect_all alongside
+            app_guard.config.error_message = Some(match app_guard.config.error_message.take() {
+                Some(save_error) => format!("{}. {}", lost, save_error),
+                None => lost,
+            });
+            return None;
+        }
+        let server = app_guard.session_mut(generation, index)?;
+        server.next_retry_at = None;
+        server.reconnect_failed = true;
+        server.reconnect_error = Some(last_error.clone());
+        drop(app_guard);
+
+        // Wait for `r` on this server's tab
+        loop {
+            match command_rx.recv() {
+                Ok(WorkerCommand::Retry) => break,
+                Ok(WorkerCommand::Stop) | Err(_) => return None,
+                Ok(_) => continue,
+            }
+        }
+    }
+}
+
+/// One round of reconnect attempts, up to the configured number of retries.
+/// Returns None once they have all failed or the worker was stopped.
+fn reconnect_attempts(
+    app: &Arc<Mutex<App>>,
+    generation: u64,
+    index: usize,
+    server_config: &ServerConfig,
+    command_rx: &mpsc::Receiver<WorkerCommand>,
+    handlers: &ssh::ConnectHandlers,
+    last_error: &mut String,
+) -> Option<Session> {
+    let max_retries = app.lock().unwrap().max_retries;
+    for attempt in 1..=max_retries {
+        let delay = reconnect_delay(attempt);
         {
             let mut app_guard = app.lock().unwrap();
+            let server = app_guard.session_mut(generation, index)?;
+            server.connected = false;
+            server.process_list_user = None;
+            server.reconnect_attempts = attempt;
+            server.reconnect_failed = false;
+            server.next_retry_at = Some(Instant::now() + delay);
+            server.reconnect_error = Some(last_error.clone());
+            if app_guard.active_tab == index && app_guard.state != AppState::Config {
+                app_guard.show_active_tab();
+            }
+        }
+
+        // Poll requests skip the rest of the wait
+        if let Ok(WorkerCommand::Stop) | Err(RecvTimeoutError::Disconnected) = command_rx.recv_timeout(delay) {
+            return None;
+        }
+
+        let memory_metric = app.lock().unwrap().config.memory_metric;
+        match open_session(server_config, memory_metric, handlers) {
+            Ok((sess, snapshot, host_info)) => {
+                let mut app_guard = app.lock().unwrap();
+                let server = app_guard.session_mut(generation, index)?;
+                server.host_info = host_info;
+                server.connected = true;
+                server.reconnect_attempts = 0;
+                server.next_retry_at = None;
+                server.reconnect_error = None;
+                server.set_connection_info(connection_info(&sess, server_config));
+                app_guard.apply_snapshot(generation, index, snapshot);
+                if app_guard.active_tab == index && app_guard.state == AppState::Reconnecting {
+                    app_guard.show_active_tab();
+                }
+                return Some(sess);
+            }
+            Err(e) => *last_error = e.to_string(),
+        }
+    }
+    None
+}
+
+/// Stop every worker without waiting for them
+fn stop_all(workers: &mut Vec<Worker>) {
+    for worker in workers.drain(..) {
+        worker.stop();
+    }
+}
+
+fn run_app(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: Arc<Mutex<App>>,
+    workers: &mut Vec<Worker>,
+) -> Result<()> {
+    // The reachability check runs on its own thread so the UI keeps drawing
+    let mut preflight_rx: Option<mpsc::Receiver<(Vec<ServerConfig>, PreflightResult)>> = None;
+
+    loop {
+        {
+            let mut app_guard = app.lock().unwrap();
+            app_guard.tick();
+
+            if let Some((servers, result)) = preflight_rx.as_ref().and_then(|rx| rx.try_recv().ok()) {
+                preflight_rx = None;
+                app_guard.config.checking_reachability = false;
+                match result {
+                    Ok(latencies) => {
+                        stop_all(workers);
+                        *workers = start_connections(&mut app_guard, app.clone(), servers);
+                        for (server, latency) in app_guard.servers.iter_mut().zip(latencies) {
+                            server.preflight_latency_ms = latency.map(|d| d.as_millis() as u64);
+                        }
+                    }
+                    Err(e) => app_guard.config.error_message = Some(e),
+                }
+            }
             
-            // Update loading animation
-            if app_guard.state == AppState::Connecting {
-                app_guard.loading.update();
+            // Show how far each connection attempt has got
+            for (index, worker) in workers.iter().enumerate() {
+                while let Ok(stage) = worker.stage_rx.try_recv() {
+                    if let Some(server) = app_guard.servers.get_mut(index) {
+                        server.loading.set_stage(stage);
+                    }
+                }
             }
             
+            // Fetch the processes of a newly selected user for the summary panel
+            if app_guard.state == AppState::Monitoring
+                && let Some(username) = app_guard.take_process_preview_request()
+                && let Some(worker) = workers.get(app_guard.active_tab)
+            {
+                worker.fetch_processes(username);
+            }
+
             terminal.draw(|f| ui::ui(f, &app_guard))?;
 
             if app_guard.should_quit {
@@ -66,276 +822,743 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: Arc<Mutex
             }
         }
 
-        if event::poll(Duration::from_millis(50))? {
-            if let Event::Key(key) = event::read()? {
-                let mut app_guard = app.lock().unwrap();
+        let ui_poll = Duration::from_millis(app.lock().unwrap().ui_poll_ms);
+        let event = if event::poll(ui_poll)? {
+            Some(event::read()?)
+        } else {
+            None
+        };
+
+        if let Some(Event::Mouse(mouse)) = event {
+            let size = terminal.size()?;
+            let area = Rect::new(0, 0, size.width, size.height);
+            handle_mouse(&mut app.lock().unwrap(), area, mouse);
+        }
+
+        if let Some(Event::Key(key)) = event {
+            let mut app_guard = app.lock().unwrap();
 
-                match app_guard.state {
-                    AppState::Config => {
-                        match key.code {
-                            KeyCode::Char('q') | KeyCode::Esc => app_guard.should_quit = true,
-                            KeyCode::Tab => {
-                                if key.modifiers.contains(KeyModifiers::SHIFT) {
-                                    app_guard.config.previous_field();
-                                } else {
-                                    app_guard.config.next_field();
+            // The help overlay takes every key until it is dismissed
+            if app_guard.show_help {
+                if matches!(key.code, KeyCode::Char('?') | KeyCode::Esc) {
+                    app_guard.show_help = false;
+                }
+                continue;
+            }
+            if key.code == KeyCode::Char('?')
+                && !matches!(app_guard.state, AppState::Config | AppState::CommandInput)
+                && !app_guard.filter_editing
+            {
+                app_guard.show_help = true;
+                continue;
+            }
+
+            match app_guard.state {
+                // Keys go to the profile name while one is being typed
+                AppState::Config if app_guard.config.profile_name_input.is_some() => match key.code {
+                    KeyCode::Esc => app_guard.config.profile_name_input = None,
+                    KeyCode::Enter => {
+                        let name = app_guard.config.profile_name_input.take().unwrap_or_default();
+                        let name = name.trim();
+                        if name.is_empty() {
+                            app_guard.config.error_message = Some("A profile needs a name".to_string());
+                        } else {
+                            match app_guard.save_profile(name).and_then(|()| App::list_profiles()) {
+                                Ok(profiles) => {
+                                    app_guard.config.selected_profile =
+                                        profiles.iter().position(|p| p == name).unwrap_or(0);
+                                    app_guard.config.profiles = profiles;
+                                    app_guard.config.error_message = None;
+                                    app_guard.config.saved_at = Some(Instant::now());
                                 }
-                            }
-                            KeyCode::Up => app_guard.config.previous_field(),
-                            KeyCode::Down => app_guard.config.next_field(),
-                            KeyCode::Char(' ') => app_guard.config.toggle_ssh_key(),
-                            KeyCode::Char(c) => app_guard.config.handle_char(c),
-                            KeyCode::Backspace => app_guard.config.handle_backspace(),
-                            KeyCode::Enter => {
-                                if app_guard.config.is_valid() {
-                                    // Switch to loading state
-                                    app_guard.state = AppState::Connecting;
-                                    app_guard.loading = ui::LoadingScreen::new();
-                                    
-                                    let host = app_guard.config.host.clone();
-                                    let user = app_guard.config.username.clone();
-                                    let password = if app_guard.config.use_ssh_key {
-                                        None
-                                    } else {
-                                        Some(app_guard.config.password.clone())
-                                    };
-                                    let ssh_key = if app_guard.config.use_ssh_key {
-                                        Some(app_guard.config.ssh_key_path.clone())
-                                    } else {
-                                        None
-                                    };
-
-                                    // Try to connect in a background thread
-                                    let app_clone = app.clone();
-                                    std::thread::spawn(move || {
-                                        match ssh::get_user_stats(
-                                            &host,
-                                            &user,
-                                            password.as_deref(),
-                                            ssh_key.as_deref(),
-                                        ) {
-                                            Ok((users, total_ram)) => {
-                                                let mut app_guard = app_clone.lock().unwrap();
-                                                app_guard.total_ram_mb = total_ram;
-                                                app_guard.update_data(users);
-                                                app_guard.state = AppState::Monitoring;
-                                                app_guard.config.error_message = None;
-
-                                                // Start data collection thread
-                                                let app_clone2 = app_clone.clone();
-                                                let host_clone = host.clone();
-                                                let user_clone = user.clone();
-                                                let password_clone = password.clone();
-                                                let ssh_key_clone = ssh_key.clone();
-
-                                                std::thread::spawn(move || loop {
-                                                    std::thread::sleep(Duration::from_secs(2));
-                                                    match ssh::get_user_stats(
-                                                        &host_clone,
-                                                        &user_clone,
-                                                        password_clone.as_deref(),
-                                                        ssh_key_clone.as_deref(),
-                                                    ) {
-                                                        Ok((users, total_ram)) => {
-                                                            let mut app = app_clone2.lock().unwrap();
-                                                            if app.state == AppState::Monitoring {
-                                                                app.total_ram_mb = total_ram;
-                                                                app.update_data(users);
-                                                            } else {
-                                                                break;
-                                                            }
-                                                        }
-                                                        Err(e) => {
-                                                            eprintln!("Error fetching stats: {}", e);
-                                                        }
-                                                    }
-                                                });
-                                            }
-                                            Err(e) => {
-                                                let mut app_guard = app_clone.lock().unwrap();
-                                                app_guard.state = AppState::Config;
-                                                app_guard.config.error_message =
-                                                    Some(format!("Connection failed: {}", e));
-                                            }
-                                        }
-                                    });
+                                Err(e) => {
+                                    app_guard.config.error_message = Some(format!("Failed to save profile: {}", e));
                                 }
                             }
-                            _ => {}
                         }
                     }
-                    AppState::Connecting => match key.code {
-                        KeyCode::Esc => {
-                            app_guard.state = AppState::Config;
+                    KeyCode::Backspace => {
+                        if let Some(name) = app_guard.config.profile_name_input.as_mut() {
+                            name.pop();
                         }
-                        _ => {}
-                    },
-                    AppState::Monitoring => match key.code {
-                        KeyCode::Char('q') | KeyCode::Esc => {
-                            app_guard.state = AppState::Config;
-                            app_guard.users.clear();
-                            app_guard.history.clear();
+                    }
+                    KeyCode::Char(c) => {
+                        if let Some(name) = app_guard.config.profile_name_input.as_mut() {
+                            name.push(c);
+                        }
+                    }
+                    _ => {}
+                },
+                AppState::Config => {
+                    let on_profile = app_guard.config.current_field == ConfigField::Profile;
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => app_guard.should_quit = true,
+                        KeyCode::Tab => {
+                            if key.modifiers.contains(KeyModifiers::SHIFT) {
+                                app_guard.config.previous_field();
+                            } else {
+                                app_guard.config.next_field();
+                            }
+                        }
+                        KeyCode::Up => app_guard.config.previous_field(),
+                        KeyCode::Down => app_guard.config.next_field(),
+                        KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            match app_guard.save_config() {
+                                Ok(()) => {
+                                    app_guard.config.error_message = None;
+                                    app_guard.config.saved_at = Some(Instant::now());
+                                }
+                                Err(e) => {
+                                    app_guard.config.error_message =
+                                        Some(format!("Failed to save config: {}", e));
+                                }
+                            }
                         }
-                        KeyCode::Char('c') | KeyCode::Char('C') => {
-                            app_guard.set_sort(ui::SortBy::Cpu);
+                        KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            app_guard.config.add_server();
                         }
-                        KeyCode::Char('r') | KeyCode::Char('R') => {
-                            app_guard.set_sort(ui::SortBy::Ram);
+                        KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            app_guard.config.remove_last_server();
+                        }
+                        KeyCode::Left if on_profile => app_guard.config.previous_profile(),
+                        KeyCode::Right if on_profile => app_guard.config.next_profile(),
+                        KeyCode::Enter if on_profile && !app_guard.config.profiles.is_empty() => {
+                            match app_guard.config.load_selected_profile() {
+                                Ok(()) => app_guard.config.error_message = None,
+                                Err(e) => {
+                                    app_guard.config.error_message = Some(format!("Failed to load profile: {}", e));
+                                }
+                            }
+                        }
+                        KeyCode::Char('n') if on_profile => app_guard.config.new_profile(),
+                        KeyCode::Char('s') if on_profile => {
+                            app_guard.config.profile_name_input = Some(String::new());
+                        }
+                        KeyCode::Char(' ') => {
+                            app_guard.config.toggle_ssh_key();
+                            app_guard.config.toggle_keyring();
+                            app_guard.config.toggle_backend();
+                            app_guard.config.toggle_jump_host();
+                        }
+                        // Ctrl+Shift+V arrives as an uppercase V. Without a
+                        // clipboard (no display, say) there is nothing to paste.
+                        KeyCode::Char('v' | 'V') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            if let Ok(text) = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+                                app_guard.config.handle_paste(&text);
+                            }
+                        }
+                        KeyCode::Char(c) => app_guard.config.handle_char(c),
+                        KeyCode::Backspace => app_guard.config.handle_backspace(),
+                        KeyCode::Enter
+                            if app_guard.config.can_connect()
+                                && !app_guard.connecting_in_progress()
+                                && preflight_rx.is_none() =>
+                        {
+                            let servers = app_guard.config.servers_to_connect();
+                            app_guard.config.checking_reachability = true;
+                            app_guard.config.error_message = None;
+                            let (result_tx, result_rx) = mpsc::channel();
+                            std::thread::spawn(move || {
+                                let result = preflight(&servers);
+                                let _ = result_tx.send((servers, result));
+                            });
+                            preflight_rx = Some(result_rx);
                         }
-                        KeyCode::Down => app_guard.next_user(),
-                        KeyCode::Up => app_guard.previous_user(),
                         _ => {}
-                    },
+                    }
                 }
+                AppState::Connecting => match key.code {
+                    KeyCode::Esc => {
+                        app_guard.disconnect_all();
+                        stop_all(workers);
+                    }
+                    // Retry with the same settings once the last attempt has failed
+                    KeyCode::Char('r') | KeyCode::Char('R')
+                        if app_guard.server().loading.error.is_some()
+                            && !app_guard.server().connecting_in_progress =>
+                    {
+                        let index = app_guard.active_tab;
+                        workers[index] = start_connection(&mut app_guard, app.clone(), index);
+                    }
+                    KeyCode::Char('[') => app_guard.previous_tab(),
+                    KeyCode::Char(']') => app_guard.next_tab(),
+                    _ => {}
+                },
+                AppState::HostVerification => match key.code {
+                    KeyCode::Char('y') | KeyCode::Char('Y') => {
+                        app_guard.answer_host_key();
+                        if let Some(worker) = workers.get(app_guard.active_tab) {
+                            worker.answer_host_key(true);
+                        }
+                    }
+                    KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                        app_guard.answer_host_key();
+                        if let Some(worker) = workers.get(app_guard.active_tab) {
+                            worker.answer_host_key(false);
+                        }
+                    }
+                    KeyCode::Char('[') => app_guard.previous_tab(),
+                    KeyCode::Char(']') => app_guard.next_tab(),
+                    _ => {}
+                },
+                AppState::Reconnecting => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => {
+                        app_guard.disconnect_all();
+                        stop_all(workers);
+                    }
+                    KeyCode::Char('r') | KeyCode::Char('R') if app_guard.server().reconnect_failed => {
+                        if let Some(worker) = workers.get(app_guard.active_tab) {
+                            worker.retry();
+                        }
+                    }
+                    KeyCode::Char('[') => app_guard.previous_tab(),
+                    KeyCode::Char(']') => app_guard.next_tab(),
+                    _ => {}
+                },
+                // Keys go to the filter bar while it is being typed in
+                AppState::Monitoring if app_guard.filter_editing => match key.code {
+                    KeyCode::Esc => app_guard.clear_filter(),
+                    KeyCode::Enter => app_guard.filter_editing = false,
+                    KeyCode::Backspace => app_guard.pop_filter_char(),
+                    KeyCode::Down => app_guard.next_user(),
+                    KeyCode::Up => app_guard.previous_user(),
+                    KeyCode::Char(c) => app_guard.push_filter_char(c),
+                    _ => {}
+                },
+                AppState::Monitoring => match key.code {
+                    KeyCode::Esc if !app_guard.user_filter.is_empty() => app_guard.clear_filter(),
+                    KeyCode::Char('/') => app_guard.open_filter(),
+                    KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => app_guard.toggle_exclusion(),
+                    KeyCode::Char('e') => app_guard.export_users(),
+                    KeyCode::Char('E') => app_guard.export_alerts(),
+                    KeyCode::Char('T') => app_guard.cycle_theme(),
+                    KeyCode::Char('v') | KeyCode::Char('V') => app_guard.cycle_graph_style(),
+                    KeyCode::Char('x') => app_guard.open_command_input(),
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        if app_guard.request_refresh()
+                            && let Some(worker) = workers.get(app_guard.active_tab)
+                        {
+                            worker.poll_now();
+                        }
+                    }
+                    KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        if let Some(worker) = workers.get(app_guard.active_tab) {
+                            worker.refresh_boot_time();
+                        }
+                    }
+                    KeyCode::Char('d') | KeyCode::Char('D') => app_guard.state = AppState::DiskIO,
+                    KeyCode::Char('L') => {
+                        app_guard.open_log_tail();
+                        if let Some(worker) = workers.get(app_guard.active_tab) {
+                            worker.poll_now();
+                        }
+                    }
+                    KeyCode::Char('q') | KeyCode::Esc => {
+                        app_guard.disconnect_all();
+                        stop_all(workers);
+                    }
+                    KeyCode::Char('c') | KeyCode::Char('C') => {
+                        app_guard.set_sort(ui::SortBy::Cpu);
+                    }
+                    KeyCode::Char('r') | KeyCode::Char('R') => {
+                        app_guard.set_sort(ui::SortBy::Ram);
+                    }
+                    KeyCode::Char('p') | KeyCode::Char('P') => {
+                        app_guard.set_sort(ui::SortBy::ProcessCount);
+                    }
+                    KeyCode::Char('u') | KeyCode::Char('U') => {
+                        app_guard.set_sort(ui::SortBy::Username);
+                    }
+                    KeyCode::Char('t') => app_guard.set_sort(ui::SortBy::LastUpdated),
+                    KeyCode::Char('o') => app_guard.set_sort(ui::SortBy::IoWait),
+                    KeyCode::Char('O') => app_guard.toggle_io_column(),
+                    KeyCode::Home if key.modifiers.contains(KeyModifiers::CONTROL) => app_guard.first_user(),
+                    KeyCode::End if key.modifiers.contains(KeyModifiers::CONTROL) => app_guard.last_user(),
+                    KeyCode::Down if key.modifiers.contains(KeyModifiers::CONTROL) => app_guard.page_down_users(),
+                    KeyCode::Up if key.modifiers.contains(KeyModifiers::CONTROL) => app_guard.page_up_users(),
+                    KeyCode::PageDown => app_guard.page_down_users(),
+                    KeyCode::PageUp => app_guard.page_up_users(),
+                    KeyCode::Down => app_guard.next_user(),
+                    KeyCode::Up => app_guard.previous_user(),
+                    KeyCode::Char('w') | KeyCode::Char('W') => app_guard.toggle_swap_column(),
+                    KeyCode::Char('i') | KeyCode::Char('I') => app_guard.toggle_uid_column(),
+                    KeyCode::Char('n') => app_guard.toggle_top_process_column(),
+                    KeyCode::Char('y') | KeyCode::Char('Y') => app_guard.toggle_cpu_split_columns(),
+                    KeyCode::Char('N') => app_guard.open_alert_history(),
+                    KeyCode::Char('B') => app_guard.toggle_baseline(),
+                    KeyCode::Char('%') => app_guard.toggle_ram_percent_column(),
+                    KeyCode::Char('h') | KeyCode::Char('H') => app_guard.toggle_highwater(),
+                    KeyCode::Char('k') | KeyCode::Char('K') => app_guard.toggle_cpu_trend_column(),
+                    KeyCode::Char('m') | KeyCode::Char('M') => {
+                        app_guard.config.memory_metric = app_guard.config.memory_metric.toggle();
+                    }
+                    KeyCode::Char('+') | KeyCode::Char('=') => app_guard.increase_refresh_interval(),
+                    KeyCode::Char('-') => app_guard.decrease_refresh_interval(),
+                    KeyCode::Char(' ') => app_guard.paused = !app_guard.paused,
+                    KeyCode::Char('g') | KeyCode::Char('G') => {
+                        app_guard.show_load_gauge = !app_guard.show_load_gauge;
+                    }
+                    KeyCode::Left => app_guard.previous_column(),
+                    KeyCode::Right => app_guard.next_column(),
+                    KeyCode::Char('s') | KeyCode::Char('S') => app_guard.sort_by_selected_column(),
+                    KeyCode::Enter if app_guard.open_process_list() => {
+                        if let Some(worker) = workers.get(app_guard.active_tab) {
+                            worker.poll_now();
+                        }
+                    }
+                    KeyCode::Char('<') => app_guard.server_mut().scroll_command_left(),
+                    KeyCode::Char('>') => app_guard.scroll_command_right(),
+                    KeyCode::Char('[') => app_guard.previous_tab(),
+                    KeyCode::Char(']') => app_guard.next_tab(),
+                    _ => {}
+                },
+                AppState::ProcessList if app_guard.pending_kill.is_some() => {
+                    let kill = app_guard.pending_kill.take();
+                    if let (KeyCode::Char('y') | KeyCode::Char('Y'), Some(kill), Some(worker)) =
+                        (key.code, kill, workers.get(app_guard.active_tab))
+                    {
+                        worker.kill(kill.pid, kill.signal);
+                    }
+                }
+                AppState::ProcessList => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => app_guard.close_process_list(),
+                    KeyCode::Char('t') => app_guard.request_kill(ssh::KillSignal::Term),
+                    KeyCode::Char('k') => app_guard.request_kill(ssh::KillSignal::Kill),
+                    KeyCode::Char('a') => {
+                        app_guard.toggle_process_affinity();
+                        if let Some(worker) = workers.get(app_guard.active_tab) {
+                            worker.poll_now();
+                        }
+                    }
+                    KeyCode::Down => app_guard.server_mut().next_process(),
+                    KeyCode::Up => app_guard.server_mut().previous_process(),
+                    KeyCode::Enter => app_guard.open_user_detail(),
+                    _ => {}
+                },
+                AppState::UserDetail => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => app_guard.close_process_list(),
+                    KeyCode::Left | KeyCode::Right => {
+                        app_guard.step_user_detail(key.code == KeyCode::Right);
+                        if let Some(worker) = workers.get(app_guard.active_tab) {
+                            worker.poll_now();
+                        }
+                    }
+                    _ => {}
+                },
+                AppState::CommandInput => match key.code {
+                    KeyCode::Esc => app_guard.state = AppState::Monitoring,
+                    KeyCode::Enter => {
+                        if let Some(command) = app_guard.submit_command()
+                            && let Some(worker) = workers.get(app_guard.active_tab)
+                        {
+                            worker.run_command(command);
+                        }
+                    }
+                    KeyCode::Backspace => {
+                        app_guard.command_input.pop();
+                    }
+                    KeyCode::Char(c) => app_guard.command_input.push(c),
+                    _ => {}
+                },
+                AppState::DiskIO => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => app_guard.state = AppState::Monitoring,
+                    _ => {}
+                },
+                AppState::LogTail => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => app_guard.state = AppState::Monitoring,
+                    KeyCode::Up => app_guard.scroll_log_up(1),
+                    KeyCode::Down => app_guard.scroll_log_down(1),
+                    KeyCode::PageUp => app_guard.scroll_log_up(10),
+                    KeyCode::PageDown => app_guard.scroll_log_down(10),
+                    _ => {}
+                },
+                AppState::AlertHistory => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => app_guard.state = AppState::Monitoring,
+                    KeyCode::Down => app_guard.next_alert(),
+                    KeyCode::Up => app_guard.previous_alert(),
+                    _ => {}
+                },
+                AppState::CommandOutput => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => app_guard.close_command_output(),
+                    KeyCode::Down => app_guard.scroll_command_output_down(),
+                    KeyCode::Up => app_guard.scroll_command_output_up(),
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Clicking a row selects that user, and the wheel moves the selection
+fn handle_mouse(app: &mut App, area: Rect, mouse: MouseEvent) {
+    if app.state != AppState::Monitoring || app.show_help {
+        return;
+    }
+    match mouse.kind {
+        MouseEventKind::Down(_) => app.select_user_at_row(area, mouse.row),
+        MouseEventKind::ScrollDown => app.next_user(),
+        MouseEventKind::ScrollUp => app.previous_user(),
+        _ => {}
+    }
+}
+
+/// What `--once` prints
+#[derive(serde::Serialize)]
+struct OnceOutput {
+    fetched_at: DateTime<Local>,
+    total_ram_mb: f64,
+    users: Vec<UserStats>,
+}
+
+/// Why the settings can't be used to connect, for modes without the form to
+/// show it on
+fn invalid_fields(config: &ConfigScreen) -> Option<String> {
+    let errors = config.validate_fields();
+    (!errors.is_empty()).then(|| {
+        errors
+            .iter()
+            .map(|(_, reason)| reason.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    })
+}
+
+/// Connect, fetch the stats a single time and print them as JSON for scripts
+fn run_once(config: &ConfigScreen) -> Result<()> {
+    if let Some(reasons) = invalid_fields(config) {
+        anyhow::bail!(
+            "--once needs a host, username and password, key or agent from the command line or config file ({})",
+            reasons
+        );
+    }
+
+    // There is nobody to ask, so only hosts already in known_hosts are accepted
+    let handlers = ssh::ConnectHandlers {
+        stages: &mpsc::channel().0,
+        confirm_host_key: &|_| false,
+        command_timeout_ms: config.command_timeout_ms,
+    };
+    let (sess, snapshot, _) = open_session(&config.server_config(), config.memory_metric, &handlers)?;
+    ssh::disconnect(&sess);
+
+    let output = OnceOutput {
+        fetched_at: Local::now(),
+        total_ram_mb: snapshot.system.ram_total_mb,
+        users: snapshot.users,
+    };
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
+/// One line of the `--log-file` log
+#[derive(serde::Serialize)]
+struct LogLine<'a> {
+    timestamp: DateTime<Local>,
+    host: String,
+    users: &'a [UserStats],
+}
+
+/// Settings for running without the TUI
+struct LogSettings {
+    path: PathBuf,
+    rotate_bytes: Option<u64>,
+    interval: Duration,
+    max_retries: u32,
+}
+
+/// Open the log for appending, so restarts add to what is already there
+fn open_log(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))
+}
+
+/// Append one poll to the log, first moving a full log aside to `<path>.1`
+fn write_log_line(log: &mut File, settings: &LogSettings, line: &LogLine) -> Result<()> {
+    let mut json = serde_json::to_string(line)?;
+    json.push('\n');
+    if let Some(limit) = settings.rotate_bytes
+        && log.metadata()?.len() + json.len() as u64 > limit
+    {
+        let mut rotated = settings.path.clone().into_os_string();
+        rotated.push(".1");
+        std::fs::rename(&settings.path, &rotated)?;
+        *log = open_log(&settings.path)?;
+    }
+    log.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Poll the server forever without a terminal UI, logging each poll as a
+/// JSON line. A dropped connection is retried with the usual backoff; the
+/// run ends with an error once the retries run out.
+fn run_headless(config: &ConfigScreen, settings: LogSettings) -> Result<()> {
+    if let Some(reasons) = invalid_fields(config) {
+        anyhow::bail!(
+            "--log-file needs a host, username and password, key or agent from the command line or config file ({})",
+            reasons
+        );
+    }
+    let mut log = open_log(&settings.path)?;
+
+    // There is nobody to ask, so only hosts already in known_hosts are accepted
+    let handlers = ssh::ConnectHandlers {
+        stages: &mpsc::channel().0,
+        confirm_host_key: &|_| false,
+        command_timeout_ms: config.command_timeout_ms,
+    };
+    let server = config.server_config();
+    let host = server.label();
+    let (mut sess, snapshot, _) = open_session(&server, config.memory_metric, &handlers)?;
+    let mut users = snapshot.users;
+
+    loop {
+        let line = LogLine {
+            timestamp: Local::now(),
+            host: host.clone(),
+            users: &users,
+        };
+        write_log_line(&mut log, &settings, &line)?;
+        std::thread::sleep(settings.interval);
+
+        match ssh::get_user_stats(&sess, server.backend, config.memory_metric) {
+            Ok(snapshot) => users = snapshot.users,
+            Err(e) => {
+                eprintln!("Lost connection to {}: {}", host, e);
+                let mut attempt = 0;
+                let (new_sess, snapshot) = loop {
+                    attempt += 1;
+                    if attempt > settings.max_retries {
+                        anyhow::bail!("Gave up on {} after {} retries", host, settings.max_retries);
+                    }
+                    std::thread::sleep(reconnect_delay(attempt));
+                    match open_session(&server, config.memory_metric, &handlers) {
+                        Ok((new_sess, snapshot, _)) => break (new_sess, snapshot),
+                        Err(e) => eprintln!("Reconnect attempt {} to {} failed: {}", attempt, host, e),
+                    }
+                };
+                sess = new_sess;
+                users = snapshot.users;
             }
         }
     }
+}
 
+/// Send tracing output to the debug log. Nothing goes to the terminal,
+/// which belongs to the TUI.
+fn init_debug_log(level: LogLevel) -> Result<()> {
+    if level == LogLevel::Off {
+        return Ok(());
+    }
+    let path = config::debug_log_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    tracing_subscriber::fmt()
+        .with_writer(Mutex::new(open_log(&path)?))
+        .with_max_level(level.filter())
+        .with_ansi(false)
+        .init();
+    tracing::info!("server_users v{} starting", env!("CARGO_PKG_VERSION"));
     Ok(())
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let args = Cli::parse();
+    init_debug_log(args.log_level)?;
 
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    // Load the config file before touching the terminal so errors print normally
+    let file_config = config::load()?;
 
     let mut app = App::new();
+
+    // App::new() already picked up the connection settings from the config
+    // file; everything is overridden by command-line arguments below
+    if let Some(show_traffic) = file_config.show_traffic {
+        app.show_traffic = show_traffic;
+    }
+    app.alert_config.cpu_threshold = file_config.cpu_alert;
+    app.alert_config.ram_threshold_mb = file_config.ram_alert_mb;
+    if let Some(max_retries) = file_config.max_retries {
+        app.max_retries = max_retries;
+    }
+    if let Some(interval) = file_config.interval {
+        app.refresh_interval_secs = interval;
+    }
+    if let Some(disk_interval) = file_config.disk_interval {
+        app.disk_interval_secs = disk_interval;
+    }
+    if let Some(max_age_days) = file_config.history_max_age_days {
+        app.history_max_age_days = max_age_days;
+    }
+    if let Some(max_history) = file_config.max_history {
+        app.max_history = max_history;
+    }
+    if let Some(log_path) = file_config.log_path {
+        app.log_path = log_path;
+    }
+    if let Some(log_highlight) = file_config.log_highlight {
+        app.log_highlight = log_highlight;
+    }
+    if let Some(filter) = args.filter_user.clone() {
+        app.user_filter = filter;
+    }
+    if let Some(exclude) = &file_config.exclude_users {
+        app.exclusion.excluded_users = exclude.names.iter().cloned().collect();
+        app.exclusion.exclude_system_users = exclude.system_users;
+    }
     
+    let password = args.password();
+
     // Check if any CLI args were provided
-    let has_cli_args = args.server.is_some() || args.user.is_some() || args.password.is_some() || args.ssh_key.is_some() || args.use_ssh_key;
+    let has_cli_args = args.server.is_some() || args.port.is_some() || args.user.is_some() || password.is_some() || args.ssh_key.is_some() || args.use_ssh_key || args.use_ssh_agent;
     
     // Pre-populate config from command-line arguments
     if let Some(server) = args.server {
         app.config.host = server;
     }
+    if let Some(port) = args.port {
+        app.config.port = port;
+    }
     if let Some(user) = args.user {
         app.config.username = user;
     }
-    if let Some(password) = args.password {
+    if let Some(password) = password {
         app.config.password = password;
         app.config.use_ssh_key = false;
+        app.config.use_ssh_agent = false;
     }
     if let Some(ssh_key) = args.ssh_key {
         app.config.ssh_key_path = ssh_key;
         app.config.use_ssh_key = true;
+        app.config.use_ssh_agent = false;
     }
     if args.use_ssh_key {
         app.config.use_ssh_key = true;
+        app.config.use_ssh_agent = false;
     }
-    
-    // If all required fields are provided, skip config and connect directly
-    if app.config.is_valid() && has_cli_args {
-        app.state = AppState::Connecting;
-        app.loading = ui::LoadingScreen::new();
-        
-        let host = app.config.host.clone();
-        let user = app.config.username.clone();
-        let password = if app.config.use_ssh_key {
-            None
-        } else {
-            Some(app.config.password.clone())
-        };
-        let ssh_key = if app.config.use_ssh_key {
-            Some(app.config.ssh_key_path.clone())
-        } else {
-            None
+    if args.use_ssh_agent {
+        app.config.use_ssh_key = false;
+        app.config.use_ssh_agent = true;
+    }
+    if let Some(bind) = args.bind {
+        app.config.bind_address = Some(bind);
+    }
+    if let Some(backend) = args.backend {
+        app.config.backend = backend;
+    }
+    if let Some(memory) = args.memory {
+        app.config.memory_metric = memory;
+    }
+    if args.no_cipher_warning {
+        app.config.warn_weak_algorithms = false;
+    }
+    if args.show_traffic {
+        app.show_traffic = true;
+    }
+    if let Some(cpu_alert) = args.cpu_alert {
+        app.alert_config.cpu_threshold = Some(cpu_alert);
+    }
+    if let Some(ram_alert) = args.ram_alert {
+        app.alert_config.ram_threshold_mb = Some(ram_alert);
+    }
+    if let Some(max_retries) = args.max_retries {
+        app.max_retries = max_retries;
+    }
+    if let Some(interval) = args.interval {
+        app.refresh_interval_secs = interval;
+    }
+    if let Some(disk_interval) = args.disk_interval {
+        app.disk_interval_secs = disk_interval;
+    }
+    if let Some(max_history) = args.max_history {
+        app.max_history = max_history;
+    }
+    if let Some(timeout_ms) = args.command_timeout_ms.or(file_config.command_timeout_ms) {
+        app.config.command_timeout_ms = timeout_ms;
+    }
+    // Printed before the terminal is taken over, so it stays in the scrollback
+    if let Some(ui_poll_ms) = args.ui_poll_ms.or(file_config.ui_poll_ms)
+        && let Some(warning) = app.set_ui_poll_ms(ui_poll_ms)
+    {
+        eprintln!("Warning: {}", warning);
+    }
+    if let Some(log_path) = args.log_path {
+        app.log_path = log_path;
+    }
+
+    if args.once {
+        return run_once(&app.config);
+    }
+    if let Some(path) = args.log_file {
+        let settings = LogSettings {
+            path,
+            rotate_bytes: args.log_rotate_mb.map(|mb| mb * 1024 * 1024),
+            interval: Duration::from_secs(app.refresh_interval_secs),
+            max_retries: app.max_retries,
         };
-        
-        let app_arc = Arc::new(Mutex::new(app));
-        let app_clone = app_arc.clone();
-        
-        // Try to connect in a background thread
-        std::thread::spawn(move || {
-            match ssh::get_user_stats(
-                &host,
-                &user,
-                password.as_deref(),
-                ssh_key.as_deref(),
-            ) {
-                Ok((users, total_ram)) => {
-                    let mut app_guard = app_clone.lock().unwrap();
-                    app_guard.total_ram_mb = total_ram;
-                    app_guard.update_data(users);
-                    app_guard.state = AppState::Monitoring;
-                    app_guard.config.error_message = None;
-
-                    // Start data collection thread
-                    let app_clone2 = app_clone.clone();
-                    let host_clone = host.clone();
-                    let user_clone = user.clone();
-                    let password_clone = password.clone();
-                    let ssh_key_clone = ssh_key.clone();
-
-                    std::thread::spawn(move || loop {
-                        std::thread::sleep(Duration::from_secs(2));
-                        match ssh::get_user_stats(
-                            &host_clone,
-                            &user_clone,
-                            password_clone.as_deref(),
-                            ssh_key_clone.as_deref(),
-                        ) {
-                            Ok((users, total_ram)) => {
-                                let mut app = app_clone2.lock().unwrap();
-                                if app.state == AppState::Monitoring {
-                                    app.total_ram_mb = total_ram;
-                                    app.update_data(users);
-                                } else {
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!("Error fetching stats: {}", e);
-                            }
-                        }
-                    });
-                }
-                Err(e) => {
-                    let mut app_guard = app_clone.lock().unwrap();
-                    app_guard.state = AppState::Config;
-                    app_guard.config.error_message =
-                        Some(format!("Connection failed: {}", e));
-                }
-            }
-        });
-        
-        let res = run_app(&mut terminal, app_arc);
-        
-        // Restore terminal
-        disable_raw_mode()?;
-        execute!(
-            terminal.backend_mut(),
-            LeaveAlternateScreen,
-            DisableMouseCapture
-        )?;
-        terminal.show_cursor()?;
-
-        if let Err(err) = res {
-            println!("Error: {:?}", err);
-        }
-    } else {
-        // Normal interactive mode
-        let app_arc = Arc::new(Mutex::new(app));
-        let res = run_app(&mut terminal, app_arc);
-
-        // Restore terminal
-        disable_raw_mode()?;
-        execute!(
-            terminal.backend_mut(),
-            LeaveAlternateScreen,
-            DisableMouseCapture
-        )?;
-        terminal.show_cursor()?;
-
-        if let Err(err) = res {
-            println!("Error: {:?}", err);
+        return run_headless(&app.config, settings);
+    }
+
+    let app_arc = Arc::new(Mutex::new(app));
+    let mut workers = Vec::new();
+
+    // Before touching the terminal, so a port in use is reported normally
+    if let Some(port) = args.prometheus_port {
+        let address = args.prometheus_bind.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        metrics::spawn_server(address, port, app_arc.clone())?;
+    }
+
+    // Setup terminal
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    // If all required fields are provided, skip config and connect directly
+    {
+        let mut app_guard = app_arc.lock().unwrap();
+        if let Some(n_users) = args.dry_run {
+            app_guard.start_servers(vec![ServerConfig {
+                host: "dry-run".to_string(),
+                port: 22,
+                username: "demo".to_string(),
+                auth: ssh::AuthMethod::Agent,
+                bind_address: None,
+                backend: StatsBackend::Ps,
+                jump: None,
+            }]);
+            workers = vec![start_dry_run(&mut app_guard, app_arc.clone(), n_users)];
+        } else if app_guard.config.is_valid() && has_cli_args {
+            let servers = app_guard.config.servers_to_connect();
+            workers = start_connections(&mut app_guard, app_arc.clone(), servers);
         }
     }
 
+    let res = run_app(&mut terminal, app_arc, &mut workers);
+
+    // Close the SSH sessions cleanly before exiting
+    for worker in workers {
+        worker.shutdown();
+    }
+
+    // Restore terminal
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+
+    if let Err(err) = res {
+        println!("Error: {:?}", err);
+    }
+
     Ok(())
 }
-
@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::ssh::UserStats;
+use crate::ui::App;
+
+/// Name, help text and value of a per-user gauge
+type UserMetric = (&'static str, &'static str, fn(&UserStats) -> f64);
+
+const USER_METRICS: [UserMetric; 3] = [
+    ("server_users_cpu_percent", "CPU usage of the user's processes, in percent of one core", |u| {
+        u.cpu_percent
+    }),
+    ("server_users_ram_mb", "Memory used by the user's processes, in MB", |u| u.ram_mb),
+    ("server_users_process_count", "Number of processes the user is running", |u| {
+        u.process_count as f64
+    }),
+];
+
+/// How long a scraper gets to send its request or take the answer. Requests
+/// are answered one at a time, so a client that goes quiet holds up the rest.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serve the latest readings for Prometheus to scrape on `address:port`, from
+/// a background thread. Fails straight away if the port is taken.
+pub fn spawn_server(address: IpAddr, port: u16, app: Arc<Mutex<App>>) -> Result<()> {
+    let listener = TcpListener::bind((address, port))
+        .with_context(|| format!("Failed to listen on {}:{}", address, port))?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(e) = respond(stream, &app) {
+                tracing::warn!("Failed to answer a metrics request: {:#}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Answer any request with the metrics, whatever path it asked for
+fn respond(mut stream: TcpStream, app: &Arc<Mutex<App>>) -> Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    // Read the request line and headers so the client isn't cut off mid-send
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim().is_empty() {
+        line.clear();
+    }
+
+    let body = render(&app.lock().unwrap());
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/openmetrics-text; version=1.0.0; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// Every server's users and system figures in OpenMetrics text format
+pub fn render(app: &App) -> String {
+    let mut out = String::new();
+    for (name, help, value) in USER_METRICS {
+        let _ = writeln!(out, "# TYPE {} gauge\n# HELP {} {}", name, name, help);
+        for server in &app.servers {
+            for user in &server.users {
+                let _ = writeln!(
+                    out,
+                    "{}{{server=\"{}\",user=\"{}\"}} {}",
+                    name,
+                    escape_label(&server.server.label()),
+                    escape_label(&user.username),
+                    value(user)
+                );
+            }
+        }
+    }
+
+    let _ = writeln!(
+        out,
+        "# TYPE server_users_total_ram_mb gauge\n# HELP server_users_total_ram_mb Total memory of the server, in MB"
+    );
+    for server in app.servers.iter().filter(|s| s.system.ram_total_mb > 0.0) {
+        let _ = writeln!(
+            out,
+            "server_users_total_ram_mb{{server=\"{}\"}} {}",
+            escape_label(&server.server.label()),
+            server.system.ram_total_mb
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "# TYPE server_users_poll_duration_ms gauge\n# HELP server_users_poll_duration_ms How long the last poll of the server took, in milliseconds"
+    );
+    for server in &app.servers {
+        if let Some(duration) = server.last_poll_duration_ms {
+            let _ = writeln!(
+                out,
+                "server_users_poll_duration_ms{{server=\"{}\"}} {}",
+                escape_label(&server.server.label()),
+                duration
+            );
+        }
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
+
+/// Backslashes, quotes and newlines have to be escaped inside label values
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::ConfigScreen;
+
+    #[test]
+    fn escapes_awkward_usernames() {
+        let mut app = App::new();
+        app.start_servers(vec![ConfigScreen::new().server_config()]);
+        app.server_mut().users = vec![UserStats::test_fixture("a\"b\\c\nd", 12.5, 256.0)];
+        let server = escape_label(&app.server().server.label());
+
+        let out = render(&app);
+        let lines: Vec<&str> = out.lines().collect();
+        for (name, value) in [
+            ("server_users_cpu_percent", "12.5"),
+            ("server_users_ram_mb", "256"),
+            ("server_users_process_count", "1"),
+        ] {
+            let expected = format!(r#"{}{{server="{}",user="a\"b\\c\nd"}} {}"#, name, server, value);
+            assert!(lines.contains(&expected.as_str()), "missing {}", expected);
+        }
+        assert_eq!(lines.last(), Some(&"# EOF"));
+        assert!(out.ends_with("# EOF\n"));
+    }
+}
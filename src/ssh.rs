@@ -1,77 +1,1691 @@
-use anyhow::Result;
 use chrono::{DateTime, Local};
-use ssh2::Session;
+use ssh2::{Channel, CheckResult, HashType, HostKeyType, KnownHostFileKind, MethodType, Session};
 use std::{
-    io::Read,
-    net::TcpStream,
+    collections::HashMap,
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
 };
+use tokio::net::TcpSocket;
 
+/// Everything that can go wrong talking to a server. Callers outside this
+/// module wrap it in anyhow like any other error.
+#[derive(Debug)]
+pub enum SshMonitorError {
+    /// Opening the TCP connection, or a local socket for it, failed
+    TcpConnect(io::Error),
+    SshHandshake(ssh2::Error),
+    AuthFailed(ssh2::Error),
+    /// A command ran but exited non-zero, with what it printed
+    CommandFailed { exit_code: i32, stderr: String },
+    /// The server printed something that couldn't be understood
+    ParseError(String),
+    /// The host key was missing, changed or not trusted
+    HostKey(String),
+    /// A channel on an established session failed
+    Session(ssh2::Error),
+    /// A command didn't finish within the session's timeout; the session
+    /// may be stuck behind it, so it should be rebuilt
+    CommandTimeout { timeout_ms: u32 },
+    /// Reading a channel or a local file failed
+    Io(io::Error),
+    /// What was being attempted when `source` happened, like anyhow's context
+    Context { message: String, source: Box<SshMonitorError> },
+}
+
+impl SshMonitorError {
+    /// Describe what was being attempted, keeping this error as the cause
+    fn context(self, message: impl Into<String>) -> Self {
+        SshMonitorError::Context {
+            message: message.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Whether this is, or was caused by, a command timing out
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            SshMonitorError::CommandTimeout { .. } => true,
+            SshMonitorError::Context { source, .. } => source.is_timeout(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for SshMonitorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SshMonitorError::TcpConnect(e) | SshMonitorError::Io(e) => write!(f, "{}", e),
+            SshMonitorError::SshHandshake(e) => write!(f, "SSH handshake failed: {}", e),
+            SshMonitorError::AuthFailed(e) | SshMonitorError::Session(e) => write!(f, "{}", e),
+            SshMonitorError::CommandFailed { exit_code, stderr } if stderr.is_empty() => {
+                write!(f, "Command exited with status {}", exit_code)
+            }
+            SshMonitorError::CommandFailed { stderr, .. } => write!(f, "{}", stderr),
+            SshMonitorError::CommandTimeout { timeout_ms } => {
+                write!(f, "Command timed out after {} ms", timeout_ms)
+            }
+            SshMonitorError::ParseError(message) | SshMonitorError::HostKey(message) => write!(f, "{}", message),
+            SshMonitorError::Context { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for SshMonitorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SshMonitorError::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<ssh2::Error> for SshMonitorError {
+    fn from(e: ssh2::Error) -> Self {
+        SshMonitorError::Session(e)
+    }
+}
+
+impl From<io::Error> for SshMonitorError {
+    fn from(e: io::Error) -> Self {
+        SshMonitorError::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, SshMonitorError>;
+
+/// Where per-user CPU figures come from
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum StatsBackend {
+    /// `ps aux`: CPU averaged over each process's lifetime
+    Ps,
+    /// `top -bn1`: CPU over the last sampling interval
+    Top,
+}
+
+impl StatsBackend {
+    pub fn label(&self) -> &'static str {
+        match self {
+            StatsBackend::Ps => "ps aux (lifetime average)",
+            StatsBackend::Top => "top -b (current)",
+        }
+    }
+
+    pub fn toggle(&self) -> StatsBackend {
+        match self {
+            StatsBackend::Ps => StatsBackend::Top,
+            StatsBackend::Top => StatsBackend::Ps,
+        }
+    }
+}
+
+/// Which per-process memory figure is summed per user
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum MemoryMetric {
+    /// Resident set size: memory actually in RAM
+    Rss,
+    /// Virtual size: all mapped address space
+    Vsz,
+}
+
+impl MemoryMetric {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MemoryMetric::Rss => "RSS",
+            MemoryMetric::Vsz => "VSZ",
+        }
+    }
+
+    pub fn toggle(&self) -> MemoryMetric {
+        match self {
+            MemoryMetric::Rss => MemoryMetric::Vsz,
+            MemoryMetric::Vsz => MemoryMetric::Rss,
+        }
+    }
+}
+
+/// How a session was set up, read once when it connects
+#[derive(Clone, Debug)]
+pub struct ConnectionInfo {
+    pub host: String,
+    pub port: u16,
+    /// "password", "key file" or "ssh-agent"
+    pub auth: &'static str,
+    /// `host:port` of the jump host the session was tunnelled through
+    pub jump: Option<String>,
+    /// Identification string the server sent, e.g. "SSH-2.0-OpenSSH_9.6"
+    pub server_version: Option<String>,
+    pub algorithms: Algorithms,
+    /// Time the pre-connect check took to get through the handshake
+    pub latency_ms: Option<u64>,
+}
+
+/// Algorithms negotiated during the SSH handshake
 #[derive(Clone, Debug)]
+pub struct Algorithms {
+    pub kex: String,
+    pub host_key: String,
+    pub cipher: String,
+    pub mac: String,
+}
+
+/// Algorithms that are deprecated or noticeably slow on modern hardware
+const WEAK_ALGORITHMS: &[&str] = &[
+    "3des-cbc",
+    "blowfish-cbc",
+    "cast128-cbc",
+    "arcfour",
+    "arcfour128",
+    "arcfour256",
+    "aes128-cbc",
+    "aes192-cbc",
+    "aes256-cbc",
+    "rijndael-cbc@lysator.liu.se",
+    "hmac-md5",
+    "hmac-md5-96",
+    "hmac-sha1-96",
+    "diffie-hellman-group1-sha1",
+    "diffie-hellman-group14-sha1",
+    "diffie-hellman-group-exchange-sha1",
+    "ssh-dss",
+];
+
+impl Algorithms {
+    pub fn is_weak(name: &str) -> bool {
+        WEAK_ALGORITHMS.contains(&name)
+    }
+
+    /// Names of any negotiated algorithms on the weak list
+    pub fn weak(&self) -> Vec<&str> {
+        [&self.kex, &self.host_key, &self.cipher, &self.mac]
+            .into_iter()
+            .map(|name| name.as_str())
+            .filter(|name| Self::is_weak(name))
+            .collect()
+    }
+}
+
+/// Read back what the server and client agreed on after the handshake
+/// Describe a freshly opened session to `host`
+pub fn connection_info(
+    sess: &Session,
+    host: &str,
+    port: u16,
+    auth: &AuthMethod,
+    jump: Option<&JumpHost>,
+) -> ConnectionInfo {
+    ConnectionInfo {
+        host: host.to_string(),
+        port,
+        auth: auth.kind(),
+        jump: jump.map(|jump| format_address(&jump.host, jump.port)),
+        server_version: sess.banner().map(str::to_string),
+        algorithms: negotiated_algorithms(sess),
+        latency_ms: None,
+    }
+}
+
+pub fn negotiated_algorithms(sess: &Session) -> Algorithms {
+    let method = |kind| sess.methods(kind).unwrap_or("unknown").to_string();
+    Algorithms {
+        kex: method(MethodType::Kex),
+        host_key: method(MethodType::HostKey),
+        cipher: method(MethodType::CryptCs),
+        mac: method(MethodType::MacCs),
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
 pub struct UserStats {
     pub username: String,
     pub cpu_percent: f64,
     pub ram_mb: f64,
+    pub process_count: u32,
+    /// Login sessions reported by `who`
+    pub session_count: u32,
+    pub swap_mb: f64,
+    pub top_command: String,
+    /// Name of the user's busiest process, without its arguments
+    pub top_process: String,
     pub last_updated: DateTime<Local>,
+    /// Size of the home directory, collected on its own slower timer
+    pub disk_usage_mb: Option<f64>,
+    /// TCP throughput since the previous poll, in kilobits per second
+    pub network_rx_kbps: f64,
+    pub network_tx_kbps: f64,
+    /// Numeric user id, when `ps` could match it to the name
+    pub uid: Option<u32>,
+    /// User-space and kernel CPU time since the previous poll, in percent of one core
+    pub cpu_user_percent: f64,
+    pub cpu_sys_percent: f64,
+    /// Share of all the storage I/O done since the previous poll, in percent
+    pub io_wait_percent: f64,
+}
+
+#[cfg(test)]
+impl UserStats {
+    /// A user with the given figures and everything else empty
+    pub fn test_fixture(username: &str, cpu_percent: f64, ram_mb: f64) -> UserStats {
+        UserStats {
+            username: username.to_string(),
+            cpu_percent,
+            ram_mb,
+            process_count: 1,
+            session_count: 0,
+            swap_mb: 0.0,
+            top_command: String::new(),
+            top_process: String::new(),
+            last_updated: Local::now(),
+            disk_usage_mb: None,
+            network_rx_kbps: 0.0,
+            network_tx_kbps: 0.0,
+            uid: None,
+            cpu_user_percent: 0.0,
+            cpu_sys_percent: 0.0,
+            io_wait_percent: 0.0,
+        }
+    }
+}
+
+/// Total RAM of the machine `generate_fake_stats` pretends to be, in MB
+const FAKE_RAM_TOTAL_MB: f64 = 16384.0;
+
+/// Made-up users for `--dry-run`. CPU follows a sine wave, offset per user so
+/// the table reorders as it runs, and RAM jumps around. Returns them with the
+/// pretend machine's total RAM in MB.
+pub fn generate_fake_stats(n_users: usize, tick: u64) -> (Vec<UserStats>, f64) {
+    let now = Local::now();
+    let users = (0..n_users)
+        .map(|i| {
+            let phase = tick as f64 / 5.0 + i as f64;
+            let cpu_percent = (phase.sin() + 1.0) * 25.0 * (1 + i % 3) as f64;
+            // A cheap hash of the user and tick rather than a random number crate
+            let noise = (i as u64 + 1)
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(tick.wrapping_mul(1442695040888963407))
+                >> 33;
+            UserStats {
+                username: format!("user{:02}", i + 1),
+                cpu_percent,
+                ram_mb: 200.0 + (noise % 2000) as f64,
+                process_count: 1 + (noise % 40) as u32,
+                session_count: (i % 3) as u32,
+                swap_mb: 0.0,
+                top_command: "python3 train.py --epochs 100".to_string(),
+                top_process: "python3".to_string(),
+                last_updated: now,
+                disk_usage_mb: None,
+                network_rx_kbps: 0.0,
+                network_tx_kbps: 0.0,
+                uid: Some(1000 + i as u32),
+                cpu_user_percent: cpu_percent * 0.8,
+                cpu_sys_percent: cpu_percent * 0.2,
+                io_wait_percent: (noise % 100) as f64 / n_users.max(1) as f64,
+            }
+        })
+        .collect();
+    (users, FAKE_RAM_TOTAL_MB)
+}
+
+/// How to authenticate once the SSH handshake is done
+#[derive(Clone, Debug)]
+pub enum AuthMethod {
+    Password(String),
+    /// Private key file, its passphrase if it has one, and whether to look
+    /// the passphrase up in the system keyring first
+    KeyFile(PathBuf, Option<String>, bool),
+    Agent,
+}
+
+impl AuthMethod {
+    /// Names the method without giving away the secret
+    fn kind(&self) -> &'static str {
+        match self {
+            AuthMethod::Password(_) => "password",
+            AuthMethod::KeyFile(..) => "key file",
+            AuthMethod::Agent => "ssh-agent",
+        }
+    }
+}
+
+/// How far a connection attempt has got, reported as it goes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionStage {
+    TcpConnect,
+    SshHandshake,
+    Authenticating,
+    FetchingData,
+    Done,
+}
+
+impl ConnectionStage {
+    pub fn message(&self) -> &'static str {
+        match self {
+            ConnectionStage::TcpConnect => "Opening TCP connection...",
+            ConnectionStage::SshHandshake => "Performing SSH handshake...",
+            ConnectionStage::Authenticating => "Authenticating...",
+            ConnectionStage::FetchingData => "Fetching initial data...",
+            ConnectionStage::Done => "Connected",
+        }
+    }
+
+    pub fn percent(&self) -> u16 {
+        match self {
+            ConnectionStage::TcpConnect => 25,
+            ConnectionStage::SshHandshake => 50,
+            ConnectionStage::Authenticating => 75,
+            ConnectionStage::FetchingData | ConnectionStage::Done => 100,
+        }
+    }
+}
+
+/// A server key that isn't in known_hosts yet, for the user to accept or reject
+#[derive(Clone, Debug)]
+pub struct UnknownHostKey {
+    pub host: String,
+    pub port: u16,
+    pub key_type: &'static str,
+    pub fingerprint: String,
+}
+
+/// Where a connection attempt reports back to whoever started it
+pub struct ConnectHandlers<'a> {
+    /// Each stage is sent as it starts
+    pub stages: &'a mpsc::Sender<ConnectionStage>,
+    /// Asked whether to trust a host that isn't in known_hosts yet
+    pub confirm_host_key: &'a dyn Fn(&UnknownHostKey) -> bool,
+    /// How long a command may block once logged in, see `DEFAULT_COMMAND_TIMEOUT_MS`
+    pub command_timeout_ms: u32,
 }
 
-pub fn get_user_stats(
+/// A bastion to tunnel the connection through, like `ssh -J`
+#[derive(Clone, Debug)]
+pub struct JumpHost {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub auth: AuthMethod,
+}
+
+/// Open and authenticate an SSH session that can be reused across polls. The
+/// server's host key is checked against ~/.ssh/known_hosts before logging in.
+pub fn connect(
     host: &str,
+    port: u16,
     user: &str,
-    password: Option<&str>,
-    ssh_key_path: Option<&str>,
-) -> Result<(Vec<UserStats>, f64)> {
-    let tcp = TcpStream::connect(format!("{}:22", host))?;
-    let mut sess = Session::new()?;
+    auth: &AuthMethod,
+    bind_address: Option<IpAddr>,
+    jump: Option<&JumpHost>,
+    handlers: &ConnectHandlers,
+) -> Result<Session> {
+    // Nobody listening just means nobody wants progress
+    let _ = handlers.stages.send(ConnectionStage::TcpConnect);
+    tracing::info!("Connecting to {}:{} as {}", host, port, user);
+    let tcp = match jump {
+        Some(jump) => tunnel_through(jump, host, port, bind_address, handlers)?,
+        None => connect_tcp(host, port, bind_address)?,
+    };
+    let mut sess = Session::new().map_err(SshMonitorError::SshHandshake)?;
     sess.set_tcp_stream(tcp);
-    sess.handshake()?;
+    // Ask the server to answer keep-alives so a dead peer shows up as an error
+    sess.set_keepalive(true, 30);
+    let _ = handlers.stages.send(ConnectionStage::SshHandshake);
+    tracing::debug!("TCP connected to {}:{}, starting SSH handshake", host, port);
+    sess.handshake().map_err(SshMonitorError::SshHandshake)?;
+    tracing::debug!("Handshake with {} done, negotiated {:?}", host, sess.methods(MethodType::CryptCs));
+    verify_host_key(&sess, host, port, handlers.confirm_host_key)?;
+
+    let _ = handlers.stages.send(ConnectionStage::Authenticating);
+    tracing::info!("Authenticating as {} with {}", user, auth.kind());
+    match auth {
+        AuthMethod::KeyFile(key_path, typed, use_keyring) => {
+            // The typed passphrase is the fallback when the keyring has none
+            let passphrase = use_keyring
+                .then(|| try_keyring_passphrase(user, host))
+                .flatten()
+                .or_else(|| typed.clone());
+            sess.userauth_pubkey_file(user, None, key_path, passphrase.as_deref())
+                .map_err(|e| match key_file_problem(key_path, passphrase.is_some()) {
+                    Some(problem) => {
+                        let message = format!("{} ({})", e, problem);
+                        SshMonitorError::AuthFailed(e).context(message)
+                    }
+                    None => SshMonitorError::AuthFailed(e),
+                })?
+        }
+        AuthMethod::Password(pwd) => sess.userauth_password(user, pwd).map_err(SshMonitorError::AuthFailed)?,
+        AuthMethod::Agent => sess.userauth_agent(user).map_err(|e| {
+            SshMonitorError::AuthFailed(e)
+                .context("SSH agent authentication failed (is ssh-agent running with a key loaded?)")
+        })?,
+    }
+    tracing::info!("Logged in to {} as {}", host, user);
+    // Every channel read and write from here on gives up after this, so a
+    // command stuck on e.g. a hung NFS mount can't block the worker forever
+    sess.set_timeout(handlers.command_timeout_ms);
+
+    Ok(sess)
+}
 
-    // Authenticate using either password or SSH key
-    if let Some(key_path) = ssh_key_path {
-        sess.userauth_pubkey_file(user, None, std::path::Path::new(key_path), None)?;
-    } else if let Some(pwd) = password {
-        sess.userauth_password(user, pwd)?;
+/// Keyring service the key passphrases are stored under
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "server-users";
+
+/// The key passphrase stored in the system keyring for `username@host`, if
+/// there is one and the keyring can be reached
+#[cfg(feature = "keyring")]
+pub fn try_keyring_passphrase(username: &str, host: &str) -> Option<String> {
+    let account = format!("{}@{}", username, host);
+    match keyring::Entry::new(KEYRING_SERVICE, &account).and_then(|entry| entry.get_password()) {
+        Ok(passphrase) => Some(passphrase),
+        Err(e) => {
+            tracing::debug!("No keyring passphrase for {}: {}", account, e);
+            None
+        }
+    }
+}
+
+/// Built without keyring support, so there is never a stored passphrase
+#[cfg(not(feature = "keyring"))]
+pub fn try_keyring_passphrase(_username: &str, _host: &str) -> Option<String> {
+    None
+}
+
+/// `host:port`, with IPv6 literals bracketed (`[::1]:22`) so the port can't be
+/// read as part of the address
+pub fn format_address(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
     } else {
-        return Err(anyhow::anyhow!("No authentication method provided"));
+        format!("{}:{}", host, port)
     }
+}
+
+/// libssh2's error code for a blocking call that ran past the session timeout
+const LIBSSH2_ERROR_TIMEOUT: i32 = -9;
+
+/// Open a TCP connection and run the SSH handshake, without logging in, to
+/// find out quickly whether the server can be reached at all. Returns how
+/// long that took.
+pub fn test_connection(host: &str, port: u16, timeout: Duration) -> Result<Duration> {
+    let started = Instant::now();
+    let addr = format_address(host, port)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| {
+            SshMonitorError::TcpConnect(io::Error::new(
+                ErrorKind::NotFound,
+                format!("Host unreachable: can't resolve {}", host),
+            ))
+        })?;
+    let tcp = TcpStream::connect_timeout(&addr, timeout).map_err(|e| {
+        SshMonitorError::TcpConnect(match e.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => io::Error::new(ErrorKind::TimedOut, "Connection timed out"),
+            kind => io::Error::new(kind, format!("Host unreachable: {}", e)),
+        })
+    })?;
+
+    let mut sess = Session::new().map_err(SshMonitorError::SshHandshake)?;
+    sess.set_tcp_stream(tcp);
+    sess.set_timeout(timeout.as_millis() as u32);
+    sess.handshake().map_err(|e| {
+        if e.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_TIMEOUT) {
+            SshMonitorError::TcpConnect(io::Error::new(ErrorKind::TimedOut, "Connection timed out"))
+        } else {
+            SshMonitorError::SshHandshake(e)
+        }
+    })?;
+    let elapsed = started.elapsed();
+    let _ = sess.disconnect(None, "Connection check", None);
+    Ok(elapsed)
+}
+
+/// Log in to the jump host and open a direct-tcpip channel from it to the
+/// target. libssh2 can only run a session over a real socket, so the channel
+/// is bridged to a loopback TCP connection by a forwarding thread, which owns
+/// the jump session and ends when either side closes.
+fn tunnel_through(
+    jump: &JumpHost,
+    host: &str,
+    port: u16,
+    bind_address: Option<IpAddr>,
+    handlers: &ConnectHandlers,
+) -> Result<TcpStream> {
+    // Logging in to the jump host all counts as the TCP connect stage of the
+    // target, but its host key still needs checking
+    let jump_handlers = ConnectHandlers {
+        stages: &mpsc::channel().0,
+        confirm_host_key: handlers.confirm_host_key,
+        command_timeout_ms: handlers.command_timeout_ms,
+    };
+    let jump_sess = connect(&jump.host, jump.port, &jump.username, &jump.auth, bind_address, None, &jump_handlers)
+        .map_err(|e| e.context(format!("Failed to connect to jump host {}", jump.host)))?;
+    let channel = jump_sess.channel_direct_tcpip(host, port, None).map_err(|e| {
+        SshMonitorError::Session(e).context(format!("Jump host {} could not reach {}:{}", jump.host, host, port))
+    })?;
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).map_err(SshMonitorError::TcpConnect)?;
+    let local = TcpStream::connect(listener.local_addr()?).map_err(SshMonitorError::TcpConnect)?;
+    let (forwarded, peer) = listener.accept().map_err(SshMonitorError::TcpConnect)?;
+    // Only ever bridge our own connection, never another local process's
+    if peer != local.local_addr()? {
+        return Err(SshMonitorError::TcpConnect(io::Error::other(format!(
+            "Unexpected connection to the jump host tunnel from {}",
+            peer
+        ))));
+    }
+
+    std::thread::spawn(move || forward_channel(jump_sess, channel, forwarded));
+    Ok(local)
+}
+
+/// Copy bytes both ways between the tunnel channel and the loopback socket
+fn forward_channel(jump_sess: Session, mut channel: Channel, mut local: TcpStream) {
+    jump_sess.set_blocking(false);
+    if local.set_nonblocking(true).is_err() {
+        return;
+    }
+
+    let mut buf = [0u8; 16 * 1024];
+    loop {
+        let mut idle = true;
+
+        match local.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                if write_fully(&mut channel, &buf[..n]).is_err() {
+                    break;
+                }
+                idle = false;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => break,
+        }
+
+        match channel.read(&mut buf) {
+            Ok(0) if channel.eof() => break,
+            Ok(0) => {}
+            Ok(n) => {
+                if write_fully(&mut local, &buf[..n]).is_err() {
+                    break;
+                }
+                idle = false;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => break,
+        }
+
+        if idle {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    let _ = channel.close();
+}
+
+/// `write_all` for a non-blocking writer, waiting out WouldBlock
+fn write_fully(writer: &mut impl Write, mut data: &[u8]) -> std::io::Result<()> {
+    while !data.is_empty() {
+        match writer.write(data) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => data = &data[n..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(1)),
+            Err(e) => return Err(e),
+        }
+    }
+    // No flush: on a channel that would throw away unread incoming data
+    Ok(())
+}
+
+fn known_hosts_path() -> PathBuf {
+    PathBuf::from(std::env::var("HOME").unwrap_or_default())
+        .join(".ssh")
+        .join("known_hosts")
+}
+
+/// Refuse a host whose key doesn't match known_hosts, and ask before trusting
+/// one that isn't in it yet, adding it to the file if accepted
+fn verify_host_key(sess: &Session, host: &str, port: u16, confirm: &dyn Fn(&UnknownHostKey) -> bool) -> Result<()> {
+    let (key, key_type) = sess
+        .host_key()
+        .ok_or_else(|| SshMonitorError::HostKey("Server did not send a host key".to_string()))?;
+    let path = known_hosts_path();
+    let mut known_hosts = sess.known_hosts()?;
+    // A missing file just means no host is known yet. Read line by line, since
+    // libssh2 rejects the whole file over one line it doesn't understand
+    // (`@cert-authority`, `@revoked`); those lines are left for OpenSSH.
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(SshMonitorError::Io(e).context(format!("Failed to read {}", path.display()))),
+    };
+    for line in contents.lines().filter(|line| !line.trim().is_empty() && !line.starts_with('#')) {
+        if let Err(e) = known_hosts.read_str(line, KnownHostFileKind::OpenSSH) {
+            tracing::debug!("Skipping known_hosts line libssh2 can't read: {}", e);
+        }
+    }
+
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(SshMonitorError::HostKey(format!(
+            "Host key for {} does not match {}. The server may have been reinstalled, or someone may be intercepting the connection.",
+            host,
+            path.display()
+        ))),
+        CheckResult::Failure => Err(SshMonitorError::HostKey(format!("Failed to check the host key for {}", host))),
+        CheckResult::NotFound => {
+            let unknown = UnknownHostKey {
+                host: host.to_string(),
+                port,
+                key_type: host_key_type_name(key_type),
+                fingerprint: sess
+                    .host_key_hash(HashType::Sha256)
+                    .map(|hash| format!("SHA256:{}", base64_unpadded(hash)))
+                    .unwrap_or_default(),
+            };
+            if !confirm(&unknown) {
+                return Err(SshMonitorError::HostKey(format!("Host key for {} was not trusted", host)));
+            }
+
+            // Same entry format as OpenSSH, which only brackets non-default ports
+            let entry = if port == 22 {
+                host.to_string()
+            } else {
+                format!("[{}]:{}", host, port)
+            };
+            append_known_host(sess, &path, &contents, &entry, key, key_type)
+                .map_err(|e| e.context(format!("Failed to write {}", path.display())))
+        }
+    }
+}
+
+/// Add one host to the end of known_hosts. The file is never rewritten,
+/// so the user's comments and the lines libssh2 can't read stay as they are.
+fn append_known_host(
+    sess: &Session,
+    path: &Path,
+    contents: &str,
+    entry: &str,
+    key: &[u8],
+    key_type: HostKeyType,
+) -> Result<()> {
+    // A list holding only the new host, to format its line the way libssh2 would
+    let mut new_host = sess.known_hosts()?;
+    new_host.add(entry, key, "added by server-users", key_type.into())?;
+    let host = new_host
+        .iter()?
+        .pop()
+        .ok_or_else(|| SshMonitorError::HostKey(format!("Failed to add {} to known_hosts", entry)))?;
+    let mut line = new_host.write_string(&host, KnownHostFileKind::OpenSSH)?;
+    line.truncate(line.trim_end().len());
+    line.push('\n');
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        line.insert(0, '\n');
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())?;
+    Ok(())
+}
+
+fn host_key_type_name(key_type: HostKeyType) -> &'static str {
+    match key_type {
+        HostKeyType::Rsa => "RSA",
+        HostKeyType::Dss => "DSA",
+        HostKeyType::Ecdsa256 | HostKeyType::Ecdsa384 | HostKeyType::Ecdsa521 => "ECDSA",
+        HostKeyType::Ed25519 => "ED25519",
+        HostKeyType::Unknown => "unknown",
+    }
+}
+
+/// What a command typed by the user printed, stderr included
+#[derive(Clone, Debug)]
+pub struct CommandOutput {
+    pub output: String,
+    pub exit_status: i32,
+}
+
+/// Run a command typed by the user on its own channel. Unlike the stats
+/// commands it runs in the user's normal locale, with stderr mixed into stdout.
+pub fn run_remote_command(sess: &Session, cmd: &str) -> Result<CommandOutput> {
+    let run = || -> Result<CommandOutput> {
+        let mut channel = sess.channel_session()?;
+        channel.handle_extended_data(ssh2::ExtendedData::Merge)?;
+        channel.exec(cmd)?;
+        let mut output = Vec::new();
+        channel.read_to_end(&mut output)?;
+        channel.wait_close()?;
+        Ok(CommandOutput {
+            output: String::from_utf8_lossy(&output).into_owned(),
+            exit_status: channel.exit_status()?,
+        })
+    };
+    run().map_err(|e| timeout_error(sess, e))
+}
+
+/// Send a signal to a process. The remote `kill` decides what the login may
+/// signal, so other users' processes fail unless connected as root, with
+/// kill's own message as the error.
+pub fn kill_process(sess: &Session, pid: u32, signal: KillSignal) -> Result<()> {
+    let result = run_remote_command(sess, &format!("kill -s {} {}", signal.name().trim_start_matches("SIG"), pid))?;
+    if result.exit_status != 0 {
+        return Err(SshMonitorError::CommandFailed {
+            exit_code: result.exit_status,
+            stderr: result.output.trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// A likely reason a private key was rejected, worked out from the file
+/// itself, since libssh2 only reports a generic failure
+fn key_file_problem(path: &Path, has_passphrase: bool) -> Option<String> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let missing_passphrase = "the key is passphrase-protected, enter its passphrase".to_string();
+
+    // Older PEM keys say whether they are encrypted in a header
+    if contents.contains("Proc-Type: 4,ENCRYPTED") {
+        return (!has_passphrase).then_some(missing_passphrase);
+    }
+
+    // OpenSSH keys keep the cipher and key type in the base64 body, unencrypted
+    let body: String = contents
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let data = base64_decode(&body)?;
+    let mut reader = data.strip_prefix(b"openssh-key-v1\0")?;
+    let cipher = take_ssh_string(&mut reader)?;
+    let _kdf = take_ssh_string(&mut reader)?;
+    let _kdf_options = take_ssh_string(&mut reader)?;
+    // Skip the key count to get to the public key, whose first field is the key type
+    reader = reader.get(4..)?;
+    let mut public_key = take_ssh_string(&mut reader)?;
+    let key_type = String::from_utf8_lossy(take_ssh_string(&mut public_key)?);
+
+    if key_type.starts_with("sk-") {
+        Some(format!(
+            "{} keys need a hardware security key, which isn't supported here; load it into ssh-agent instead",
+            key_type
+        ))
+    } else if cipher != b"none" && !has_passphrase {
+        Some(missing_passphrase)
+    } else {
+        None
+    }
+}
+
+/// Split a length-prefixed string, as used throughout the SSH wire format, off the front of `data`
+fn take_ssh_string<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let value = data.get(4..4 + len)?;
+    *data = &data[4 + len..];
+    Some(value)
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes().filter(|&c| c != b'=' && !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = ((buffer << 6) | value as u32) & 0xffff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Base64 without '=' padding, the way OpenSSH prints fingerprints
+fn base64_unpadded(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+/// Close the session with a proper disconnect message instead of just dropping the socket
+pub fn disconnect(sess: &Session) {
+    let _ = sess.disconnect(None, "server_users monitor closed", None);
+}
+
+/// One process in the per-user drill-down list
+#[derive(Clone, Debug)]
+pub struct ProcessEntry {
+    pub pid: u32,
+    pub command: String,
+    pub cpu_percent: f64,
+    pub ram_mb: f64,
+    /// CPUs the process may run on, like "0,2,4-7". None until fetched, or
+    /// if the server wouldn't say.
+    pub cpu_affinity: Option<String>,
+}
+
+/// Signal sent to a process from the process list
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KillSignal {
+    Term,
+    Kill,
+}
+
+impl KillSignal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            KillSignal::Term => "SIGTERM",
+            KillSignal::Kill => "SIGKILL",
+        }
+    }
+}
+
+/// Bytes moved over SSH channels, counting command lines sent and output read
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Traffic {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl Traffic {
+    pub fn total(&self) -> u64 {
+        self.bytes_read + self.bytes_written
+    }
+
+    pub fn add(&mut self, other: Traffic) {
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+    }
+}
+
+/// Machine-wide figures, as opposed to the per-user ones
+#[derive(Clone, Debug, Default)]
+pub struct SystemStats {
+    /// 1, 5 and 15 minute load averages
+    pub load_average: [f64; 3],
+    pub core_count: usize,
+    pub ram_total_mb: f64,
+    pub ram_used_mb: f64,
+    pub ram_free_mb: f64,
+    /// Cumulative CPU time counters from /proc/stat
+    pub cpu_times: Option<CpuTimes>,
+}
+
+/// The aggregate `cpu` line of /proc/stat, in clock ticks since boot. Only
+/// meaningful as a difference between two polls.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuTimes {
+    /// user + nice + system
+    pub busy: u64,
+    pub total: u64,
+}
+
+impl CpuTimes {
+    /// Share of CPU time spent busy between `previous` and these counters, 0-100
+    pub fn percent_since(&self, previous: &CpuTimes) -> Option<f64> {
+        let total = self.total.checked_sub(previous.total)?;
+        let busy = self.busy.checked_sub(previous.busy)?;
+        (total > 0).then(|| busy as f64 / total as f64 * 100.0)
+    }
+}
+
+/// Bytes a user's open TCP sockets have moved so far. Only meaningful as a
+/// difference between two polls.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NetworkBytes {
+    pub sent: u64,
+    pub received: u64,
+}
+
+/// CPU time a user's processes have used so far, in clock ticks, from
+/// /proc/<pid>/stat. Only meaningful as a difference between two polls.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuTicks {
+    pub user: u64,
+    pub sys: u64,
+}
+
+/// A block device's I/O counters from /proc/diskstats. Only meaningful as a
+/// difference between two polls.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiskCounters {
+    pub sectors_read: u64,
+    pub sectors_written: u64,
+    /// Milliseconds the device has spent doing I/O
+    pub io_ms: u64,
+}
+
+/// Everything collected from the server in a single poll
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub users: Vec<UserStats>,
+    pub system: SystemStats,
+    pub traffic: Traffic,
+    pub network: HashMap<String, NetworkBytes>,
+    /// Keyed by device name, whole disks only
+    pub disks: HashMap<String, DiskCounters>,
+    /// Wall time spent running the poll's commands over the session
+    pub poll_duration: Duration,
+    /// Keyed by username
+    pub cpu_ticks: HashMap<String, CpuTicks>,
+    /// Length of a clock tick, to turn `cpu_ticks` into seconds
+    pub clock_ticks_per_sec: u64,
+    /// Bytes read from and written to storage so far, keyed by username.
+    /// Only meaningful as a difference between two polls.
+    pub io_bytes: HashMap<String, u64>,
+}
+
+/// Operating system family reported by `uname -s`, which decides how `ps`
+/// has to be asked for the per-user stats
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RemoteOs {
+    Linux,
+    /// FreeBSD, NetBSD and OpenBSD
+    Bsd,
+    MacOs,
+    Unknown,
+}
+
+impl RemoteOs {
+    pub fn from_uname(uname: &str) -> RemoteOs {
+        match uname.trim() {
+            "Linux" => RemoteOs::Linux,
+            "FreeBSD" | "NetBSD" | "OpenBSD" | "DragonFly" => RemoteOs::Bsd,
+            "Darwin" => RemoteOs::MacOs,
+            _ => RemoteOs::Unknown,
+        }
+    }
+
+    /// Lists every process as `user %cpu vsz rss command` (sizes in KB)
+    /// under a header line. BSD `ps` reads `-e` as "show the environment", so it
+    /// needs `-ax` to see every process; anything else gets the POSIX `-A`.
+    fn ps_command(&self) -> &'static str {
+        match self {
+            RemoteOs::Linux => "ps -eo user,pcpu,vsz,rss,comm",
+            RemoteOs::Bsd | RemoteOs::MacOs => "ps -axo user,pcpu,vsz,rss,comm",
+            RemoteOs::Unknown => "ps -A -o user,pcpu,vsz,rss,comm",
+        }
+    }
+}
+
+pub fn get_remote_os(sess: &Session, traffic: &mut Traffic) -> Result<RemoteOs> {
+    Ok(RemoteOs::from_uname(&run_command(sess, "uname -s", traffic)?))
+}
+
+/// Printed between the sections of the batched poll command
+const SECTION_SEPARATOR: &str = "---SEPARATOR---";
+
+/// Login sessions per user from `who`
+const SESSIONS_COMMAND: &str = "who | awk '{count[$1]++} END {for (u in count) print u, count[u]}'";
+
+/// The machine's CPU counters
+const CPU_COMMAND: &str = "head -n1 /proc/stat";
+
+/// Disk counters. Only devices in /sys/block are kept, so partitions don't
+/// count their disk's I/O twice.
+const DISKSTATS_COMMAND: &str =
+    "ls /sys/block 2>/dev/null | awk 'NR==FNR {disk[$1]; next} $3 in disk' - /proc/diskstats 2>/dev/null";
+
+/// Each user's uid. Comes from ps so names are truncated the same way.
+const UIDS_COMMAND: &str = "ps -eo uid=,user= | sort -u";
+
+/// Full command line of each user's busiest process (first line per user
+/// once sorted by CPU)
+const TOP_COMMANDS_COMMAND: &str = "ps -eo user,args --sort=-pcpu --no-headers | awk '!seen[$1]++'";
+
+/// Swap per user: sum VmSwap from every process's status file by owner uid,
+/// naming uids the same way ps does so they line up with the other rows
+const SWAP_COMMAND: &str = r#"{ ps -eo uid=,user= | awk '{print "U", $1, $2}'; cat /proc/[0-9]*/status 2>/dev/null; } | awk '$1=="U" {name[$2]=$3; next} /^Uid:/ {uid=$2} /^VmSwap:/ {swap[uid]+=$2} END {for (uid in swap) if (uid in name) printf "%s %.2f\n", name[uid], swap[uid]/1024}'"#;
+
+/// Load, core count and memory. Each line is tagged so a tool missing on the
+/// server only leaves its own figure empty.
+const SYSTEM_COMMAND: &str =
+    r#"echo "load $(cat /proc/loadavg)"; echo "cores $(nproc)"; free -m | awk '/^Mem:/ {print "mem", $2, $3, $4}'"#;
+
+/// The clock tick rate, then utime and stime summed per user. The command
+/// name in /proc/<pid>/stat can contain spaces, so everything up to its
+/// closing parenthesis is cut off first, leaving utime and stime as fields
+/// 12 and 13.
+const CPU_TICKS_COMMAND: &str = r#"echo "hz $(getconf CLK_TCK)"; { ps -eo pid=,user= | awk '{print "P", $1, $2}'; cat /proc/[0-9]*/stat 2>/dev/null; } | awk '$1=="P" {owner[$2]=$3; next} {pid=$1; sub(/^.*\) /, ""); split($0, f, " "); if (pid in owner) {u[owner[pid]]+=f[12]; s[owner[pid]]+=f[13]}} END {for (n in u) print n, u[n], s[n]}'"#;
+
+/// `read_bytes` plus `write_bytes` from /proc/<pid>/io summed per user. These
+/// count what actually reached storage, unlike `rchar`/`wchar`, which include
+/// pipes and the page cache. Other users' io files are only readable as root.
+const IO_COMMAND: &str = r#"{ ps -eo pid=,user= | awk '{print "P", $1, $2}'; grep -HE '^(read|write)_bytes' /proc/[0-9]*/io 2>/dev/null; } | awk '$1=="P" {owner[$2]=$3; next} {split($1, p, "/"); if (p[3] in owner) b[owner[p[3]]]+=$2} END {for (n in b) print n, b[n]}'"#;
+
+/// Process owners followed by every TCP socket with its byte counters
+const NETWORK_COMMAND: &str = r#"ps -eo pid=,user= | awk '{print "P", $1, $2}'; ss -tinpH 2>/dev/null"#;
+
+/// Poll the server, detecting its OS first so `ps` is asked the right way
+pub fn get_user_stats(sess: &Session, backend: StatsBackend, memory: MemoryMetric) -> Result<Snapshot> {
+    let started = Instant::now();
+    let mut traffic = Traffic::default();
+    let os = get_remote_os(sess, &mut traffic)?;
+    let mut snapshot = get_user_stats_batch(sess, os, backend, memory)?;
+    snapshot.traffic.add(traffic);
+    snapshot.poll_duration = started.elapsed();
+    Ok(snapshot)
+}
+
+/// Sum `ps` output per user as `user cpu mem_mb count top_process` lines
+fn ps_stats_command(os: RemoteOs, memory: MemoryMetric) -> String {
+    let column = match memory {
+        MemoryMetric::Rss => 4,
+        MemoryMetric::Vsz => 3,
+    };
+    format!(
+        r#"{} | awk 'NR>1 {{if (!($1 in top) || $2>best[$1]) {{best[$1]=$2; top[$1]=$5}} cpu[$1]+=$2; mem[$1]+=${column}; count[$1]++}} END {{for(user in cpu) printf "%s %.2f %.2f %d %s\n", user, cpu[user], mem[user]/1024, count[user], top[user]}}'"#,
+        os.ps_command()
+    )
+}
+
+/// Everything a poll needs, run as a single command on one channel. Each
+/// query is its own section of the output, so they are parsed independently
+/// and one failing only leaves its own figures empty.
+pub fn get_user_stats_batch(
+    sess: &Session,
+    os: RemoteOs,
+    backend: StatsBackend,
+    memory: MemoryMetric,
+) -> Result<Snapshot> {
+    let started = Instant::now();
+    let mut traffic = Traffic::default();
 
-    // Get per-user CPU and memory usage
-    let mut channel = sess.channel_session()?;
-    
     // This command gets CPU and memory usage per user
-    // Uses ps to get processes with user, CPU%, and memory
-    let cmd = r#"ps aux | awk 'NR>1 {cpu[$1]+=$3; mem[$1]+=$4; rss[$1]+=$6} END {for(user in cpu) printf "%s %.2f %.2f\n", user, cpu[user], rss[user]/1024}'"#;
-    
-    channel.exec(cmd)?;
-    let mut output = String::new();
-    channel.read_to_string(&mut output)?;
-    channel.wait_close()?;
+    let stats_cmd = match (backend, os) {
+        // Uses top's process table; sizes may carry an m/g/t suffix on big processes
+        (StatsBackend::Top, RemoteOs::Linux) => {
+            // VIRT is column 5 and RES column 6
+            let column = match memory {
+                MemoryMetric::Rss => 6,
+                MemoryMetric::Vsz => 5,
+            };
+            format!(
+                r#"top -bn1 | awk '/^ *PID/ {{p=1; next}} p && NF>=12 {{r=${column}; m=1; if (r ~ /m$/) m=1024; else if (r ~ /g$/) m=1048576; else if (r ~ /t$/) m=1073741824; if (!($2 in top) || $9>best[$2]) {{best[$2]=$9; top[$2]=$12}} cpu[$2]+=$9; mem[$2]+=r*m; count[$2]++}} END {{for(user in cpu) printf "%s %.2f %.2f %d %s\n", user, cpu[user], mem[user]/1024, count[user], top[user]}}'"#
+            )
+        }
+        // Uses ps to get processes with user, CPU%, and memory. `top -b` is
+        // procps only, so other systems always use ps.
+        _ => ps_stats_command(os, memory),
+    };
+
+    let commands = [
+        stats_cmd.as_str(),
+        SESSIONS_COMMAND,
+        CPU_COMMAND,
+        DISKSTATS_COMMAND,
+        UIDS_COMMAND,
+        TOP_COMMANDS_COMMAND,
+        SWAP_COMMAND,
+        SYSTEM_COMMAND,
+        NETWORK_COMMAND,
+        CPU_TICKS_COMMAND,
+        IO_COMMAND,
+    ];
+    let cmd = commands.join(&format!("; echo '{SECTION_SEPARATOR}'; "));
+
+    let output = run_command(sess, &cmd, &mut traffic)?;
+    let sections: Vec<&str> = output.split(SECTION_SEPARATOR).collect();
+    let section = |i: usize| sections.get(i).copied().unwrap_or("");
+    let [
+        stats_output,
+        sessions_output,
+        cpu_output,
+        diskstats_output,
+        uids_output,
+        top_output,
+        swap_output,
+        system_output,
+        network_output,
+        cpu_ticks_output,
+        io_output,
+    ] = std::array::from_fn(section);
 
     let now = Local::now();
     let mut users = Vec::new();
 
-    for line in output.lines() {
+    for line in stats_output.lines() {
         let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 3 {
+        if parts.len() >= 4 {
             users.push(UserStats {
                 username: parts[0].to_string(),
                 cpu_percent: parts[1].parse().unwrap_or(0.0),
                 ram_mb: parts[2].parse().unwrap_or(0.0),
+                process_count: parts[3].parse().unwrap_or(0),
+                session_count: 0,
+                swap_mb: 0.0,
+                top_command: String::new(),
+                top_process: parts.get(4).unwrap_or(&"").to_string(),
                 last_updated: now,
+                disk_usage_mb: None,
+                network_rx_kbps: 0.0,
+                network_tx_kbps: 0.0,
+                uid: None,
+                cpu_user_percent: 0.0,
+                cpu_sys_percent: 0.0,
+                io_wait_percent: 0.0,
             });
+        } else if !line.trim().is_empty() {
+            tracing::debug!("Skipping unparseable stats line: {:?}", line);
+        }
+    }
+
+    for line in sessions_output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() >= 2
+            && let Some(user) = users.iter_mut().find(|u| u.username == parts[0])
+        {
+            user.session_count = parts[1].parse().unwrap_or(0);
+        }
+    }
+
+    for line in uids_output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() >= 2
+            && let Some(user) = users.iter_mut().find(|u| u.username == parts[1])
+        {
+            user.uid = parts[0].parse().ok();
+        }
+    }
+
+    for line in top_output.lines() {
+        let line = line.trim_start();
+        if let Some((username, args)) = line.split_once(char::is_whitespace)
+            && let Some(user) = users.iter_mut().find(|u| u.username == username)
+        {
+            user.top_command = args.trim().to_string();
+        }
+    }
+
+    for line in swap_output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() >= 2
+            && let Some(user) = users.iter_mut().find(|u| u.username == parts[0])
+        {
+            user.swap_mb = parts[1].parse().unwrap_or(0.0);
         }
     }
 
     // Sort by CPU usage (descending)
-    users.sort_by(|a, b| b.cpu_percent.partial_cmp(&a.cpu_percent).unwrap());
-
-    // Get total RAM
-    let mut channel = sess.channel_session()?;
-    channel.exec("free -m | awk 'NR==2 {print $2}'")?;
-    let mut ram_output = String::new();
-    channel.read_to_string(&mut ram_output)?;
-    channel.wait_close()?;
-    
-    let total_ram_mb: f64 = ram_output.trim().parse().unwrap_or(0.0);
-
-    Ok((users, total_ram_mb))
+    users.sort_by(|a, b| b.cpu_percent.total_cmp(&a.cpu_percent));
+
+    let mut system = parse_system_stats(system_output);
+    system.cpu_times = parse_cpu_times(cpu_output);
+    let (clock_ticks_per_sec, cpu_ticks) = parse_cpu_ticks(cpu_ticks_output);
+
+    Ok(Snapshot {
+        users,
+        system,
+        traffic,
+        network: parse_network_bytes(network_output),
+        disks: parse_diskstats(diskstats_output),
+        poll_duration: started.elapsed(),
+        cpu_ticks,
+        clock_ticks_per_sec,
+        io_bytes: parse_io_bytes(io_output),
+    })
+}
+
+/// Parse the `user bytes` lines of the I/O section
+fn parse_io_bytes(output: &str) -> HashMap<String, u64> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (username, bytes) = line.split_once(' ')?;
+            Some((username.to_string(), bytes.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Parse the `hz` line and the `user utime stime` lines. Linux has used 100
+/// ticks a second for years, so that stands in if getconf is missing.
+fn parse_cpu_ticks(output: &str) -> (u64, HashMap<String, CpuTicks>) {
+    let mut hz = 100;
+    let mut ticks = HashMap::new();
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            ["hz", value] => hz = value.parse().unwrap_or(100).max(1),
+            [username, user, sys] => {
+                ticks.insert(
+                    username.to_string(),
+                    CpuTicks {
+                        user: user.parse().unwrap_or(0),
+                        sys: sys.parse().unwrap_or(0),
+                    },
+                );
+            }
+            _ => {}
+        }
+    }
+    (hz, ticks)
+}
+
+/// Parse /proc/diskstats lines (`major minor name reads merged sectors ms
+/// writes merged sectors ms in_flight io_ms ...`), leaving out loop and RAM
+/// disks, which are never interesting
+fn parse_diskstats(output: &str) -> HashMap<String, DiskCounters> {
+    let mut disks = HashMap::new();
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 13 || fields[2].starts_with("loop") || fields[2].starts_with("ram") {
+            continue;
+        }
+        let value = |i: usize| fields[i].parse::<u64>().unwrap_or(0);
+        disks.insert(
+            fields[2].to_string(),
+            DiskCounters {
+                sectors_read: value(5),
+                sectors_written: value(9),
+                io_ms: value(12),
+            },
+        );
+    }
+    disks
+}
+
+/// Byte counters of every TCP socket from `ss`, summed per owning user. Sockets
+/// of other users are only attributed when the login can see their processes,
+/// which usually means root.
+fn parse_network_bytes(output: &str) -> HashMap<String, NetworkBytes> {
+    let mut owners = HashMap::new();
+    let mut totals: HashMap<String, NetworkBytes> = HashMap::new();
+    // ss prints each socket on one line and its counters on the next
+    let mut socket_owner: Option<String> = None;
+    for line in output.lines() {
+        if let Some(rest) = line.strip_prefix("P ") {
+            if let Some((pid, user)) = rest.split_once(' ') {
+                owners.insert(pid.to_string(), user.to_string());
+            }
+        } else if !line.starts_with(char::is_whitespace) {
+            socket_owner = line
+                .split_once("pid=")
+                .and_then(|(_, rest)| rest.split(|c: char| !c.is_ascii_digit()).next())
+                .and_then(|pid| owners.get(pid).cloned());
+        } else if let Some(user) = socket_owner.take() {
+            let counter = |name: &str| {
+                line.split_whitespace()
+                    .find_map(|field| field.strip_prefix(name))
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .unwrap_or(0)
+            };
+            let entry = totals.entry(user).or_default();
+            entry.sent += counter("bytes_sent:");
+            entry.received += counter("bytes_received:");
+        }
+    }
+
+    totals
+}
+
+/// Parse the `cpu  user nice system idle iowait irq softirq steal ...` line
+fn parse_cpu_times(output: &str) -> Option<CpuTimes> {
+    let line = output.lines().find(|line| line.starts_with("cpu "))?;
+    let values: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .take(8)
+        .map(|v| v.parse().unwrap_or(0))
+        .collect();
+    if values.len() < 4 {
+        return None;
+    }
+    Some(CpuTimes {
+        busy: values[0] + values[1] + values[2],
+        total: values.iter().sum(),
+    })
+}
+
+/// Parse the tagged `load`, `cores` and `mem` lines
+fn parse_system_stats(output: &str) -> SystemStats {
+    let mut system = SystemStats::default();
+    for line in output.lines() {
+        let Some((key, rest)) = line.split_once(' ') else {
+            continue;
+        };
+        let values: Vec<&str> = rest.split_whitespace().collect();
+        let number = |i: usize| values.get(i).and_then(|v| v.parse().ok()).unwrap_or(0.0);
+        match key {
+            "load" => system.load_average = [number(0), number(1), number(2)],
+            "cores" => system.core_count = number(0) as usize,
+            "mem" => {
+                system.ram_total_mb = number(0);
+                system.ram_used_mb = number(1);
+                system.ram_free_mb = number(2);
+            }
+            _ => {}
+        }
+    }
+
+    system
+}
+
+/// What the server calls itself and what it runs, for the title bar
+#[derive(Clone, Debug)]
+pub struct HostInfo {
+    /// Fully qualified where the server knows its domain
+    pub hostname: String,
+    /// Kernel name and release, e.g. "Linux 5.15.0-91-generic"
+    pub os: String,
+}
+
+pub fn get_host_info(sess: &Session) -> Result<HostInfo> {
+    let output = run_command(
+        sess,
+        "hostname -f 2>/dev/null || hostname; uname -sr",
+        &mut Traffic::default(),
+    )?;
+    let mut lines = output.lines().map(str::trim);
+    let hostname = lines.next().unwrap_or_default().to_string();
+    let os = lines.next().unwrap_or_default().to_string();
+    if hostname.is_empty() || os.is_empty() {
+        return Err(SshMonitorError::ParseError(format!("Unexpected host info: {}", output.trim())));
+    }
+    Ok(HostInfo { hostname, os })
+}
+
+/// When the server booted. Read from `uptime -s`, or worked out from
+/// /proc/uptime where that isn't supported, and converted to a Unix time on
+/// the server so its time zone doesn't matter.
+pub fn get_boot_time(sess: &Session) -> Result<DateTime<Local>> {
+    let output = run_command(
+        sess,
+        r#"date -d "$(uptime -s 2>/dev/null)" +%s 2>/dev/null || awk -v now="$(date +%s)" '{print int(now - $1)}' /proc/uptime"#,
+        &mut Traffic::default(),
+    )?;
+    let secs: i64 = output
+        .trim()
+        .parse()
+        .map_err(|_| SshMonitorError::ParseError(format!("Unexpected boot time: {}", output.trim())))?;
+    DateTime::from_timestamp(secs, 0)
+        .map(|boot| boot.with_timezone(&Local))
+        .ok_or_else(|| SshMonitorError::ParseError(format!("Boot time out of range: {}", secs)))
+}
+
+/// Prints each regular user's name and home directory size in MB
+const DISK_USAGE_COMMAND: &str = r#"export LC_ALL=C; getent passwd | awk -F: '$3 >= 1000 && $3 < 65534 {print $1, $6}' | while read -r user home; do [ -d "$home" ] && echo "$user $(du -sm "$home" 2>/dev/null | cut -f1)"; done"#;
+
+/// The size of each regular user's home directory, being worked out on a
+/// channel of its own. `du` has to walk every file, which can take far longer
+/// than the command timeout, so the output is collected a bit at a time by
+/// `poll` instead of waiting for it.
+pub struct DiskUsageCheck {
+    channel: Channel,
+    output: Vec<u8>,
+}
+
+impl DiskUsageCheck {
+    pub fn start(sess: &Session) -> Result<DiskUsageCheck> {
+        tracing::debug!("exec: {}", DISK_USAGE_COMMAND);
+        let mut channel = sess.channel_session()?;
+        channel.exec(DISK_USAGE_COMMAND)?;
+        Ok(DiskUsageCheck {
+            channel,
+            output: Vec::new(),
+        })
+    }
+
+    /// Take whatever `du` has printed so far without blocking. Returns the
+    /// sizes in MB, keyed by username, once it has finished.
+    pub fn poll(&mut self, sess: &Session) -> Result<Option<HashMap<String, f64>>> {
+        sess.set_blocking(false);
+        let finished = self.read_available();
+        sess.set_blocking(true);
+        if !finished? {
+            return Ok(None);
+        }
+        self.channel.wait_close()?;
+        Ok(Some(parse_disk_usage(&String::from_utf8_lossy(&self.output))))
+    }
+
+    /// Read until the channel has nothing more for now. True once it is at EOF.
+    fn read_available(&mut self) -> Result<bool> {
+        let mut buf = [0u8; 16 * 1024];
+        loop {
+            match self.channel.read(&mut buf) {
+                Ok(0) => return Ok(self.channel.eof()),
+                Ok(n) => self.output.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+fn parse_disk_usage(output: &str) -> HashMap<String, f64> {
+    let mut usage = HashMap::new();
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() >= 2
+            && let Ok(mb) = parts[1].parse()
+        {
+            usage.insert(parts[0].to_string(), mb);
+        }
+    }
+    usage
+}
+
+/// All processes owned by one user, busiest first
+pub fn fetch_processes_for_user(sess: &Session, username: &str) -> Result<Vec<ProcessEntry>> {
+    // Filter here rather than with `ps -u` so truncated names like "longuse+"
+    // match exactly as they appear in the user table
+    let output = run_command(
+        sess,
+        "ps -eo user=,pid=,pcpu=,rss=,args= --sort=-pcpu",
+        &mut Traffic::default(),
+    )?;
+
+    let mut processes = Vec::new();
+    for line in output.lines() {
+        let mut rest = line.trim_start();
+        let mut fields = Vec::with_capacity(4);
+        for _ in 0..4 {
+            let Some((field, tail)) = rest.split_once(char::is_whitespace) else {
+                break;
+            };
+            fields.push(field);
+            rest = tail.trim_start();
+        }
+        if fields.len() < 4 || fields[0] != username {
+            continue;
+        }
+        processes.push(ProcessEntry {
+            pid: fields[1].parse().unwrap_or(0),
+            cpu_percent: fields[2].parse().unwrap_or(0.0),
+            ram_mb: fields[3].parse::<f64>().unwrap_or(0.0) / 1024.0,
+            command: rest.trim_end().to_string(),
+            cpu_affinity: None,
+        });
+    }
+
+    Ok(processes)
+}
+
+/// Fill in which CPUs each process may run on, from `Cpus_allowed_list` in
+/// /proc/<pid>/status. Best effort: processes that exited, or servers
+/// without /proc, are left as None.
+pub fn fill_cpu_affinity(sess: &Session, processes: &mut [ProcessEntry]) -> Result<()> {
+    if processes.is_empty() {
+        return Ok(());
+    }
+    let paths: Vec<String> = processes.iter().map(|p| format!("/proc/{}/status", p.pid)).collect();
+    // grep exits non-zero when some files are missing, so ignore its status
+    let output = run_command(
+        sess,
+        &format!("grep -H Cpus_allowed_list {} 2>/dev/null; true", paths.join(" ")),
+        &mut Traffic::default(),
+    )?;
+
+    let mut affinity = HashMap::new();
+    for line in output.lines() {
+        // /proc/1234/status:Cpus_allowed_list:	0-7
+        let Some((path, list)) = line.split_once(":Cpus_allowed_list:") else {
+            continue;
+        };
+        if let Some(pid) = path
+            .strip_prefix("/proc/")
+            .and_then(|rest| rest.strip_suffix("/status"))
+            .and_then(|pid| pid.parse::<u32>().ok())
+        {
+            affinity.insert(pid, list.trim().to_string());
+        }
+    }
+    for process in processes {
+        process.cpu_affinity = affinity.remove(&process.pid);
+    }
+    Ok(())
+}
+
+/// Run a command on its own channel and return everything it printed
+fn run_command(sess: &Session, cmd: &str, traffic: &mut Traffic) -> Result<String> {
+    // Force the C locale for every command in the pipeline, otherwise servers
+    // with e.g. a European locale print "1,50" which doesn't parse as f64
+    let cmd = format!("export LC_ALL=C; {}", cmd);
+    let output = exec_command(sess, &cmd)?;
+    traffic.bytes_written += cmd.len() as u64;
+    traffic.bytes_read += output.len() as u64;
+    Ok(output)
+}
+
+/// Run a command on a channel of its own and return its stdout, closing the
+/// channel before returning
+pub fn exec_command(sess: &Session, cmd: &str) -> Result<String> {
+    tracing::debug!("exec: {}", cmd);
+    let run = || -> Result<String> {
+        let mut channel = sess.channel_session()?;
+        channel.exec(cmd)?;
+        let mut output = String::new();
+        channel.read_to_string(&mut output)?;
+        channel.wait_close()?;
+        Ok(output)
+    };
+    run().map_err(|e| timeout_error(sess, e))
+}
+
+/// How long a command may block before it counts as stuck, unless
+/// `--command-timeout-ms` says otherwise
+pub const DEFAULT_COMMAND_TIMEOUT_MS: u32 = 10_000;
+
+/// Turn libssh2 giving up on a blocking call into `CommandTimeout`. Reads
+/// through `std::io::Read` report the same thing as `ErrorKind::TimedOut`.
+fn timeout_error(sess: &Session, error: SshMonitorError) -> SshMonitorError {
+    let timed_out = match &error {
+        SshMonitorError::Session(e) => e.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_TIMEOUT),
+        SshMonitorError::Io(e) => e.kind() == ErrorKind::TimedOut,
+        _ => false,
+    };
+    if timed_out {
+        SshMonitorError::CommandTimeout {
+            timeout_ms: sess.timeout(),
+        }
+    } else {
+        error
+    }
+}
+
+/// How many lines of the remote log the log view shows
+pub const LOG_TAIL_LINES: usize = 50;
+
+/// The last `LOG_TAIL_LINES` lines of a file on the server. Fails with what
+/// `tail` said if it couldn't read the file.
+pub fn tail_log(sess: &Session, path: &str) -> Result<Vec<String>> {
+    let quoted = format!("'{}'", path.replace('\'', "'\\''"));
+    let output = run_remote_command(sess, &format!("tail -n {} {}", LOG_TAIL_LINES, quoted))?;
+    if output.exit_status != 0 {
+        return Err(SshMonitorError::CommandFailed {
+            exit_code: output.exit_status,
+            stderr: output.output.trim().to_string(),
+        });
+    }
+    Ok(output.output.lines().map(str::to_string).collect())
+}
+
+/// The newest wtmp entry for `username` from `last`, with the username and
+/// column padding taken out. None when wtmp has no login for them.
+pub fn last_login(sess: &Session, username: &str) -> Result<Option<String>> {
+    let quoted = format!("'{}'", username.replace('\'', "'\\''"));
+    let output = run_command(
+        sess,
+        &format!("last -w -F -n 1 {} 2>/dev/null | head -n 1", quoted),
+        &mut Traffic::default(),
+    )?;
+    // An empty wtmp prints a blank line and then "wtmp begins ..."
+    Ok(output
+        .trim()
+        .strip_prefix(username)
+        .map(|rest| rest.split_whitespace().collect::<Vec<_>>().join(" ")))
+}
+
+/// Whether the session still answers, by opening and closing a channel. Used
+/// to tell a failed command apart from a dropped connection.
+pub fn is_alive(sess: &Session) -> bool {
+    sess.channel_session()
+        .and_then(|mut channel| channel.close())
+        .is_ok()
+}
+
+/// Open the TCP connection to the server, optionally from a specific local address
+fn connect_tcp(host: &str, port: u16, bind_address: Option<IpAddr>) -> Result<TcpStream> {
+    let Some(bind_ip) = bind_address else {
+        return TcpStream::connect(format_address(host, port)).map_err(SshMonitorError::TcpConnect);
+    };
+
+    // The remote address has to be in the same family as the local one
+    let remote = format_address(host, port)
+        .to_socket_addrs()
+        .map_err(SshMonitorError::TcpConnect)?
+        .find(|addr| addr.is_ipv4() == bind_ip.is_ipv4())
+        .ok_or_else(|| {
+            SshMonitorError::TcpConnect(io::Error::other(format!(
+                "{} has no address reachable from {}",
+                host, bind_ip
+            )))
+        })?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .map_err(SshMonitorError::TcpConnect)?;
+
+    runtime
+        .block_on(async {
+            let socket = if bind_ip.is_ipv4() {
+                TcpSocket::new_v4()?
+            } else {
+                TcpSocket::new_v6()?
+            };
+            socket.bind(SocketAddr::new(bind_ip, 0)).map_err(|e| {
+                SshMonitorError::TcpConnect(e).context(format!("Failed to bind to local address {}", bind_ip))
+            })?;
+
+            let stream = socket.connect(remote).await?.into_std()?;
+            stream.set_nonblocking(false)?;
+            Ok::<_, SshMonitorError>(stream)
+        })
+        .map_err(|e| match e {
+            SshMonitorError::Io(e) => SshMonitorError::TcpConnect(e),
+            e => e,
+        })
 }
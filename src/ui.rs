@@ -1,93 +1,845 @@
+use anyhow::Result;
 use chrono::{DateTime, Local};
 use ratatui::{
-    layout::{Alignment, Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     symbols,
     text::{Line, Span},
     widgets::{
-        Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph, Row, Table,
+        Axis, Block, Borders, Cell, Chart, Clear, Dataset, Gauge, GraphType, Paragraph, Row, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, Sparkline, Table, TableState, Wrap,
     },
     Frame,
 };
 
-use crate::ssh::UserStats;
+use regex::Regex;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::{IpAddr, Ipv6Addr},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::config;
+use crate::export;
+use crate::ssh::{
+    format_address, Algorithms, AuthMethod, ConnectionInfo, ConnectionStage, DiskCounters, HostInfo, JumpHost, KillSignal, MemoryMetric, NetworkBytes, ProcessEntry, Snapshot, StatsBackend,
+    SystemStats, Traffic, UnknownHostKey, UserStats, CommandOutput, CpuTicks, DEFAULT_COMMAND_TIMEOUT_MS,
+};
+
+/// CPU readings shown per user in the trend sparkline
+const CPU_TREND_LEN: usize = 10;
+
+/// CPU readings kept per user, enough for the user detail screen's sparkline
+const CPU_HISTORY_LEN: usize = 120;
+
+/// Processes listed on the user detail screen
+const USER_DETAIL_PROCESSES: usize = 10;
+
+const MAX_REFRESH_INTERVAL_SECS: u64 = 3600;
+
+/// How long the main loop waits for a key before redrawing, in milliseconds
+const DEFAULT_UI_POLL_MS: u64 = 50;
+const MIN_UI_POLL_MS: u64 = 10;
+const MAX_UI_POLL_MS: u64 = 500;
+/// Past this, keys and the display start to feel sluggish
+const SLUGGISH_UI_POLL_MS: u64 = 200;
+
+/// How long the heartbeat dot stays bright, then green, after new data
+const HEARTBEAT_BRIGHT_MS: u64 = 150;
+const HEARTBEAT_FADE_MS: u64 = 300;
+
+/// Intervals below this poll often enough to be noticeable over a WAN link
+const WAN_WARNING_INTERVAL_SECS: u64 = 5;
+
+/// Processes shown for the selected user in the summary column
+const PROCESS_PREVIEW_LEN: usize = 5;
+
+/// Alerts kept for the alert history screen
+const MAX_ALERT_HISTORY: usize = 500;
+
+/// Characters of the busiest process name shown in the Top Process column
+const TOP_PROCESS_WIDTH: usize = 20;
+
+/// Rows moved by PgUp/PgDn in the user table
+const USER_PAGE_ROWS: isize = 10;
+
+/// Smallest change between polls that earns a trend arrow
+const CPU_DELTA_THRESHOLD: f64 = 0.5;
+const RAM_DELTA_THRESHOLD_MB: f64 = 10.0;
+
+/// Log shown by `L` unless `--log-path` or `log_path` says otherwise
+const DEFAULT_LOG_PATH: &str = "/var/log/syslog";
+/// Log lines shown in red unless `log_highlight` says otherwise
+const DEFAULT_LOG_HIGHLIGHT: &str = "ERROR|CRIT";
+
+
+/// Colors handed out to users, chosen to stay readable on dark terminals.
+/// Light green is kept back to mark users who are logged in.
+const USER_COLORS: [Color; 9] = [
+    Color::LightRed,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::Cyan,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Magenta,
+];
+
+/// Human-readable byte count, e.g. "1.2 MB"
+fn format_bytes(bytes: f64) -> String {
+    if bytes >= 1024.0 * 1024.0 * 1024.0 {
+        format!("{:.1} GB", bytes / (1024.0 * 1024.0 * 1024.0))
+    } else if bytes >= 1024.0 * 1024.0 {
+        format!("{:.1} MB", bytes / (1024.0 * 1024.0))
+    } else if bytes >= 1024.0 {
+        format!("{:.1} KB", bytes / 1024.0)
+    } else {
+        format!("{:.0} B", bytes)
+    }
+}
+
+/// Deterministic color for a username, so the same user keeps the same color
+/// no matter where they end up in the sorted table
+pub fn color_for_user(username: &str) -> Color {
+    // FNV-1a, which unlike the std hasher is stable between runs
+    let hash = username.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    USER_COLORS[(hash % USER_COLORS.len() as u64) as usize]
+}
+
+/// Color scheme for the screens, cycled with `T`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Theme {
+    Dark,
+    Light,
+    ColorBlindFriendly,
+}
+
+/// Characters the charts are drawn with, cycled with `v`. Braille is the
+/// finest, but some terminal multiplexers mangle it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphStyle {
+    Braille,
+    HalfBlock,
+    Dot,
+}
+
+impl GraphStyle {
+    pub fn label(&self) -> &'static str {
+        match self {
+            GraphStyle::Braille => "Braille",
+            GraphStyle::HalfBlock => "Half block",
+            GraphStyle::Dot => "Dot",
+        }
+    }
+
+    pub fn next(&self) -> GraphStyle {
+        match self {
+            GraphStyle::Braille => GraphStyle::HalfBlock,
+            GraphStyle::HalfBlock => GraphStyle::Dot,
+            GraphStyle::Dot => GraphStyle::Braille,
+        }
+    }
+
+    fn marker(&self) -> symbols::Marker {
+        match self {
+            GraphStyle::Braille => symbols::Marker::Braille,
+            GraphStyle::HalfBlock => symbols::Marker::HalfBlock,
+            GraphStyle::Dot => symbols::Marker::Dot,
+        }
+    }
+}
+
+/// The colors a theme gives each role on screen
+#[derive(Clone, Copy, Debug)]
+pub struct ThemeColors {
+    /// Titles and section headings
+    pub accent: Color,
+    /// Labels, table headers and the focused field
+    pub highlight: Color,
+    pub good: Color,
+    pub warning: Color,
+    pub alert: Color,
+    pub muted: Color,
+    pub disabled: Color,
+    pub selection_fg: Color,
+    pub selection_bg: Color,
+    pub logged_in: Color,
+    pub graph_cpu: Color,
+    pub graph_cpu_sys: Color,
+    pub graph_ram: Color,
+    pub graph_swap: Color,
+    pub graph_load: Color,
+    pub graph_read: Color,
+    pub graph_write: Color,
+    /// Added to warnings and alerts, so they stand out by more than hue
+    pub warning_modifier: Modifier,
+    pub alert_modifier: Modifier,
+}
+
+impl Theme {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+            Theme::ColorBlindFriendly => "Color-blind friendly",
+        }
+    }
+
+    pub fn next(&self) -> Theme {
+        match self {
+            Theme::Dark => Theme::Light,
+            Theme::Light => Theme::ColorBlindFriendly,
+            Theme::ColorBlindFriendly => Theme::Dark,
+        }
+    }
+
+    pub fn colors(&self) -> ThemeColors {
+        match self {
+            Theme::Dark => ThemeColors {
+                accent: Color::Cyan,
+                highlight: Color::Yellow,
+                good: Color::Green,
+                warning: Color::Yellow,
+                alert: Color::Red,
+                muted: Color::Gray,
+                disabled: Color::DarkGray,
+                selection_fg: Color::Black,
+                selection_bg: Color::LightCyan,
+                logged_in: Color::LightGreen,
+                graph_cpu: Color::Green,
+                graph_cpu_sys: Color::LightRed,
+                graph_ram: Color::Magenta,
+                graph_swap: Color::Yellow,
+                graph_load: Color::Cyan,
+                graph_read: Color::LightBlue,
+                graph_write: Color::LightRed,
+                warning_modifier: Modifier::empty(),
+                alert_modifier: Modifier::empty(),
+            },
+            // Yellow and cyan vanish on a white background
+            Theme::Light => ThemeColors {
+                accent: Color::Blue,
+                highlight: Color::Magenta,
+                good: Color::Green,
+                warning: Color::Indexed(130),
+                alert: Color::Red,
+                muted: Color::DarkGray,
+                disabled: Color::Gray,
+                selection_fg: Color::White,
+                selection_bg: Color::Blue,
+                logged_in: Color::Green,
+                graph_cpu: Color::Blue,
+                graph_cpu_sys: Color::Red,
+                graph_ram: Color::Magenta,
+                graph_swap: Color::Indexed(130),
+                graph_load: Color::Indexed(30),
+                graph_read: Color::Blue,
+                graph_write: Color::Red,
+                warning_modifier: Modifier::empty(),
+                alert_modifier: Modifier::empty(),
+            },
+            // Blue and orange in place of green and red, with warnings in
+            // bold and alerts underlined as well
+            Theme::ColorBlindFriendly => ThemeColors {
+                accent: Color::Cyan,
+                highlight: Color::Yellow,
+                good: Color::LightBlue,
+                warning: Color::Yellow,
+                alert: Color::Rgb(230, 159, 0),
+                muted: Color::Gray,
+                disabled: Color::DarkGray,
+                selection_fg: Color::Black,
+                selection_bg: Color::White,
+                logged_in: Color::LightBlue,
+                graph_cpu: Color::LightBlue,
+                graph_cpu_sys: Color::Rgb(230, 159, 0),
+                graph_ram: Color::Rgb(230, 159, 0),
+                graph_swap: Color::Yellow,
+                graph_load: Color::Rgb(86, 180, 233),
+                graph_read: Color::LightBlue,
+                graph_write: Color::Rgb(230, 159, 0),
+                warning_modifier: Modifier::BOLD,
+                alert_modifier: Modifier::BOLD.union(Modifier::UNDERLINED),
+            },
+        }
+    }
+}
+
+impl ThemeColors {
+    fn warning_style(&self) -> Style {
+        Style::default().fg(self.warning).add_modifier(self.warning_modifier)
+    }
+
+    fn alert_style(&self) -> Style {
+        Style::default().fg(self.alert).add_modifier(self.alert_modifier)
+    }
+}
+
+/// Whether a username passes the user filter, ignoring case. A pattern with
+/// `*` or `?` has to match the whole name as a glob; anything else only has
+/// to appear somewhere in it.
+pub fn matches_filter(username: &str, pattern: &str) -> bool {
+    let (username, pattern) = (username.to_lowercase(), pattern.to_lowercase());
+    if !pattern.contains(['*', '?']) {
+        return username.contains(&pattern);
+    }
+    let username: Vec<char> = username.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    // Classic backtracking glob: remember the last `*` and retry from one
+    // character further along whenever the rest fails to match
+    let (mut n, mut p) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < username.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == username[n] => {
+                n += 1;
+                p += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
 
-const MAX_HISTORY: usize = 100;
+/// Largest first, with readings that didn't parse (NaN) at the bottom
+fn descending_nan_last(a: f64, b: f64) -> std::cmp::Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => std::cmp::Ordering::Equal,
+        (true, false) => std::cmp::Ordering::Greater,
+        (false, true) => std::cmp::Ordering::Less,
+        (false, false) => b.total_cmp(&a),
+    }
+}
+
+/// `▲` or `▼` when a reading moved by more than `threshold` since the last
+/// poll, otherwise nothing
+fn delta_indicator(current: f64, previous: f64, threshold: f64) -> &'static str {
+    let delta = current - previous;
+    if delta > threshold {
+        "▲"
+    } else if delta < -threshold {
+        "▼"
+    } else {
+        ""
+    }
+}
+
+/// A figure followed by its trend arrow, green going up and red going down
+fn cell_with_delta(text: String, arrow: &'static str, colors: &ThemeColors) -> Cell<'static> {
+    let color = if arrow == "▲" { colors.good } else { colors.alert };
+    Cell::from(Line::from(vec![Span::raw(text), Span::styled(arrow, Style::default().fg(color))]))
+}
+
+/// A figure as the change since the baseline, red when it went up and green
+/// when it went down. Users who weren't around for the baseline count from zero.
+fn baseline_cell(current: f64, baseline: Option<f64>, unit: &str, colors: &ThemeColors) -> Cell<'static> {
+    let delta = current - baseline.unwrap_or(0.0);
+    let text = format!("{:+.2}{}", delta, unit);
+    if delta > 0.0 {
+        Cell::from(text).style(Style::default().fg(colors.alert))
+    } else if delta < 0.0 {
+        Cell::from(text).style(Style::default().fg(colors.good))
+    } else {
+        Cell::from(text)
+    }
+}
+
+/// Green for a quick poll, yellow from 100 ms and red past 500 ms
+fn poll_duration_color(ms: u64, colors: &ThemeColors) -> Color {
+    if ms > 500 {
+        colors.alert
+    } else if ms >= 100 {
+        colors.warning
+    } else {
+        colors.good
+    }
+}
+
+/// How worrying a row's age is: warning past 10 seconds, alert past 30
+fn staleness_color(secs: i64, colors: &ThemeColors) -> Color {
+    if secs > 30 {
+        colors.alert
+    } else if secs > 10 {
+        colors.warning
+    } else {
+        Color::Reset
+    }
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum AppState {
     Config,
     Connecting,
+    HostVerification,
+    Reconnecting,
     Monitoring,
+    ProcessList,
+    UserDetail,
+    CommandInput,
+    CommandOutput,
+    DiskIO,
+    AlertHistory,
+    LogTail,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ConfigField {
+    Profile,
     Host,
+    Port,
     Username,
     Password,
     UseSSHKey,
     SSHKeyPath,
+    KeyPassphrase,
+    UseKeyring,
+    UseSSHAgent,
+    Backend,
+    UseJumpHost,
+    JumpHost,
+    JumpPort,
+}
+
+/// Append a typed digit to a port number, ignoring anything that isn't a
+/// digit or would overflow the port range
+fn push_port_digit(port: &mut u16, c: char) {
+    if let Some(digit) = c.to_digit(10) {
+        let value = *port as u32 * 10 + digit;
+        if value <= u16::MAX as u32 {
+            *port = value as u16;
+        }
+    }
+}
+
+/// Connection settings for one server
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub auth: AuthMethod,
+    pub bind_address: Option<IpAddr>,
+    pub backend: StatsBackend,
+    pub jump: Option<JumpHost>,
+}
+
+impl ServerConfig {
+    /// Whether the host is on this machine or a private network, judged from
+    /// the address alone; hostnames that need DNS count as remote
+    pub fn is_local_network(&self) -> bool {
+        match self.host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+            Ok(IpAddr::V6(ip)) => {
+                // Unique local (fc00::/7) and link-local (fe80::/10) ranges
+                ip.is_loopback()
+                    || (ip.segments()[0] & 0xfe00) == 0xfc00
+                    || (ip.segments()[0] & 0xffc0) == 0xfe80
+            }
+            Err(_) => self.host == "localhost" || self.host.ends_with(".local"),
+        }
+    }
+
+    /// File the chart history for this server is kept in between runs
+    pub fn history_path(&self) -> PathBuf {
+        config::data_dir().join(format!("{}_history.json", self.file_name_label()))
+    }
+
+    /// The label with anything awkward in a file name replaced by '_'
+    pub fn file_name_label(&self) -> String {
+        self.label()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect()
+    }
+
+    /// Name shown on tabs and titles, with the port only when it isn't the default
+    pub fn label(&self) -> String {
+        if self.port == 22 {
+            self.host.clone()
+        } else {
+            format_address(&self.host, self.port)
+        }
+    }
 }
 
 pub struct ConfigScreen {
     pub host: String,
+    pub port: u16,
     pub username: String,
     pub password: String,
     pub use_ssh_key: bool,
+    pub use_ssh_agent: bool,
     pub ssh_key_path: String,
+    pub key_passphrase: String,
+    /// Look the key passphrase up in the system keyring before using the typed one
+    pub use_keyring: bool,
+    pub bind_address: Option<IpAddr>,
+    pub backend: StatsBackend,
+    pub memory_metric: MemoryMetric,
+    /// How long a remote command may block before the session is rebuilt
+    pub command_timeout_ms: u32,
+    pub warn_weak_algorithms: bool,
     pub current_field: ConfigField,
     pub error_message: Option<String>,
+    pub saved_at: Option<Instant>,
+    /// Set while the servers are checked before connecting
+    pub checking_reachability: bool,
+    pub servers: Vec<ServerConfig>,
+    pub use_jump_host: bool,
+    pub jump_host: String,
+    pub jump_port: u16,
+    /// Separate jump host credentials from the config file; the main ones are used otherwise
+    pub jump_username: Option<String>,
+    pub jump_auth: Option<AuthMethod>,
+    /// Names of the `[profiles.<name>]` tables in the config file
+    pub profiles: Vec<String>,
+    pub selected_profile: usize,
+    /// The name being typed for saving the form as a profile
+    pub profile_name_input: Option<String>,
 }
 
 impl ConfigScreen {
     pub fn new() -> Self {
         ConfigScreen {
             host: String::new(),
+            port: 22,
             username: String::new(),
             password: String::new(),
             use_ssh_key: false,
+            use_ssh_agent: false,
             ssh_key_path: format!("{}/.ssh/id_rsa", std::env::var("HOME").unwrap_or_default()),
+            key_passphrase: String::new(),
+            use_keyring: cfg!(feature = "keyring"),
+            bind_address: None,
+            backend: StatsBackend::Ps,
+            memory_metric: MemoryMetric::Rss,
+            command_timeout_ms: DEFAULT_COMMAND_TIMEOUT_MS,
+            warn_weak_algorithms: true,
             current_field: ConfigField::Host,
             error_message: None,
+            saved_at: None,
+            checking_reachability: false,
+            servers: Vec::new(),
+            use_jump_host: false,
+            jump_host: String::new(),
+            jump_port: 22,
+            jump_username: None,
+            jump_auth: None,
+            profiles: Vec::new(),
+            selected_profile: 0,
+            profile_name_input: None,
+        }
+    }
+
+    /// Start from the saved config file, if there is one. With profiles saved,
+    /// the profile list is focused so one can be picked straight away.
+    pub fn load_from_file() -> Result<Self> {
+        let file = config::load()?;
+        let mut screen = ConfigScreen::new();
+        screen.apply_file_config(&file);
+        screen.profiles = file.profiles.keys().cloned().collect();
+        if !screen.profiles.is_empty() {
+            screen.current_field = ConfigField::Profile;
+        }
+        Ok(screen)
+    }
+
+    /// Fill the form from the settings set in a config file or profile
+    fn apply_file_config(&mut self, file: &config::FileConfig) {
+        if let Some(ref host) = file.host {
+            self.host = host.clone();
+        }
+        if let Some(port) = file.port {
+            self.port = port;
+        }
+        if let Some(ref username) = file.username {
+            self.username = username.clone();
+        }
+        if let Some(use_ssh_key) = file.use_ssh_key {
+            self.use_ssh_key = use_ssh_key;
+        }
+        if let Some(use_ssh_agent) = file.use_ssh_agent {
+            self.use_ssh_agent = use_ssh_agent;
+            if use_ssh_agent {
+                self.use_ssh_key = false;
+            }
+        }
+        if let Some(ref ssh_key_path) = file.ssh_key_path {
+            self.ssh_key_path = ssh_key_path.clone();
+        }
+        self.bind_address = file.bind;
+        if let Some(backend) = file.backend {
+            self.backend = backend;
+        }
+        if let Some(memory) = file.memory {
+            self.memory_metric = memory;
+        }
+        if let Some(cipher_warning) = file.cipher_warning {
+            self.warn_weak_algorithms = cipher_warning;
+        }
+        if let Some(ref jump) = file.jump {
+            self.use_jump_host = true;
+            self.jump_host = jump.host.clone();
+            if let Some(port) = jump.port {
+                self.jump_port = port;
+            }
+            self.jump_username = jump.username.clone();
+            self.jump_auth = if jump.use_ssh_agent == Some(true) {
+                Some(AuthMethod::Agent)
+            } else {
+                jump.ssh_key_path
+                    .as_ref()
+                    .map(|path| AuthMethod::KeyFile(PathBuf::from(path), None, false))
+            };
+        }
+    }
+
+    /// Clear the connection settings, keeping the profile list and the
+    /// servers already added
+    pub fn new_profile(&mut self) {
+        *self = ConfigScreen {
+            profiles: std::mem::take(&mut self.profiles),
+            selected_profile: self.selected_profile,
+            servers: std::mem::take(&mut self.servers),
+            ..ConfigScreen::new()
+        };
+    }
+
+    /// Replace the form with the highlighted profile
+    pub fn load_selected_profile(&mut self) -> Result<()> {
+        let Some(name) = self.profiles.get(self.selected_profile).cloned() else {
+            return Ok(());
+        };
+        let file = config::load()?;
+        let profile = file
+            .profiles
+            .get(&name)
+            .ok_or_else(|| anyhow::anyhow!("Profile {} is no longer in the config file", name))?;
+        self.new_profile();
+        self.apply_file_config(profile);
+        self.current_field = ConfigField::Profile;
+        Ok(())
+    }
+
+    pub fn next_profile(&mut self) {
+        if !self.profiles.is_empty() {
+            self.selected_profile = (self.selected_profile + 1) % self.profiles.len();
+        }
+    }
+
+    pub fn previous_profile(&mut self) {
+        if !self.profiles.is_empty() {
+            self.selected_profile = (self.selected_profile + self.profiles.len() - 1) % self.profiles.len();
+        }
+    }
+
+    /// Connection settings as config file keys. The password is never included.
+    pub fn file_values(&self) -> Vec<(&'static str, Option<toml::Value>)> {
+        let text = |value: &str| {
+            if value.is_empty() {
+                None
+            } else {
+                Some(toml::Value::String(value.to_string()))
+            }
+        };
+        vec![
+            ("host", text(&self.host)),
+            ("port", Some(toml::Value::Integer(self.port as i64))),
+            ("username", text(&self.username)),
+            ("use_ssh_key", Some(toml::Value::Boolean(self.use_ssh_key))),
+            ("use_ssh_agent", Some(toml::Value::Boolean(self.use_ssh_agent))),
+            ("ssh_key_path", text(&self.ssh_key_path)),
+            ("jump", self.jump_file_value()),
+        ]
+    }
+
+    /// The `[jump]` table, keeping any separate credentials it was loaded with
+    fn jump_file_value(&self) -> Option<toml::Value> {
+        if !self.use_jump_host || self.jump_host.is_empty() {
+            return None;
         }
+        let mut table = toml::Table::new();
+        table.insert("host".to_string(), toml::Value::String(self.jump_host.clone()));
+        table.insert("port".to_string(), toml::Value::Integer(self.jump_port as i64));
+        if let Some(ref username) = self.jump_username {
+            table.insert("username".to_string(), toml::Value::String(username.clone()));
+        }
+        match self.jump_auth {
+            Some(AuthMethod::Agent) => {
+                table.insert("use_ssh_agent".to_string(), toml::Value::Boolean(true));
+            }
+            Some(AuthMethod::KeyFile(ref path, ..)) => {
+                table.insert(
+                    "ssh_key_path".to_string(),
+                    toml::Value::String(path.display().to_string()),
+                );
+            }
+            Some(AuthMethod::Password(_)) | None => {}
+        }
+        Some(toml::Value::Table(table))
     }
 
     pub fn next_field(&mut self) {
         self.current_field = match self.current_field {
-            ConfigField::Host => ConfigField::Username,
+            ConfigField::Host => ConfigField::Port,
+            ConfigField::Port => ConfigField::Username,
             ConfigField::Username => ConfigField::UseSSHKey,
-            ConfigField::UseSSHKey => {
-                if self.use_ssh_key {
-                    ConfigField::SSHKeyPath
-                } else {
-                    ConfigField::Password
-                }
-            }
-            ConfigField::Password => ConfigField::Host,
-            ConfigField::SSHKeyPath => ConfigField::Host,
+            ConfigField::UseSSHKey => self.auth_field(),
+            ConfigField::Password => ConfigField::Backend,
+            ConfigField::SSHKeyPath => ConfigField::KeyPassphrase,
+            ConfigField::KeyPassphrase if cfg!(feature = "keyring") => ConfigField::UseKeyring,
+            ConfigField::KeyPassphrase => ConfigField::Backend,
+            ConfigField::UseKeyring => ConfigField::Backend,
+            ConfigField::UseSSHAgent => ConfigField::Backend,
+            ConfigField::Backend => ConfigField::UseJumpHost,
+            ConfigField::UseJumpHost if self.use_jump_host => ConfigField::JumpHost,
+            ConfigField::UseJumpHost => ConfigField::Profile,
+            ConfigField::JumpHost => ConfigField::JumpPort,
+            ConfigField::JumpPort => ConfigField::Profile,
+            ConfigField::Profile => ConfigField::Host,
         };
     }
 
     pub fn previous_field(&mut self) {
         self.current_field = match self.current_field {
-            ConfigField::Host => {
-                if self.use_ssh_key {
-                    ConfigField::SSHKeyPath
-                } else {
-                    ConfigField::Password
-                }
-            }
-            ConfigField::Username => ConfigField::Host,
+            ConfigField::Profile if self.use_jump_host => ConfigField::JumpPort,
+            ConfigField::Profile => ConfigField::UseJumpHost,
+            ConfigField::Host => ConfigField::Profile,
+            ConfigField::Port => ConfigField::Host,
+            ConfigField::Username => ConfigField::Port,
             ConfigField::UseSSHKey => ConfigField::Username,
             ConfigField::Password => ConfigField::UseSSHKey,
             ConfigField::SSHKeyPath => ConfigField::UseSSHKey,
+            ConfigField::KeyPassphrase => ConfigField::SSHKeyPath,
+            ConfigField::UseKeyring => ConfigField::KeyPassphrase,
+            ConfigField::UseSSHAgent => ConfigField::UseSSHKey,
+            ConfigField::Backend if self.auth_field() == ConfigField::SSHKeyPath && cfg!(feature = "keyring") => {
+                ConfigField::UseKeyring
+            }
+            ConfigField::Backend if self.auth_field() == ConfigField::SSHKeyPath => ConfigField::KeyPassphrase,
+            ConfigField::Backend => self.auth_field(),
+            ConfigField::UseJumpHost => ConfigField::Backend,
+            ConfigField::JumpHost => ConfigField::UseJumpHost,
+            ConfigField::JumpPort => ConfigField::JumpHost,
         };
     }
 
+    /// The field shown below the auth toggle for the selected auth mode
+    fn auth_field(&self) -> ConfigField {
+        if self.use_ssh_agent {
+            ConfigField::UseSSHAgent
+        } else if self.use_ssh_key {
+            ConfigField::SSHKeyPath
+        } else {
+            ConfigField::Password
+        }
+    }
+
+    pub fn auth_method(&self) -> AuthMethod {
+        if self.use_ssh_agent {
+            AuthMethod::Agent
+        } else if self.use_ssh_key {
+            let passphrase = Some(self.key_passphrase.clone()).filter(|p| !p.is_empty());
+            AuthMethod::KeyFile(PathBuf::from(&self.ssh_key_path), passphrase, self.use_keyring)
+        } else {
+            AuthMethod::Password(self.password.clone())
+        }
+    }
+
+    pub fn server_config(&self) -> ServerConfig {
+        ServerConfig {
+            host: self.host_address().to_string(),
+            port: self.port,
+            username: self.username.clone(),
+            auth: self.auth_method(),
+            bind_address: self.bind_address,
+            backend: self.backend,
+            jump: self.jump_config(),
+        }
+    }
+
+    /// The host as typed, without the brackets an IPv6 literal may be written in
+    fn host_address(&self) -> &str {
+        self.host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(&self.host)
+    }
+
+    /// The jump host to tunnel through, if enabled
+    pub fn jump_config(&self) -> Option<JumpHost> {
+        if !self.use_jump_host {
+            return None;
+        }
+        Some(JumpHost {
+            host: self.jump_host.clone(),
+            port: self.jump_port,
+            username: self.jump_username.clone().unwrap_or_else(|| self.username.clone()),
+            auth: self.jump_auth.clone().unwrap_or_else(|| self.auth_method()),
+        })
+    }
+
+    /// Whether the server in the form is already in the list
+    fn form_already_added(&self) -> bool {
+        self.servers.iter().any(|s| {
+            s.host == self.host_address() && s.port == self.port && s.username == self.username
+        })
+    }
+
+    /// Add the server in the form to the list and clear the host so the next
+    /// one can be entered. The other settings are kept, since servers in a
+    /// cluster usually share them.
+    pub fn add_server(&mut self) {
+        // The invalid fields are already marked on the form
+        if !self.is_valid() {
+            return;
+        }
+        if !self.form_already_added() {
+            self.servers.push(self.server_config());
+        }
+        self.host.clear();
+        self.current_field = ConfigField::Host;
+        self.error_message = None;
+    }
+
+    pub fn remove_last_server(&mut self) {
+        self.servers.pop();
+    }
+
+    /// Every added server, plus the one in the form if it is filled in and new
+    pub fn servers_to_connect(&self) -> Vec<ServerConfig> {
+        let mut servers = self.servers.clone();
+        if self.is_valid() && !self.form_already_added() {
+            servers.push(self.server_config());
+        }
+        servers
+    }
+
+    pub fn can_connect(&self) -> bool {
+        self.is_valid() || !self.servers.is_empty()
+    }
+
     pub fn handle_char(&mut self, c: char) {
         match self.current_field {
             ConfigField::Host => self.host.push(c),
+            ConfigField::Port => push_port_digit(&mut self.port, c),
             ConfigField::Username => self.username.push(c),
             ConfigField::Password => {
                 if !self.use_ssh_key {
@@ -99,7 +851,27 @@ impl ConfigScreen {
                     self.ssh_key_path.push(c)
                 }
             }
-            ConfigField::UseSSHKey => {}
+            ConfigField::KeyPassphrase => {
+                if self.use_ssh_key {
+                    self.key_passphrase.push(c)
+                }
+            }
+            ConfigField::JumpHost => self.jump_host.push(c),
+            ConfigField::JumpPort => push_port_digit(&mut self.jump_port, c),
+            ConfigField::UseSSHKey
+            | ConfigField::UseKeyring
+            | ConfigField::UseSSHAgent
+            | ConfigField::Backend
+            | ConfigField::UseJumpHost
+            | ConfigField::Profile => {}
+        }
+    }
+
+    /// Type pasted text into the current field. Line breaks from copying a
+    /// whole line are dropped, and fields that ignore typing ignore this too.
+    pub fn handle_paste(&mut self, text: &str) {
+        for c in text.chars().filter(|c| !c.is_control()) {
+            self.handle_char(c);
         }
     }
 
@@ -108,6 +880,9 @@ impl ConfigScreen {
             ConfigField::Host => {
                 self.host.pop();
             }
+            ConfigField::Port => {
+                self.port /= 10;
+            }
             ConfigField::Username => {
                 self.username.pop();
             }
@@ -121,55 +896,145 @@ impl ConfigScreen {
                     self.ssh_key_path.pop();
                 }
             }
-            ConfigField::UseSSHKey => {}
+            ConfigField::KeyPassphrase => {
+                if self.use_ssh_key {
+                    self.key_passphrase.pop();
+                }
+            }
+            ConfigField::JumpHost => {
+                self.jump_host.pop();
+            }
+            ConfigField::JumpPort => {
+                self.jump_port /= 10;
+            }
+            ConfigField::UseSSHKey
+            | ConfigField::UseKeyring
+            | ConfigField::UseSSHAgent
+            | ConfigField::Backend
+            | ConfigField::UseJumpHost
+            | ConfigField::Profile => {}
         }
     }
 
+    /// Cycle password -> SSH key -> SSH agent -> password
     pub fn toggle_ssh_key(&mut self) {
         if self.current_field == ConfigField::UseSSHKey {
-            self.use_ssh_key = !self.use_ssh_key;
-            if self.use_ssh_key {
+            if self.use_ssh_agent {
+                self.use_ssh_agent = false;
+            } else if self.use_ssh_key {
+                self.use_ssh_key = false;
+                self.use_ssh_agent = true;
+            } else {
+                self.use_ssh_key = true;
+            }
+            if self.use_ssh_key || self.use_ssh_agent {
                 self.password.clear();
             }
+            if !self.use_ssh_key {
+                self.key_passphrase.clear();
+            }
+        }
+    }
+
+    pub fn toggle_keyring(&mut self) {
+        if self.current_field == ConfigField::UseKeyring {
+            self.use_keyring = !self.use_keyring;
+        }
+    }
+
+    pub fn toggle_backend(&mut self) {
+        if self.current_field == ConfigField::Backend {
+            self.backend = self.backend.toggle();
+        }
+    }
+
+    pub fn toggle_jump_host(&mut self) {
+        if self.current_field == ConfigField::UseJumpHost {
+            self.use_jump_host = !self.use_jump_host;
         }
     }
 
     pub fn is_valid(&self) -> bool {
-        !self.host.is_empty() 
-            && !self.username.is_empty() 
-            && (self.use_ssh_key || !self.password.is_empty())
+        self.validate_fields().is_empty()
+    }
+
+    /// Every field that would stop the form from connecting, with why
+    pub fn validate_fields(&self) -> Vec<(ConfigField, String)> {
+        let mut errors = Vec::new();
+        let mut check = |field: ConfigField, failed: bool, reason: &str| {
+            if failed {
+                errors.push((field, reason.to_string()));
+            }
+        };
+
+        if self.host.is_empty() {
+            check(ConfigField::Host, true, "Host is required");
+        } else {
+            check(ConfigField::Host, self.host.contains(char::is_whitespace), "Host cannot contain spaces");
+            // A colon only makes sense in an IPv6 literal; the port has its own field
+            check(
+                ConfigField::Host,
+                self.host_address().contains(':') && self.host_address().parse::<Ipv6Addr>().is_err(),
+                "Not a valid IPv6 address (the port goes in the Port field)",
+            );
+        }
+        check(ConfigField::Port, self.port == 0, "Port must be between 1 and 65535");
+        if self.username.is_empty() {
+            check(ConfigField::Username, true, "Username is required");
+        } else {
+            check(
+                ConfigField::Username,
+                self.username.contains(char::is_whitespace),
+                "Username cannot contain spaces",
+            );
+        }
+
+        if self.use_ssh_key {
+            if self.ssh_key_path.is_empty() {
+                check(ConfigField::SSHKeyPath, true, "Key path is required");
+            } else {
+                check(ConfigField::SSHKeyPath, !Path::new(&self.ssh_key_path).exists(), "File does not exist");
+            }
+        } else if !self.use_ssh_agent {
+            check(ConfigField::Password, self.password.is_empty(), "Password is required");
+        }
+
+        if self.use_jump_host {
+            if self.jump_host.is_empty() {
+                check(ConfigField::JumpHost, true, "Jump host is required");
+            } else {
+                check(
+                    ConfigField::JumpHost,
+                    self.jump_host.contains(char::is_whitespace),
+                    "Jump host cannot contain spaces",
+                );
+            }
+            check(ConfigField::JumpPort, self.jump_port == 0, "Port must be between 1 and 65535");
+        }
+
+        errors
     }
 }
 
 pub struct LoadingScreen {
     pub progress: u16,
-    pub direction: i16,
     pub message: String,
+    pub error: Option<String>,
 }
 
 impl LoadingScreen {
     pub fn new() -> Self {
         LoadingScreen {
             progress: 0,
-            direction: 1,
             message: "Connecting to SSH server...".to_string(),
+            error: None,
         }
     }
 
-    pub fn update(&mut self) {
-        if self.direction > 0 {
-            self.progress += 2;
-            if self.progress >= 100 {
-                self.direction = -1;
-            }
-        } else {
-            if self.progress <= 2 {
-                self.direction = 1;
-                self.progress = 0;
-            } else {
-                self.progress -= 2;
-            }
-        }
+    /// Show how far the connection attempt has got
+    pub fn set_stage(&mut self, stage: ConnectionStage) {
+        self.progress = stage.percent();
+        self.message = stage.message().to_string();
     }
 }
 
@@ -177,104 +1042,2018 @@ impl LoadingScreen {
 pub enum SortBy {
     Cpu,
     Ram,
+    ProcessCount,
+    Username,
+    RamPercent,
+    Disk,
+    /// Newest first, so users that dropped out of `ps` sink to the bottom
+    LastUpdated,
+    IoWait,
 }
 
-#[derive(Clone, Debug)]
-pub struct HistoricalData {
-    pub _timestamp: DateTime<Local>,
-    pub cpu_total: f64,
-    pub ram_total: f64,
+/// Columns of the monitoring table
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Column {
+    User,
+    Cpu,
+    CpuUser,
+    CpuSys,
+    CpuTrend,
+    Ram,
+    RamPercent,
+    Procs,
+    Sessions,
+    Disk,
+    Network,
+    Swap,
+    IoWait,
+    Uid,
+    TopProcess,
+    LastUpdated,
 }
 
-pub struct App {
-    pub state: AppState,
-    pub config: ConfigScreen,
-    pub loading: LoadingScreen,
-    pub users: Vec<UserStats>,
-    pub history: Vec<HistoricalData>,
-    pub selected_user: usize,
-    pub sort_by: SortBy,
-    pub should_quit: bool,
-    pub total_ram_mb: f64,
-}
+impl Column {
+    fn title(&self, memory_metric: MemoryMetric) -> String {
+        match self {
+            Column::User => "User".to_string(),
+            Column::Cpu => "CPU %".to_string(),
+            Column::CpuUser => "User %".to_string(),
+            Column::CpuSys => "Sys %".to_string(),
+            Column::CpuTrend => "CPU trend".to_string(),
+            Column::Ram => format!("{} (MB)", memory_metric.label()),
+            Column::RamPercent => "RAM%".to_string(),
+            Column::Procs => "Procs".to_string(),
+            Column::Sessions => "Sessions".to_string(),
+            Column::Disk => "Disk (MB)".to_string(),
+            Column::Network => "Net↑↓ (kbps)".to_string(),
+            Column::Swap => "Swap (MB)".to_string(),
+            Column::IoWait => "I/O %".to_string(),
+            Column::Uid => "UID".to_string(),
+            Column::TopProcess => "Top Process".to_string(),
+            Column::LastUpdated => "Last Updated".to_string(),
+        }
+    }
 
-impl App {
-    pub fn new() -> App {
-        App {
-            state: AppState::Config,
-            config: ConfigScreen::new(),
+    /// Sort mode for this column, if the table can be sorted by it
+    fn sort_by(&self) -> Option<SortBy> {
+        match self {
+            Column::Cpu => Some(SortBy::Cpu),
+            Column::Ram => Some(SortBy::Ram),
+            Column::RamPercent => Some(SortBy::RamPercent),
+            Column::Procs => Some(SortBy::ProcessCount),
+            Column::Disk => Some(SortBy::Disk),
+            Column::User => Some(SortBy::Username),
+            Column::LastUpdated => Some(SortBy::LastUpdated),
+            Column::IoWait => Some(SortBy::IoWait),
+            _ => None,
+        }
+    }
+
+    fn width(&self) -> Constraint {
+        match self {
+            Column::User => Constraint::Fill(5),
+            Column::Cpu => Constraint::Fill(4),
+            Column::CpuUser | Column::CpuSys => Constraint::Fill(3),
+            Column::CpuTrend => Constraint::Length(CPU_TREND_LEN as u16),
+            Column::Ram => Constraint::Fill(4),
+            Column::RamPercent => Constraint::Fill(3),
+            Column::Procs => Constraint::Fill(3),
+            Column::Sessions => Constraint::Fill(3),
+            Column::Disk => Constraint::Fill(4),
+            Column::Network => Constraint::Fill(6),
+            Column::Swap => Constraint::Fill(4),
+            Column::IoWait => Constraint::Fill(3),
+            Column::Uid => Constraint::Fill(3),
+            Column::TopProcess => Constraint::Min(TOP_PROCESS_WIDTH as u16),
+            Column::LastUpdated => Constraint::Fill(7),
+        }
+    }
+}
+
+/// Limits that raise an alert when a user goes over them
+#[derive(Clone, Debug, Default)]
+pub struct AlertConfig {
+    pub cpu_threshold: Option<f64>,
+    pub ram_threshold_mb: Option<f64>,
+}
+
+impl AlertConfig {
+    /// The configured threshold for a kind of alert
+    pub fn threshold(&self, kind: AlertKind) -> Option<f64> {
+        match kind {
+            AlertKind::CpuHigh => self.cpu_threshold,
+            AlertKind::RamHigh => self.ram_threshold_mb,
+        }
+    }
+
+    pub fn file_values(&self) -> Vec<(&'static str, Option<toml::Value>)> {
+        vec![
+            ("cpu_alert", self.cpu_threshold.map(toml::Value::Float)),
+            ("ram_alert_mb", self.ram_threshold_mb.map(toml::Value::Float)),
+        ]
+    }
+}
+
+/// Accounts below this UID are system accounts on most Linux distributions
+pub const SYSTEM_UID_MAX: u32 = 1000;
+
+/// Accounts kept out of the user table, from `[exclude_users]` in the config
+#[derive(Clone, Debug, Default)]
+pub struct UserExclusion {
+    pub excluded_users: HashSet<String>,
+    /// Hide every account with a UID below `SYSTEM_UID_MAX`
+    pub exclude_system_users: bool,
+    /// Cleared to show the excluded accounts again without forgetting the list
+    pub disabled: bool,
+}
+
+impl UserExclusion {
+    pub fn excludes(&self, user: &UserStats) -> bool {
+        !self.disabled
+            && (self.excluded_users.contains(&user.username)
+                || (self.exclude_system_users && user.uid.is_some_and(|uid| uid < SYSTEM_UID_MAX)))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+pub enum AlertKind {
+    CpuHigh,
+    RamHigh,
+}
+
+impl AlertKind {
+    const ALL: [AlertKind; 2] = [AlertKind::CpuHigh, AlertKind::RamHigh];
+
+    /// The user's current figure for this kind of alert
+    fn value(&self, user: &UserStats) -> f64 {
+        match self {
+            AlertKind::CpuHigh => user.cpu_percent,
+            AlertKind::RamHigh => user.ram_mb,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            AlertKind::CpuHigh => "CPU",
+            AlertKind::RamHigh => "RAM",
+        }
+    }
+
+    /// A figure for this kind of alert, with its unit
+    fn format_value(&self, value: f64) -> String {
+        match self {
+            AlertKind::CpuHigh => format!("{:.1}%", value),
+            AlertKind::RamHigh => format!("{:.0} MB", value),
+        }
+    }
+
+    fn message(&self, value: f64, threshold: f64) -> String {
+        match self {
+            AlertKind::CpuHigh => format!("CPU {:.1}% over {:.0}%", value, threshold),
+            AlertKind::RamHigh => format!("RAM {:.0} MB over {:.0} MB", value, threshold),
+        }
+    }
+}
+
+/// A user who went over one of the alert thresholds. Exported without the
+/// server, which the export's file name already gives.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Alert {
+    #[serde(skip)]
+    pub server: String,
+    #[serde(rename = "timestamp")]
+    pub triggered_at: DateTime<Local>,
+    pub username: String,
+    pub kind: AlertKind,
+    pub value: f64,
+    pub threshold: f64,
+}
+
+/// Unix time in seconds, used to place history samples on a chart's x axis
+fn chart_x(timestamp: &DateTime<Local>) -> f64 {
+    timestamp.timestamp_millis() as f64 / 1000.0
+}
+
+/// X axis bounds covering the recorded history, as Unix timestamps
+pub fn history_time_bounds(history: &VecDeque<HistoricalData>) -> (f64, f64) {
+    match (history.front(), history.back()) {
+        (Some(first), Some(last)) => {
+            let start = chart_x(&first.timestamp);
+            // A single sample still needs a non-empty range
+            (start, chart_x(&last.timestamp).max(start + 1.0))
+        }
+        _ => (0.0, 1.0),
+    }
+}
+
+/// The two ends of a flat line across the whole chart at `peak`
+fn highwater_points((start, end): (f64, f64), peak: f64) -> [(f64, f64); 2] {
+    [(start, peak), (end, peak)]
+}
+
+/// A faint reference line marking the highest value in the history
+fn highwater_dataset<'a>(points: &'a [(f64, f64)], colors: &ThemeColors) -> Dataset<'a> {
+    Dataset::default()
+        .name("Peak")
+        .marker(symbols::Marker::Dot)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(colors.muted).add_modifier(Modifier::DIM))
+        .data(points)
+}
+
+/// Clock times for the left edge, middle and right edge of the x axis
+fn time_axis_labels((start, end): (f64, f64)) -> Vec<Line<'static>> {
+    [start, (start + end) / 2.0, end]
+        .iter()
+        .map(|&t| {
+            let time = DateTime::from_timestamp(t as i64, 0)
+                .map(|dt| dt.with_timezone(&Local).format("%H:%M:%S").to_string())
+                .unwrap_or_default();
+            Line::from(time)
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct HistoricalData {
+    pub timestamp: DateTime<Local>,
+    pub cpu_total: f64,
+    pub ram_total: f64,
+    pub swap_total: f64,
+    /// Throughput summed over the physical disks, in kB/s
+    #[serde(default)]
+    pub disk_read_kbps: f64,
+    #[serde(default)]
+    pub disk_write_kbps: f64,
+    #[serde(default)]
+    pub load_avg_1min: f64,
+    /// User-space and kernel shares of the CPU, from /proc/<pid>/stat
+    #[serde(default)]
+    pub cpu_user_total: f64,
+    #[serde(default)]
+    pub cpu_sys_total: f64,
+}
+
+/// One block device's throughput and busy time between the last two polls
+#[derive(Clone, Debug)]
+pub struct DiskIoStats {
+    pub device: String,
+    pub read_kbps: f64,
+    pub write_kbps: f64,
+    pub util_percent: f64,
+}
+
+impl DiskIoStats {
+    /// LVM and dm-crypt volumes sit on top of the physical disks, so their
+    /// I/O is already counted there
+    fn is_device_mapper(&self) -> bool {
+        self.device.starts_with("dm-")
+    }
+}
+
+/// Device mapper volumes moving less than this (kB/s) are left off the table
+const DM_MIN_KBPS: f64 = 1.0;
+
+/// Everything collected from one server, shown on its own tab
+pub struct ServerSession {
+    pub server: ServerConfig,
+    pub loading: LoadingScreen,
+    pub connecting_in_progress: bool,
+    pub connected: bool,
+    pub users: Vec<UserStats>,
+    /// Oldest sample first, trimmed from the front as new ones arrive
+    pub history: VecDeque<HistoricalData>,
+    pub selected_user: usize,
+    pub system: SystemStats,
+    pub last_update_frame: u64,
+    pub command_scroll: usize,
+    pub connection_info: Option<ConnectionInfo>,
+    /// Handshake time from the check made before connecting, if there was one
+    pub preflight_latency_ms: Option<u64>,
+    /// When the home directory sizes were last asked for
+    pub disk_checked_at: Option<Instant>,
+    pub process_list_user: Option<String>,
+    pub processes: Vec<ProcessEntry>,
+    pub selected_process: usize,
+    pub traffic: Traffic,
+    pub traffic_since: Option<Instant>,
+    pub reconnect_attempts: u32,
+    pub next_retry_at: Option<Instant>,
+    pub reconnect_error: Option<String>,
+    /// Out of retries; waits for `r` while the other servers carry on
+    pub reconnect_failed: bool,
+    pub last_poll_at: Option<Instant>,
+    pub disk_usage: HashMap<String, f64>,
+    pub disk_updated_at: Option<Instant>,
+    /// A host key waiting for the user to trust or reject it
+    pub host_key_prompt: Option<UnknownHostKey>,
+    /// Users left out of the table by the exclusion list, kept so turning
+    /// it off shows them straight away
+    pub excluded_users: Vec<UserStats>,
+    /// Each user's last few CPU readings, oldest first
+    pub cpu_history: HashMap<String, VecDeque<f64>>,
+    /// Socket byte counters from the previous poll, to turn the next ones into rates
+    pub network_totals: HashMap<String, NetworkBytes>,
+    /// CPU tick counters from the previous poll, likewise
+    pub cpu_ticks: HashMap<String, CpuTicks>,
+    pub cpu_ticks_sampled_at: Option<Instant>,
+    /// Storage byte counters from the previous poll, likewise
+    pub io_bytes: HashMap<String, u64>,
+    pub io_sampled: bool,
+    pub network_sampled_at: Option<Instant>,
+    /// Machine-wide CPU usage between the last two polls, 0-100
+    pub system_cpu_percent: Option<f64>,
+    /// Disk counters from the previous poll, to turn the next ones into rates
+    pub disk_counters: HashMap<String, DiskCounters>,
+    pub disk_sampled_at: Option<Instant>,
+    /// Per-device throughput, sorted by device name
+    pub disk_io: Vec<DiskIoStats>,
+    /// Fetched once per connection, or again with Ctrl+U
+    pub boot_time: Option<DateTime<Local>>,
+    /// The server's own hostname and OS, fetched on connect
+    pub host_info: Option<HostInfo>,
+    /// Each user's stats from the previous poll, for the trend arrows
+    pub prev_stats: HashMap<String, UserStats>,
+    /// How long the last poll's commands took to come back
+    pub last_poll_duration_ms: Option<u64>,
+    /// Ctrl+R asked for a poll that hasn't come back yet
+    pub refresh_requested: bool,
+    /// Busiest processes of the user selected in the table, for the summary
+    pub selected_user_processes: Vec<ProcessEntry>,
+    /// Who `selected_user_processes` were fetched for
+    pub selected_user_processes_for: Option<String>,
+    /// End of the remote log file, refreshed with each poll while it is shown
+    pub log_lines: Vec<String>,
+    pub log_error: Option<String>,
+    /// Newest login from `last` for the user on the detail screen, once fetched
+    pub last_login: Option<String>,
+    pub last_login_for: Option<String>,
+    /// Users as they were when `B` was pressed, which CPU and RAM are then shown against
+    pub baseline: Option<HashMap<String, UserStats>>,
+    pub baseline_at: Option<DateTime<Local>>,
+    /// How many lines up from the end of the log the view is scrolled
+    pub log_scroll: usize,
+}
+
+impl ServerSession {
+    pub fn new(server: ServerConfig) -> ServerSession {
+        ServerSession {
+            server,
             loading: LoadingScreen::new(),
+            connecting_in_progress: false,
+            connected: false,
             users: Vec::new(),
-            history: Vec::new(),
+            history: VecDeque::new(),
             selected_user: 0,
-            sort_by: SortBy::Cpu,
-            should_quit: false,
-            total_ram_mb: 0.0,
+            system: SystemStats::default(),
+            last_update_frame: 0,
+            command_scroll: 0,
+            connection_info: None,
+            preflight_latency_ms: None,
+            disk_checked_at: None,
+            process_list_user: None,
+            processes: Vec::new(),
+            selected_process: 0,
+            traffic: Traffic::default(),
+            traffic_since: None,
+            reconnect_attempts: 0,
+            next_retry_at: None,
+            reconnect_error: None,
+            reconnect_failed: false,
+            last_poll_at: None,
+            disk_usage: HashMap::new(),
+            disk_updated_at: None,
+            host_key_prompt: None,
+            excluded_users: Vec::new(),
+            cpu_history: HashMap::new(),
+            network_totals: HashMap::new(),
+            network_sampled_at: None,
+            cpu_ticks: HashMap::new(),
+            cpu_ticks_sampled_at: None,
+            io_bytes: HashMap::new(),
+            io_sampled: false,
+            system_cpu_percent: None,
+            disk_counters: HashMap::new(),
+            disk_sampled_at: None,
+            disk_io: Vec::new(),
+            boot_time: None,
+            host_info: None,
+            prev_stats: HashMap::new(),
+            last_poll_duration_ms: None,
+            refresh_requested: false,
+            selected_user_processes: Vec::new(),
+            selected_user_processes_for: None,
+            log_lines: Vec::new(),
+            log_error: None,
+            last_login: None,
+            last_login_for: None,
+            baseline: None,
+            baseline_at: None,
+            log_scroll: 0,
         }
     }
 
-    pub fn update_data(&mut self, users: Vec<UserStats>) {
-        self.users = users;
-        self.sort_users();
-        
+    pub fn save_history(&self) -> Result<()> {
+        let path = self.server.history_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_string(&self.history)?)?;
+        Ok(())
+    }
+
+    /// Pre-populate the charts from the last run, dropping samples older than
+    /// `max_age_days` and keeping at most `max_history`. A missing file just
+    /// means there is nothing to load.
+    pub fn load_history(&mut self, max_age_days: u32, max_history: usize) -> Result<()> {
+        let contents = match std::fs::read_to_string(self.server.history_path()) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut history: VecDeque<HistoricalData> = serde_json::from_str(&contents)?;
+
+        let cutoff = Local::now() - chrono::Duration::days(max_age_days as i64);
+        history.retain(|h| h.timestamp >= cutoff);
+        if history.len() > max_history {
+            history.drain(..history.len() - max_history);
+        }
+        self.history = history;
+        Ok(())
+    }
+
+    /// Time since boot as "X days Y hours Z mins"
+    pub fn uptime(&self) -> Option<String> {
+        let minutes = (Local::now() - self.boot_time?).num_minutes().max(0);
+        Some(format!(
+            "{} days {} hours {} mins",
+            minutes / (24 * 60),
+            minutes / 60 % 24,
+            minutes % 60
+        ))
+    }
+
+    /// Whether the session dropped and is waiting to be rebuilt
+    pub fn is_reconnecting(&self) -> bool {
+        !self.connected && (self.next_retry_at.is_some() || self.reconnect_failed)
+    }
+
+    /// Style for the heartbeat dot, fading out after new data arrives. Frames
+    /// are turned into time so the fade takes as long at any UI poll interval.
+    pub fn heartbeat_style(&self, frame: u64, ui_poll_ms: u64, colors: &ThemeColors) -> Style {
+        let elapsed_ms = frame.wrapping_sub(self.last_update_frame).saturating_mul(ui_poll_ms);
+        if elapsed_ms <= HEARTBEAT_BRIGHT_MS {
+            Style::default().fg(colors.good).add_modifier(Modifier::BOLD)
+        } else if elapsed_ms <= HEARTBEAT_FADE_MS {
+            Style::default().fg(colors.good)
+        } else {
+            Style::default().fg(colors.muted)
+        }
+    }
+
+    pub fn apply_snapshot(
+        &mut self,
+        snapshot: Snapshot,
+        frame: u64,
+        sort_by: &SortBy,
+        max_history: usize,
+        exclusion: &UserExclusion,
+    ) {
+        // Needs the previous poll's counters, so the first poll leaves it unknown
+        if let (Some(current), Some(previous)) = (snapshot.system.cpu_times, self.system.cpu_times) {
+            self.system_cpu_percent = current.percent_since(&previous);
+        }
+        self.system = snapshot.system;
+        self.traffic.add(snapshot.traffic);
+        self.traffic_since.get_or_insert_with(Instant::now);
+        self.last_poll_at = Some(Instant::now());
+        self.last_poll_duration_ms = Some(snapshot.poll_duration.as_millis() as u64);
+        self.refresh_requested = false;
+        let mut users = snapshot.users;
+        self.apply_network_rates(&mut users, snapshot.network);
+        self.apply_cpu_split(&mut users, snapshot.cpu_ticks, snapshot.clock_ticks_per_sec);
+        self.apply_io_weight(&mut users, snapshot.io_bytes);
+        self.apply_disk_io_rates(snapshot.disks);
+        self.update_data(users, frame, sort_by, max_history, exclusion);
+    }
+
+    /// Work out each disk's throughput and utilization from how far its
+    /// counters moved since the previous poll
+    fn apply_disk_io_rates(&mut self, disks: HashMap<String, DiskCounters>) {
+        let now = Instant::now();
+        if let Some(sampled_at) = self.disk_sampled_at {
+            let secs = now.duration_since(sampled_at).as_secs_f64();
+            self.disk_io.clear();
+            for (device, current) in &disks {
+                let Some(previous) = self.disk_counters.get(device) else {
+                    continue;
+                };
+                if secs <= 0.0 {
+                    continue;
+                }
+                // Sectors are always 512 bytes in /proc/diskstats
+                let kbps = |current: u64, previous: u64| current.saturating_sub(previous) as f64 / 2.0 / secs;
+                let stats = DiskIoStats {
+                    device: device.clone(),
+                    read_kbps: kbps(current.sectors_read, previous.sectors_read),
+                    write_kbps: kbps(current.sectors_written, previous.sectors_written),
+                    util_percent: (current.io_ms.saturating_sub(previous.io_ms) as f64 / (secs * 10.0)).min(100.0),
+                };
+                if stats.is_device_mapper() && stats.read_kbps + stats.write_kbps < DM_MIN_KBPS {
+                    continue;
+                }
+                self.disk_io.push(stats);
+            }
+            self.disk_io.sort_by(|a, b| a.device.cmp(&b.device));
+        }
+        self.disk_counters = disks;
+        self.disk_sampled_at = Some(now);
+    }
+
+    /// Work out each user's user-space and kernel CPU from how many clock
+    /// ticks their processes used since the previous poll. Processes that
+    /// exited take their ticks with them, which counts as none used.
+    fn apply_cpu_split(&mut self, users: &mut [UserStats], ticks: HashMap<String, CpuTicks>, hz: u64) {
+        let now = Instant::now();
+        if let Some(sampled_at) = self.cpu_ticks_sampled_at {
+            let secs = now.duration_since(sampled_at).as_secs_f64();
+            let percent = |current: u64, previous: u64| current.saturating_sub(previous) as f64 / hz as f64 / secs * 100.0;
+            for user in users.iter_mut() {
+                if let (Some(current), Some(previous)) = (ticks.get(&user.username), self.cpu_ticks.get(&user.username))
+                    && secs > 0.0
+                {
+                    user.cpu_user_percent = percent(current.user, previous.user);
+                    user.cpu_sys_percent = percent(current.sys, previous.sys);
+                }
+            }
+        }
+        self.cpu_ticks = ticks;
+        self.cpu_ticks_sampled_at = Some(now);
+    }
+
+    /// Work out each user's share of the storage I/O done since the previous
+    /// poll. A share rather than a rate, since per-process wait time isn't
+    /// exposed and bytes moved are the nearest stand-in.
+    fn apply_io_weight(&mut self, users: &mut [UserStats], io_bytes: HashMap<String, u64>) {
+        if self.io_sampled {
+            let moved = |username: &str| {
+                let current = io_bytes.get(username).copied().unwrap_or(0);
+                current.saturating_sub(self.io_bytes.get(username).copied().unwrap_or(current))
+            };
+            let total: u64 = io_bytes.keys().map(|username| moved(username)).sum();
+            if total > 0 {
+                for user in users.iter_mut() {
+                    user.io_wait_percent = moved(&user.username) as f64 / total as f64 * 100.0;
+                }
+            }
+        }
+        self.io_bytes = io_bytes;
+        self.io_sampled = true;
+    }
+
+    /// Work out each user's throughput from how far their socket byte counters
+    /// moved since the previous poll. Closed sockets can make a total drop, which
+    /// counts as no traffic rather than negative.
+    fn apply_network_rates(&mut self, users: &mut [UserStats], network: HashMap<String, NetworkBytes>) {
+        let now = Instant::now();
+        if let Some(sampled_at) = self.network_sampled_at {
+            let secs = now.duration_since(sampled_at).as_secs_f64();
+            let kbps = |current: u64, previous: u64| current.saturating_sub(previous) as f64 * 8.0 / 1000.0 / secs;
+            for user in users.iter_mut() {
+                if let (Some(current), Some(previous)) =
+                    (network.get(&user.username), self.network_totals.get(&user.username))
+                    && secs > 0.0
+                {
+                    user.network_tx_kbps = kbps(current.sent, previous.sent);
+                    user.network_rx_kbps = kbps(current.received, previous.received);
+                }
+            }
+        }
+        self.network_totals = network;
+        self.network_sampled_at = Some(now);
+    }
+
+    /// True when every visible process belongs to the account we logged in as,
+    /// which usually means /proc is mounted with hidepid and the table is incomplete
+    pub fn only_own_processes_visible(&self) -> bool {
+        let login = self.server.username.as_str();
+        !self.users.is_empty()
+            && self.users.iter().all(|u| {
+                // ps truncates long usernames and marks them with a trailing '+'
+                u.username == login
+                    || u.username
+                        .strip_suffix('+')
+                        .is_some_and(|prefix| login.starts_with(prefix))
+            })
+    }
+
+    /// Estimated bytes per hour the monitor has used since connecting
+    pub fn traffic_per_hour(&self) -> f64 {
+        match self.traffic_since {
+            Some(since) => {
+                let hours = since.elapsed().as_secs_f64() / 3600.0;
+                if hours > 0.0 {
+                    self.traffic.total() as f64 / hours
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        }
+    }
+
+    pub fn update_data(
+        &mut self,
+        users: Vec<UserStats>,
+        frame: u64,
+        sort_by: &SortBy,
+        max_history: usize,
+        exclusion: &UserExclusion,
+    ) {
+        self.prev_stats = std::mem::take(&mut self.users)
+            .into_iter()
+            .map(|user| (user.username.clone(), user))
+            .collect();
+        (self.excluded_users, self.users) = users.into_iter().partition(|u| exclusion.excludes(u));
+        self.fill_disk_usage();
+        self.last_update_frame = frame;
+
+        // Per-user CPU trend, forgetting users who have no processes left
+        self.cpu_history
+            .retain(|username, _| self.users.iter().any(|u| &u.username == username));
+        for user in &self.users {
+            let readings = self.cpu_history.entry(user.username.clone()).or_default();
+            readings.push_back(user.cpu_percent);
+            if readings.len() > CPU_HISTORY_LEN {
+                readings.pop_front();
+            }
+        }
+        self.sort_users(sort_by);
+
         // Calculate totals for history
         let cpu_total: f64 = self.users.iter().map(|u| u.cpu_percent).sum();
         let ram_total: f64 = self.users.iter().map(|u| u.ram_mb).sum();
+        let swap_total: f64 = self.users.iter().map(|u| u.swap_mb).sum();
+        let physical_disks = self.disk_io.iter().filter(|d| !d.is_device_mapper());
+        let (disk_read_kbps, disk_write_kbps) =
+            physical_disks.fold((0.0, 0.0), |(read, write), d| (read + d.read_kbps, write + d.write_kbps));
         
-        self.history.push(HistoricalData {
-            _timestamp: Local::now(),
+        self.history.push_back(HistoricalData {
+            timestamp: Local::now(),
             cpu_total,
             ram_total,
+            swap_total,
+            disk_read_kbps,
+            disk_write_kbps,
+            load_avg_1min: self.system.load_average[0],
+            cpu_user_total: self.users.iter().map(|u| u.cpu_user_percent).sum(),
+            cpu_sys_total: self.users.iter().map(|u| u.cpu_sys_percent).sum(),
         });
         
-        // Keep only last MAX_HISTORY entries
-        if self.history.len() > MAX_HISTORY {
-            self.history.remove(0);
+        // Keep only the last max_history entries
+        while self.history.len() > max_history {
+            self.history.pop_front();
+        }
+    }
+
+    /// Split the users again after the exclusion list was turned on or off
+    pub fn apply_exclusion(&mut self, exclusion: &UserExclusion, sort_by: &SortBy) {
+        let users: Vec<UserStats> = std::mem::take(&mut self.users)
+            .into_iter()
+            .chain(std::mem::take(&mut self.excluded_users))
+            .collect();
+        (self.excluded_users, self.users) = users.into_iter().partition(|u| exclusion.excludes(u));
+        self.sort_users(sort_by);
+        self.selected_user = 0;
+    }
+
+    /// Store the latest home directory sizes and show them against each user
+    pub fn set_disk_usage(&mut self, usage: HashMap<String, f64>, sort_by: &SortBy) {
+        self.disk_usage = usage;
+        self.disk_updated_at = Some(Instant::now());
+        self.fill_disk_usage();
+        self.sort_users(sort_by);
+    }
+
+    /// Copy the last known disk sizes onto the users, which arrive without them
+    fn fill_disk_usage(&mut self) {
+        for user in &mut self.users {
+            user.disk_usage_mb = self.disk_usage.get(&user.username).copied();
+        }
+    }
+
+    /// True once the sizes have missed a couple of their scheduled refreshes
+    pub fn disk_usage_is_stale(&self, interval_secs: u64) -> bool {
+        self.disk_updated_at
+            .is_none_or(|at| at.elapsed() > Duration::from_secs(interval_secs * 2))
+    }
+
+    pub fn sort_users(&mut self, sort_by: &SortBy) {
+        match sort_by {
+            SortBy::Cpu => {
+                self.users.sort_by(|a, b| descending_nan_last(a.cpu_percent, b.cpu_percent));
+            }
+            // Every user is divided by the same total, so RAM% orders the same as RAM
+            SortBy::Ram | SortBy::RamPercent => {
+                self.users.sort_by(|a, b| descending_nan_last(a.ram_mb, b.ram_mb));
+            }
+            SortBy::ProcessCount => {
+                self.users.sort_by_key(|u| std::cmp::Reverse(u.process_count));
+            }
+            SortBy::Username => {
+                self.users.sort_by(|a, b| a.username.cmp(&b.username));
+            }
+            // Users without a known size go last
+            SortBy::Disk => {
+                // Sizes not measured yet go after every known one
+                self.users.sort_by(|a, b| match (a.disk_usage_mb, b.disk_usage_mb) {
+                    (Some(a), Some(b)) => descending_nan_last(a, b),
+                    (a, b) => b.is_some().cmp(&a.is_some()),
+                });
+            }
+            SortBy::LastUpdated => {
+                self.users.sort_by_key(|u| std::cmp::Reverse(u.last_updated));
+            }
+            SortBy::IoWait => {
+                self.users.sort_by(|a, b| descending_nan_last(a.io_wait_percent, b.io_wait_percent));
+            }
+        }
+    }
+
+    /// Store how the session was set up, with the latency from the check made
+    /// before connecting
+    pub fn set_connection_info(&mut self, mut info: ConnectionInfo) {
+        info.latency_ms = self.preflight_latency_ms;
+        self.connection_info = Some(info);
+    }
+
+    /// Keep the previous lines on failure, so a hiccup doesn't blank the view
+    pub fn set_log_tail(&mut self, result: std::result::Result<Vec<String>, String>) {
+        match result {
+            Ok(lines) => {
+                self.log_lines = lines;
+                self.log_error = None;
+            }
+            Err(e) => self.log_error = Some(e),
+        }
+    }
+
+    pub fn set_processes(&mut self, processes: Vec<ProcessEntry>) {
+        self.processes = processes;
+        self.selected_process = self
+            .selected_process
+            .min(self.processes.len().saturating_sub(1));
+    }
+
+    pub fn next_process(&mut self) {
+        if !self.processes.is_empty() {
+            self.selected_process = (self.selected_process + 1) % self.processes.len();
+        }
+    }
+
+    pub fn previous_process(&mut self) {
+        if !self.processes.is_empty() {
+            if self.selected_process > 0 {
+                self.selected_process -= 1;
+            } else {
+                self.selected_process = self.processes.len() - 1;
+            }
+        }
+    }
+
+    /// A user's memory as a share of the server's total, if the total is known
+    pub fn ram_percent(&self, user: &UserStats) -> Option<f64> {
+        let total = self.system.ram_total_mb;
+        (total > 0.0).then(|| user.ram_mb / total * 100.0)
+    }
+
+    /// The user's recent CPU readings as block characters, scaled so a full
+    /// block is one core's worth or the user's peak, whichever is higher
+    pub fn cpu_sparkline(&self, username: &str) -> String {
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let Some(readings) = self.cpu_history.get(username) else {
+            return String::new();
+        };
+        let recent = readings.iter().skip(readings.len().saturating_sub(CPU_TREND_LEN));
+        let max = recent.clone().copied().fold(100.0, f64::max);
+        recent
+            .map(|cpu| BARS[((cpu / max) * (BARS.len() - 1) as f64).round() as usize])
+            .collect()
+    }
+
+    /// Users whose name passes `filter` (see `matches_filter`), in table order
+    pub fn filtered_users(&self, filter: &str) -> Vec<&UserStats> {
+        self.users
+            .iter()
+            .filter(|u| matches_filter(&u.username, filter))
+            .collect()
+    }
+
+    /// Keep the selection on a row of the table as shown with `filter`,
+    /// since users can drop out of it between polls
+    pub fn clamp_selection(&mut self, filter: &str) {
+        let count = self.filtered_users(filter).len();
+        self.selected_user = self.selected_user.min(count.saturating_sub(1));
+    }
+
+    pub fn scroll_command_left(&mut self) {
+        self.command_scroll = self.command_scroll.saturating_sub(8);
+    }
+}
+
+pub struct App {
+    pub state: AppState,
+    pub config: ConfigScreen,
+    pub servers: Vec<ServerSession>,
+    pub active_tab: usize,
+    pub generation: u64,
+    pub connects_in_flight: usize,
+    pub sort_by: SortBy,
+    pub should_quit: bool,
+    pub show_load_gauge: bool,
+    pub frame: u64,
+    /// Column under the header cursor. Kept as a column rather than a position,
+    /// since the Net column comes and goes with the traffic.
+    pub selected_column: Column,
+    pub show_swap_column: bool,
+    /// Each user's share of the storage I/O
+    pub show_io_column: bool,
+    pub show_uid_column: bool,
+    pub show_top_process_column: bool,
+    pub show_cpu_trend_column: bool,
+    /// User and kernel CPU columns, and the CPU chart split the same way
+    pub show_cpu_split_columns: bool,
+    pub show_ram_percent_column: bool,
+    /// A line on each chart at the highest value in its history
+    pub show_highwater: bool,
+    /// Which CPUs each process may run on, in the process list
+    pub show_process_affinity: bool,
+    pub show_traffic: bool,
+    pub alert_config: AlertConfig,
+    pub exclusion: UserExclusion,
+    pub alerts: Vec<Alert>,
+    /// Every alert raised since starting, oldest first
+    pub alert_history: Vec<Alert>,
+    pub selected_alert: usize,
+    pub max_retries: u32,
+    pub refresh_interval_secs: u64,
+    pub disk_interval_secs: u64,
+    /// How long the main loop waits for a key before redrawing
+    pub ui_poll_ms: u64,
+    pub history_max_age_days: u32,
+    /// Samples kept per server for the charts
+    pub max_history: usize,
+    pub user_filter: String,
+    pub filter_editing: bool,
+    pub show_help: bool,
+    pub toast: Option<Toast>,
+    pub command_input: String,
+    pub command_run: Option<CommandRun>,
+    pub command_output_scroll: u16,
+    pub pending_kill: Option<PendingKill>,
+    pub theme: Theme,
+    pub graph_style: GraphStyle,
+    pub paused: bool,
+    /// Log file on the server shown by `L`
+    pub log_path: String,
+    /// Log lines matching this are shown in red
+    pub log_highlight: Regex,
+}
+
+impl App {
+    pub fn new() -> App {
+        App {
+            state: AppState::Config,
+            config: ConfigScreen::load_from_file().unwrap_or_else(|_| ConfigScreen::new()),
+            servers: Vec::new(),
+            active_tab: 0,
+            generation: 0,
+            connects_in_flight: 0,
+            sort_by: SortBy::Cpu,
+            should_quit: false,
+            show_load_gauge: true,
+            frame: 0,
+            selected_column: Column::Cpu,
+            show_swap_column: false,
+            show_io_column: false,
+            show_uid_column: false,
+            show_top_process_column: false,
+            show_cpu_trend_column: false,
+            show_cpu_split_columns: false,
+            show_ram_percent_column: false,
+            show_highwater: false,
+            show_process_affinity: false,
+            show_traffic: false,
+            alert_config: AlertConfig::default(),
+            exclusion: UserExclusion::default(),
+            alerts: Vec::new(),
+            alert_history: Vec::new(),
+            selected_alert: 0,
+            max_retries: 5,
+            refresh_interval_secs: 2,
+            ui_poll_ms: DEFAULT_UI_POLL_MS,
+            disk_interval_secs: 60,
+            history_max_age_days: 7,
+            max_history: 100,
+            user_filter: String::new(),
+            filter_editing: false,
+            show_help: false,
+            toast: None,
+            command_input: String::new(),
+            command_run: None,
+            command_output_scroll: 0,
+            pending_kill: None,
+            theme: Theme::Dark,
+            graph_style: GraphStyle::Braille,
+            paused: false,
+            log_path: DEFAULT_LOG_PATH.to_string(),
+            log_highlight: Regex::new(DEFAULT_LOG_HIGHLIGHT).unwrap(),
+        }
+    }
+
+    /// Set the UI poll interval, clamped to what stays usable. Returns a
+    /// warning when the value had to be clamped or will feel sluggish.
+    pub fn set_ui_poll_ms(&mut self, ms: u64) -> Option<String> {
+        self.ui_poll_ms = ms.clamp(MIN_UI_POLL_MS, MAX_UI_POLL_MS);
+        if self.ui_poll_ms != ms {
+            Some(format!(
+                "UI poll interval {} ms is outside {}-{} ms, using {} ms",
+                ms, MIN_UI_POLL_MS, MAX_UI_POLL_MS, self.ui_poll_ms
+            ))
+        } else if ms > SLUGGISH_UI_POLL_MS {
+            Some(format!("UI poll interval {} ms will make keys and the display slower to respond", ms))
+        } else {
+            None
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    /// The server on the active tab. Only valid once connecting has started.
+    pub fn server(&self) -> &ServerSession {
+        &self.servers[self.active_tab]
+    }
+
+    pub fn server_mut(&mut self) -> &mut ServerSession {
+        &mut self.servers[self.active_tab]
+    }
+
+    /// The server in slot `index`, unless the list has been replaced since
+    /// `generation` and the slot now belongs to a different connection
+    pub fn session_mut(&mut self, generation: u64, index: usize) -> Option<&mut ServerSession> {
+        if generation == self.generation {
+            self.servers.get_mut(index)
+        } else {
+            None
+        }
+    }
+
+    /// Store new data for the server in slot `index` and raise or clear alerts
+    /// for it. Returns false if that server is no longer being monitored.
+    pub fn apply_snapshot(&mut self, generation: u64, index: usize, snapshot: Snapshot) -> bool {
+        let frame = self.frame;
+        let sort_by = self.sort_by.clone();
+        let max_history = self.max_history;
+        let exclusion = self.exclusion.clone();
+        let filter = self.user_filter.clone();
+        let Some(server) = self.session_mut(generation, index) else {
+            return false;
+        };
+        server.apply_snapshot(snapshot, frame, &sort_by, max_history, &exclusion);
+        server.clamp_selection(&filter);
+        let label = server.server.label();
+        let users = server.users.clone();
+        self.update_alerts(&label, &users);
+        true
+    }
+
+    /// Hand fresh home directory sizes to the server they came from. Returns
+    /// false if that server is no longer being monitored.
+    pub fn apply_disk_usage(&mut self, generation: u64, index: usize, usage: HashMap<String, f64>) -> bool {
+        let sort_by = self.sort_by.clone();
+        let Some(server) = self.session_mut(generation, index) else {
+            return false;
+        };
+        server.set_disk_usage(usage, &sort_by);
+        true
+    }
+
+    /// Raise an alert for each user who has gone over a threshold, and drop
+    /// alerts for users who are back under it
+    fn update_alerts(&mut self, server: &str, users: &[UserStats]) {
+        let config = self.alert_config.clone();
+        let over = |username: &str, kind: AlertKind| {
+            let threshold = config.threshold(kind)?;
+            let user = users.iter().find(|u| u.username == username)?;
+            let value = kind.value(user);
+            (value > threshold).then_some(value)
+        };
+
+        self.alerts
+            .retain(|alert| alert.server != server || over(&alert.username, alert.kind).is_some());
+
+        for user in users {
+            for kind in AlertKind::ALL {
+                let Some(value) = over(&user.username, kind) else {
+                    continue;
+                };
+                let already_raised = self.alerts.iter().any(|alert| {
+                    alert.server == server && alert.username == user.username && alert.kind == kind
+                });
+                if !already_raised {
+                    let alert = Alert {
+                        server: server.to_string(),
+                        username: user.username.clone(),
+                        kind,
+                        value,
+                        threshold: config.threshold(kind).unwrap_or_default(),
+                        triggered_at: Local::now(),
+                    };
+                    self.alert_history.push(alert.clone());
+                    self.alerts.push(alert);
+                }
+            }
+        }
+
+        // Keep only the last MAX_ALERT_HISTORY entries
+        if self.alert_history.len() > MAX_ALERT_HISTORY {
+            self.alert_history.drain(..self.alert_history.len() - MAX_ALERT_HISTORY);
+            self.selected_alert = self.selected_alert.min(self.alert_history.len() - 1);
+        }
+    }
+
+    pub fn open_log_tail(&mut self) {
+        self.server_mut().log_scroll = 0;
+        self.state = AppState::LogTail;
+    }
+
+    /// Scroll back towards the start of the fetched lines
+    pub fn scroll_log_up(&mut self, lines: usize) {
+        let server = self.server_mut();
+        server.log_scroll = (server.log_scroll + lines).min(server.log_lines.len().saturating_sub(1));
+    }
+
+    /// Scroll forward towards the newest line
+    pub fn scroll_log_down(&mut self, lines: usize) {
+        let server = self.server_mut();
+        server.log_scroll = server.log_scroll.saturating_sub(lines);
+    }
+
+    pub fn open_alert_history(&mut self) {
+        // Start on the newest alert
+        self.selected_alert = self.alert_history.len().saturating_sub(1);
+        self.state = AppState::AlertHistory;
+    }
+
+    pub fn next_alert(&mut self) {
+        if self.selected_alert + 1 < self.alert_history.len() {
+            self.selected_alert += 1;
+        }
+    }
+
+    pub fn previous_alert(&mut self) {
+        self.selected_alert = self.selected_alert.saturating_sub(1);
+    }
+
+    /// Save the connection settings and alert thresholds to the config file
+    pub fn save_config(&self) -> Result<()> {
+        let mut values = self.config.file_values();
+        values.extend(self.alert_config.file_values());
+        config::save(values)
+    }
+
+    /// Save the connection settings in the form as `[profiles.<name>]`. Like
+    /// `save_config`, the password is never written.
+    pub fn save_profile(&self, name: &str) -> Result<()> {
+        config::save_profile(name, self.config.file_values())
+    }
+
+    /// Names of the profiles in the config file
+    pub fn list_profiles() -> Result<Vec<String>> {
+        Ok(config::load()?.profiles.into_keys().collect())
+    }
+
+    /// Replace the server list and show the first tab while it connects
+    pub fn start_servers(&mut self, servers: Vec<ServerConfig>) {
+        self.servers = servers.into_iter().map(ServerSession::new).collect();
+        self.active_tab = 0;
+        self.generation += 1;
+        self.state = AppState::Connecting;
+    }
+
+    /// Save the chart history of every server that has any
+    pub fn save_history(&self) -> Result<()> {
+        for server in self.servers.iter().filter(|s| !s.history.is_empty()) {
+            server.save_history()?;
+        }
+        Ok(())
+    }
+
+    /// Drop every server and go back to the config screen, keeping their
+    /// history for next time
+    pub fn disconnect_all(&mut self) {
+        if let Err(e) = self.save_history() {
+            self.config.error_message = Some(format!("Failed to save history: {}", e));
+        }
+        self.servers.clear();
+        self.alerts.clear();
+        self.user_filter.clear();
+        self.filter_editing = false;
+        self.paused = false;
+        self.active_tab = 0;
+        self.generation += 1;
+        self.state = AppState::Config;
+    }
+
+    /// Whether a connection attempt from an earlier visit to the config screen is still running
+    pub fn connecting_in_progress(&self) -> bool {
+        self.connects_in_flight > 0
+    }
+
+    pub fn next_tab(&mut self) {
+        if !self.servers.is_empty() {
+            self.active_tab = (self.active_tab + 1) % self.servers.len();
+            self.show_active_tab();
+        }
+    }
+
+    pub fn previous_tab(&mut self) {
+        if !self.servers.is_empty() {
+            self.active_tab = (self.active_tab + self.servers.len() - 1) % self.servers.len();
+            self.show_active_tab();
+        }
+    }
+
+    /// Show the monitoring screen for the active tab, or its loading or
+    /// reconnecting screen if it isn't up
+    pub fn show_active_tab(&mut self) {
+        let server = self.server();
+        self.state = if server.host_key_prompt.is_some() {
+            AppState::HostVerification
+        } else if server.connected {
+            AppState::Monitoring
+        } else if server.is_reconnecting() {
+            AppState::Reconnecting
+        } else {
+            AppState::Connecting
+        };
+    }
+
+    /// Take down the active tab's host key prompt once it has been answered
+    pub fn answer_host_key(&mut self) {
+        self.server_mut().host_key_prompt = None;
+        self.show_active_tab();
+    }
+
+    /// Step the refresh interval up: by a second below 10s, then by 5s
+    pub fn increase_refresh_interval(&mut self) {
+        let step = if self.refresh_interval_secs < 10 { 1 } else { 5 };
+        self.refresh_interval_secs = (self.refresh_interval_secs + step).min(MAX_REFRESH_INTERVAL_SECS);
+    }
+
+    pub fn decrease_refresh_interval(&mut self) {
+        let step = if self.refresh_interval_secs <= 10 { 1 } else { 5 };
+        self.refresh_interval_secs = self.refresh_interval_secs.saturating_sub(step).max(1);
+    }
+
+    /// Seconds until the active server is polled again
+    pub fn seconds_until_refresh(&self) -> u64 {
+        let interval = Duration::from_secs(self.refresh_interval_secs);
+        match self.server().last_poll_at {
+            Some(at) => interval.saturating_sub(at.elapsed()).as_secs(),
+            None => self.refresh_interval_secs,
+        }
+    }
+
+    pub fn set_sort(&mut self, sort_by: SortBy) {
+        self.sort_by = sort_by;
+        for server in &mut self.servers {
+            server.sort_users(&self.sort_by);
+        }
+    }
+
+    /// Columns currently shown in the monitoring table, in order
+    pub fn visible_columns(&self) -> Vec<Column> {
+        let mut columns = vec![Column::User, Column::Cpu];
+        if self.show_cpu_split_columns {
+            columns.push(Column::CpuUser);
+            columns.push(Column::CpuSys);
+        }
+        if self.show_cpu_trend_column {
+            columns.push(Column::CpuTrend);
+        }
+        columns.push(Column::Ram);
+        if self.show_ram_percent_column {
+            columns.push(Column::RamPercent);
+        }
+        columns.push(Column::Procs);
+        columns.push(Column::Sessions);
+        columns.push(Column::Disk);
+        // Only worth the width once some traffic has been seen
+        if self
+            .server()
+            .users
+            .iter()
+            .any(|u| u.network_rx_kbps > 0.0 || u.network_tx_kbps > 0.0)
+        {
+            columns.push(Column::Network);
+        }
+        if self.show_swap_column {
+            columns.push(Column::Swap);
+        }
+        if self.show_io_column {
+            columns.push(Column::IoWait);
+        }
+        if self.show_uid_column {
+            columns.push(Column::Uid);
+        }
+        if self.show_top_process_column {
+            columns.push(Column::TopProcess);
+        }
+        columns.push(Column::LastUpdated);
+        columns
+    }
+
+    /// The column under the header cursor, falling back to CPU while the
+    /// chosen one is hidden
+    pub fn cursor_column(&self) -> Column {
+        if self.visible_columns().contains(&self.selected_column) {
+            self.selected_column
+        } else {
+            Column::Cpu
+        }
+    }
+
+    pub fn next_column(&mut self) {
+        self.step_column(1);
+    }
+
+    pub fn previous_column(&mut self) {
+        self.step_column(-1);
+    }
+
+    fn step_column(&mut self, step: isize) {
+        let columns = self.visible_columns();
+        let current = self.cursor_column();
+        let position = columns.iter().position(|&column| column == current).unwrap_or(0);
+        self.selected_column = columns[(position as isize + step).rem_euclid(columns.len() as isize) as usize];
+    }
+
+    /// Sort by the column under the header cursor, if it is sortable
+    pub fn sort_by_selected_column(&mut self) {
+        if let Some(sort_by) = self.cursor_column().sort_by() {
+            self.set_sort(sort_by);
+        }
+    }
+
+    pub fn toggle_swap_column(&mut self) {
+        self.show_swap_column = !self.show_swap_column;
+    }
+
+    pub fn toggle_io_column(&mut self) {
+        self.show_io_column = !self.show_io_column;
+    }
+
+    /// Show or hide the excluded accounts on every server
+    pub fn toggle_exclusion(&mut self) {
+        self.exclusion.disabled = !self.exclusion.disabled;
+        let sort_by = self.sort_by.clone();
+        for server in &mut self.servers {
+            server.apply_exclusion(&self.exclusion, &sort_by);
+        }
+    }
+
+    pub fn toggle_uid_column(&mut self) {
+        self.show_uid_column = !self.show_uid_column;
+    }
+
+    pub fn toggle_top_process_column(&mut self) {
+        self.show_top_process_column = !self.show_top_process_column;
+    }
+
+    pub fn toggle_cpu_split_columns(&mut self) {
+        self.show_cpu_split_columns = !self.show_cpu_split_columns;
+    }
+
+    pub fn toggle_cpu_trend_column(&mut self) {
+        self.show_cpu_trend_column = !self.show_cpu_trend_column;
+    }
+
+    /// Take a baseline of the current figures, or drop the one already taken
+    pub fn toggle_baseline(&mut self) {
+        let server = self.server_mut();
+        if server.baseline.take().is_some() {
+            server.baseline_at = None;
+        } else {
+            server.baseline = Some(server.users.iter().map(|u| (u.username.clone(), u.clone())).collect());
+            server.baseline_at = Some(Local::now());
+        }
+    }
+
+    pub fn toggle_process_affinity(&mut self) {
+        self.show_process_affinity = !self.show_process_affinity;
+    }
+
+    pub fn toggle_highwater(&mut self) {
+        self.show_highwater = !self.show_highwater;
+    }
+
+    pub fn toggle_ram_percent_column(&mut self) {
+        self.show_ram_percent_column = !self.show_ram_percent_column;
+    }
+
+    /// Users on the active tab that pass the filter. The selection indexes into this list.
+    pub fn visible_users(&self) -> Vec<&UserStats> {
+        self.server().filtered_users(&self.user_filter)
+    }
+
+    /// The highlighted user, or None when the table is empty
+    pub fn user_at_selection(&self) -> Option<&UserStats> {
+        self.visible_users().get(self.server().selected_user).copied()
+    }
+
+    pub fn next_user(&mut self) {
+        let count = self.visible_users().len();
+        if count > 0 {
+            let server = self.server_mut();
+            server.selected_user = (server.selected_user + 1) % count;
+            server.command_scroll = 0;
+        }
+    }
+
+    pub fn previous_user(&mut self) {
+        let count = self.visible_users().len();
+        if count > 0 {
+            let server = self.server_mut();
+            if server.selected_user > 0 && server.selected_user < count {
+                server.selected_user -= 1;
+            } else {
+                server.selected_user = count - 1;
+            }
+            server.command_scroll = 0;
+        }
+    }
+
+    /// Move the selection by `offset` rows, stopping at the first and last
+    /// user rather than wrapping like the arrow keys
+    pub fn move_user_selection(&mut self, offset: isize) {
+        let count = self.visible_users().len();
+        if count > 0 {
+            let server = self.server_mut();
+            server.selected_user = server.selected_user.saturating_add_signed(offset).min(count - 1);
+            server.command_scroll = 0;
+        }
+    }
+
+    pub fn first_user(&mut self) {
+        self.move_user_selection(isize::MIN);
+    }
+
+    pub fn last_user(&mut self) {
+        self.move_user_selection(isize::MAX);
+    }
+
+    pub fn page_down_users(&mut self) {
+        self.move_user_selection(USER_PAGE_ROWS);
+    }
+
+    pub fn page_up_users(&mut self) {
+        self.move_user_selection(-USER_PAGE_ROWS);
+    }
+
+    /// Select the user drawn at terminal row `row`, if any, given the size of the terminal
+    pub fn select_user_at_row(&mut self, area: Rect, row: u16) {
+        let (_, table_area) = user_table_areas(self, area);
+        // Rows start below the top border and the header
+        let first_row = table_area.y + 2;
+        let visible_rows = table_area.height.saturating_sub(3) as usize;
+        if row < first_row || (row - first_row) as usize >= visible_rows {
+            return;
+        }
+
+        // The table scrolls just far enough to keep the selection on screen
+        let offset = (self.server().selected_user + 1).saturating_sub(visible_rows);
+        let index = offset + (row - first_row) as usize;
+        if index < self.visible_users().len() {
+            let server = self.server_mut();
+            server.selected_user = index;
+            server.command_scroll = 0;
+        }
+    }
+
+    pub fn scroll_command_right(&mut self) {
+        let len = self
+            .user_at_selection()
+            .map(|u| u.top_command.chars().count())
+            .unwrap_or(0);
+        let server = self.server_mut();
+        server.command_scroll = (server.command_scroll + 8).min(len.saturating_sub(1));
+    }
+
+    /// Write the active server's users to a timestamped CSV in the working
+    /// directory and report how it went in a toast
+    pub fn export_users(&mut self) {
+        let server = self.server();
+        let filename = format!(
+            "server-users-{}-{}.csv",
+            server.server.file_name_label(),
+            Local::now().format("%Y%m%d-%H%M%S")
+        );
+        match export::export_csv(&server.users, Path::new(&filename)) {
+            Ok(()) => self.show_toast(format!("Exported to {}", filename), false),
+            Err(e) => self.show_toast(format!("Export failed: {}", e), true),
+        }
+    }
+
+    /// Write the active server's alert history to a timestamped JSON file in
+    /// the working directory
+    pub fn export_alerts(&mut self) {
+        let label = self.server().server.label();
+        let alerts: Vec<Alert> = self
+            .alert_history
+            .iter()
+            .filter(|alert| alert.server == label)
+            .cloned()
+            .collect();
+        let filename = format!(
+            "server-users-alerts-{}-{}.json",
+            self.server().server.file_name_label(),
+            Local::now().format("%Y%m%d-%H%M%S")
+        );
+        match export::export_alerts_json(&alerts, Path::new(&filename)) {
+            Ok(()) => self.show_toast(format!("Exported {} alerts to {}", alerts.len(), filename), false),
+            Err(e) => self.show_toast(format!("Export failed: {}", e), true),
+        }
+    }
+
+    /// Mark the active server as refreshing ahead of a forced poll. Paused
+    /// servers don't poll, so there is nothing to wait for.
+    pub fn request_refresh(&mut self) -> bool {
+        if self.paused {
+            return false;
+        }
+        self.server_mut().refresh_requested = true;
+        true
+    }
+
+    pub fn cycle_theme(&mut self) {
+        self.theme = self.theme.next();
+        self.show_toast(format!("Theme: {}", self.theme.label()), false);
+    }
+
+    pub fn cycle_graph_style(&mut self) {
+        self.graph_style = self.graph_style.next();
+        self.show_toast(format!("Chart markers: {}", self.graph_style.label()), false);
+    }
+
+    pub fn show_toast(&mut self, message: String, is_error: bool) {
+        self.toast = Some(Toast {
+            message,
+            is_error,
+            shown_at: Instant::now(),
+        });
+    }
+
+    /// The toast, while it is still meant to be on screen
+    pub fn visible_toast(&self) -> Option<&Toast> {
+        self.toast.as_ref().filter(|toast| toast.shown_at.elapsed() < TOAST_DURATION)
+    }
+
+    /// Open the filter bar above the user table
+    pub fn open_filter(&mut self) {
+        self.filter_editing = true;
+    }
+
+    /// Clear the filter and close the bar
+    pub fn clear_filter(&mut self) {
+        self.user_filter.clear();
+        self.filter_editing = false;
+        self.reset_user_selection();
+    }
+
+    pub fn push_filter_char(&mut self, c: char) {
+        self.user_filter.push(c);
+        self.reset_user_selection();
+    }
+
+    pub fn pop_filter_char(&mut self) {
+        self.user_filter.pop();
+        self.reset_user_selection();
+    }
+
+    /// Go back to the top row, since the old index may point elsewhere in the new view
+    fn reset_user_selection(&mut self) {
+        for server in &mut self.servers {
+            server.selected_user = 0;
+            server.command_scroll = 0;
+        }
+    }
+
+    /// The selected user's name when their processes haven't been asked for
+    /// yet, marking them as asked for. The old user's list is dropped so it
+    /// isn't shown under the new name.
+    pub fn take_process_preview_request(&mut self) -> Option<String> {
+        let username = self.user_at_selection()?.username.clone();
+        let server = self.server_mut();
+        if server.selected_user_processes_for.as_deref() == Some(username.as_str()) {
+            return None;
+        }
+        server.selected_user_processes.clear();
+        server.selected_user_processes_for = Some(username.clone());
+        Some(username)
+    }
+
+    /// Drill down into the selected user's processes. Returns false if no user is selected.
+    pub fn open_process_list(&mut self) -> bool {
+        let Some(username) = self.user_at_selection().map(|u| u.username.clone()) else {
+            return false;
+        };
+        let server = self.server_mut();
+        server.process_list_user = Some(username);
+        server.processes.clear();
+        server.selected_process = 0;
+        self.state = AppState::ProcessList;
+        true
+    }
+
+    /// Switch from the process list to a full-screen card for the same user
+    pub fn open_user_detail(&mut self) {
+        if self.server().process_list_user.is_some() {
+            self.state = AppState::UserDetail;
+        }
+    }
+
+    /// The user the detail screen is about, while they still have processes
+    pub fn user_detail_user(&self) -> Option<&UserStats> {
+        let server = self.server();
+        let username = server.process_list_user.as_deref()?;
+        server.users.iter().find(|u| u.username == username)
+    }
+
+    /// Move the detail screen to the next or previous user in the table,
+    /// moving the table's selection along with it
+    pub fn step_user_detail(&mut self, forward: bool) {
+        let visible: Vec<String> = self.visible_users().iter().map(|u| u.username.clone()).collect();
+        if visible.is_empty() {
+            return;
+        }
+        let server = self.server_mut();
+        let current = server
+            .process_list_user
+            .as_ref()
+            .and_then(|username| visible.iter().position(|u| u == username))
+            .unwrap_or(server.selected_user);
+        let next = if forward {
+            (current + 1) % visible.len()
+        } else {
+            (current + visible.len() - 1) % visible.len()
+        };
+        server.selected_user = next;
+        server.command_scroll = 0;
+        server.process_list_user = Some(visible[next].clone());
+        server.processes.clear();
+        server.selected_process = 0;
+    }
+
+    pub fn close_process_list(&mut self) {
+        let server = self.server_mut();
+        server.process_list_user = None;
+        server.processes.clear();
+        self.pending_kill = None;
+        self.state = AppState::Monitoring;
+    }
+
+    /// Ask to confirm sending `signal` to the highlighted process
+    pub fn request_kill(&mut self, signal: KillSignal) {
+        let server = self.server();
+        self.pending_kill = server.processes.get(server.selected_process).map(|process| PendingKill {
+            pid: process.pid,
+            command: process.command.clone(),
+            signal,
+        });
+    }
+
+    pub fn open_command_input(&mut self) {
+        self.command_input.clear();
+        self.state = AppState::CommandInput;
+    }
+
+    /// Move from the input box to the output panel, returning the command for
+    /// the worker to run. Nothing happens if the box is empty.
+    pub fn submit_command(&mut self) -> Option<String> {
+        let command = self.command_input.trim().to_string();
+        if command.is_empty() {
+            return None;
+        }
+        self.command_run = Some(CommandRun {
+            command: command.clone(),
+            result: None,
+        });
+        self.command_output_scroll = 0;
+        self.state = AppState::CommandOutput;
+        Some(command)
+    }
+
+    pub fn close_command_output(&mut self) {
+        self.command_run = None;
+        self.state = AppState::Monitoring;
+    }
+
+    pub fn scroll_command_output_down(&mut self) {
+        self.command_output_scroll = self.command_output_scroll.saturating_add(1);
+    }
+
+    pub fn scroll_command_output_up(&mut self) {
+        self.command_output_scroll = self.command_output_scroll.saturating_sub(1);
+    }
+}
+
+pub fn ui(f: &mut Frame, app: &App) {
+    let colors = app.theme.colors();
+    match app.state {
+        AppState::Config => render_config_screen(f, &app.config, app.connecting_in_progress(), &colors),
+        AppState::Connecting => render_loading_screen(f, app, &colors),
+        AppState::HostVerification => render_host_verification_screen(f, app, &colors),
+        AppState::Reconnecting => render_reconnecting_screen(f, app, &colors),
+        AppState::Monitoring => render_monitoring_screen(f, app, &colors),
+        AppState::ProcessList => render_process_list_screen(f, app, &colors),
+        AppState::UserDetail => match app.user_detail_user() {
+            Some(user) => render_user_detail_screen(f, app, user, &colors),
+            // Gone from `ps` since the screen was opened
+            None => render_process_list_screen(f, app, &colors),
+        },
+        AppState::CommandInput => {
+            render_monitoring_screen(f, app, &colors);
+            render_command_input(f, app, &colors);
+        }
+        AppState::CommandOutput => {
+            render_monitoring_screen(f, app, &colors);
+            render_command_output_screen(f, app, &colors);
         }
+        AppState::DiskIO => render_disk_io_screen(f, app, &colors),
+        AppState::LogTail => render_log_tail_screen(f, app, &colors),
+        AppState::AlertHistory => {
+            render_monitoring_screen(f, app, &colors);
+            render_alert_history(f, app, &colors);
+        }
+    }
+
+    if app.show_help {
+        render_help_overlay(f, app.graph_style, &colors);
     }
+}
+
+/// A command typed with `x` and, once it has finished, what came of it
+#[derive(Clone, Debug)]
+pub struct CommandRun {
+    pub command: String,
+    pub result: Option<std::result::Result<CommandOutput, String>>,
+}
+
+/// A process waiting on the user to confirm sending it a signal
+#[derive(Clone, Debug)]
+pub struct PendingKill {
+    pub pid: u32,
+    pub command: String,
+    pub signal: KillSignal,
+}
+
+/// Output lines shown for a command; anything past this is cut off
+const MAX_COMMAND_OUTPUT_LINES: usize = 50;
+
+/// A short message shown briefly in the summary panel
+#[derive(Clone, Debug)]
+pub struct Toast {
+    pub message: String,
+    pub is_error: bool,
+    pub shown_at: Instant,
+}
+
+/// How long a toast stays up
+const TOAST_DURATION: Duration = Duration::from_secs(1);
+
+/// Every keybinding outside the config screen, grouped by what it acts on
+const HELP_SECTIONS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "Navigation",
+        &[
+            ("↑/↓", "Select user, or process in the process list"),
+            ("PgUp/PgDn", "Move the selection 10 users (also Ctrl+↑/↓)"),
+            ("Ctrl+Home/End", "Select the first or last user"),
+            ("Enter", "Show the selected user's processes"),
+            ("←/→", "Highlight a column header"),
+            ("</>", "Scroll the top process command line"),
+            ("[/]", "Switch server tab"),
+            ("q/Esc", "Back, or disconnect from the monitoring screen"),
+        ],
+    ),
+    (
+        "Process list",
+        &[
+            ("t", "Send SIGTERM to the selected process"),
+            ("k", "Send SIGKILL to the selected process"),
+            ("a", "Show which CPUs each process may run on"),
+            ("Enter", "Open the user's detail card (←/→ other users)"),
+        ],
+    ),
+    (
+        "Sort",
+        &[
+            ("c", "Sort by CPU"),
+            ("r", "Sort by RAM"),
+            ("p", "Sort by process count"),
+            ("u", "Sort by username"),
+            ("t", "Sort by last updated"),
+            ("o", "Sort by share of storage I/O"),
+            ("s", "Sort by the highlighted column"),
+        ],
+    ),
+    (
+        "Filter",
+        &[
+            ("/", "Filter users by name"),
+            ("Enter", "Keep the filter and go back to the table"),
+            ("Esc", "Clear the filter"),
+        ],
+    ),
+    (
+        "Display",
+        &[
+            ("w", "Toggle swap column"),
+            ("O", "Toggle I/O column"),
+            ("i", "Toggle UID column"),
+            ("Ctrl+E", "Show/hide excluded users"),
+            ("n", "Toggle top process column"),
+            ("k", "Toggle CPU trend column"),
+            ("y", "Toggle user/kernel CPU columns"),
+            ("%", "Toggle RAM% column"),
+            ("h", "Toggle peak lines on the charts"),
+            ("m", "Toggle RSS/VSZ memory"),
+            ("g", "Toggle load gauge"),
+            ("+/-", "Lengthen or shorten the refresh interval"),
+            ("Space", "Pause or resume updates"),
+            ("T", "Cycle dark, light and color-blind friendly themes"),
+            ("v", "Cycle chart markers: braille, half block, dot"),
+            ("N", "Show the alert history"),
+            ("B", "Show CPU and RAM as changes since now, or go back"),
+            ("e", "Export the users to CSV"),
+            ("E", "Export the alert history to JSON"),
+            ("x", "Run a command on the server"),
+            ("Ctrl+R", "Poll the server now"),
+            ("Ctrl+U", "Refresh the server's uptime"),
+            ("d", "Show per-disk I/O"),
+            ("L", "Follow the server's log file"),
+        ],
+    ),
+    (
+        "Connection",
+        &[
+            ("r", "Retry a server that failed to connect"),
+            ("y/n", "Trust or reject an unknown host key"),
+            ("Esc", "Cancel connecting"),
+        ],
+    ),
+];
+
+/// A rectangle of the given percentage size in the middle of `area`
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+/// One-line box for typing a command to run on the active server
+fn render_command_input(f: &mut Frame, app: &App, colors: &ThemeColors) {
+    let popup = centered_rect(70, 100, f.area());
+    let area = Rect {
+        y: popup.y + popup.height.saturating_sub(3) / 2,
+        height: 3.min(popup.height),
+        ..popup
+    };
+    f.render_widget(Clear, area);
+    let input = Paragraph::new(format!("$ {}_", app.command_input))
+        .style(Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Run on {} (Enter to run, Esc to cancel)", app.server().server.label())),
+        );
+    f.render_widget(input, area);
+}
+
+/// The last command's exit code and output, scrollable and wrapped to the panel
+/// The selected user's busiest processes, in the summary column
+fn render_process_preview(f: &mut Frame, server: &ServerSession, username: &str, area: Rect, colors: &ThemeColors) {
+    let header = Row::new(vec!["PID", "CPU %", "Command"])
+        .style(Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD));
+    let rows: Vec<Row> = server
+        .selected_user_processes
+        .iter()
+        .take(PROCESS_PREVIEW_LEN)
+        .map(|process| {
+            Row::new(vec![
+                Cell::from(process.pid.to_string()),
+                Cell::from(format!("{:.1}", process.cpu_percent)),
+                Cell::from(process.command.clone()),
+            ])
+        })
+        .collect();
+    let title = if server.selected_user_processes.is_empty() {
+        format!("Processes - {} (loading...)", username)
+    } else {
+        format!("Processes - {}", username)
+    };
+    let table = Table::new(rows, [Constraint::Length(8), Constraint::Length(6), Constraint::Fill(1)])
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(table, area);
+}
+
+/// Every alert raised so far, newest at the bottom
+fn render_alert_history(f: &mut Frame, app: &App, colors: &ThemeColors) {
+    let area = centered_rect(80, 80, f.area());
+    f.render_widget(Clear, area);
+
+    let header = Row::new(vec!["Time", "Server", "User", "Alert", "Value", "Threshold"])
+        .style(Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD))
+        .height(1);
+    let rows: Vec<Row> = app
+        .alert_history
+        .iter()
+        .map(|alert| {
+            Row::new(vec![
+                Cell::from(alert.triggered_at.format("%Y-%m-%d %H:%M:%S").to_string()),
+                Cell::from(alert.server.clone()),
+                Cell::from(alert.username.clone()),
+                Cell::from(alert.kind.label()),
+                Cell::from(alert.kind.format_value(alert.value)).style(colors.alert_style()),
+                Cell::from(alert.kind.format_value(alert.threshold)),
+            ])
+        })
+        .collect();
+    let title = if app.alert_history.is_empty() {
+        "Alert history - nothing yet (Esc to close)".to_string()
+    } else {
+        format!("Alert history - {} alerts (↑/↓ to scroll, Esc to close)", app.alert_history.len())
+    };
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(19),
+            Constraint::Fill(3),
+            Constraint::Fill(2),
+            Constraint::Length(5),
+            Constraint::Fill(2),
+            Constraint::Fill(2),
+        ],
+    )
+    .header(header)
+    .highlight_style(Style::default().fg(colors.selection_fg).bg(colors.selection_bg))
+    .block(Block::default().borders(Borders::ALL).title(title));
+    let mut state = TableState::default().with_selected((!app.alert_history.is_empty()).then_some(app.selected_alert));
+    f.render_stateful_widget(table, area, &mut state);
+}
 
-    pub fn sort_users(&mut self) {
-        match self.sort_by {
-            SortBy::Cpu => {
-                self.users.sort_by(|a, b| b.cpu_percent.partial_cmp(&a.cpu_percent).unwrap());
-            }
-            SortBy::Ram => {
-                self.users.sort_by(|a, b| b.ram_mb.partial_cmp(&a.ram_mb).unwrap());
+fn render_command_output_screen(f: &mut Frame, app: &App, colors: &ThemeColors) {
+    let Some(run) = &app.command_run else {
+        return;
+    };
+    let area = centered_rect(80, 80, f.area());
+    f.render_widget(Clear, area);
+
+    let mut lines = vec![Line::from(Span::styled(
+        format!("$ {}", run.command),
+        Style::default().fg(colors.accent).add_modifier(Modifier::BOLD),
+    ))];
+    match &run.result {
+        None => lines.push(Line::from(Span::styled("Running...", colors.warning_style()))),
+        Some(Err(e)) => lines.push(Line::from(Span::styled(
+            format!("Failed to run: {}", e),
+            colors.alert_style(),
+        ))),
+        Some(Ok(output)) => {
+            let status_color = if output.exit_status == 0 { colors.good } else { colors.alert };
+            lines.push(Line::from(Span::styled(
+                format!("Exit code: {}", output.exit_status),
+                Style::default().fg(status_color),
+            )));
+            lines.push(Line::from(""));
+            let total = output.output.lines().count();
+            lines.extend(
+                output
+                    .output
+                    .lines()
+                    .take(MAX_COMMAND_OUTPUT_LINES)
+                    .map(|line| Line::from(line.to_string())),
+            );
+            if total > MAX_COMMAND_OUTPUT_LINES {
+                lines.push(Line::from(Span::styled(
+                    format!("... {} more lines not shown", total - MAX_COMMAND_OUTPUT_LINES),
+                    Style::default().fg(colors.muted),
+                )));
             }
         }
     }
 
-    pub fn set_sort(&mut self, sort_by: SortBy) {
-        self.sort_by = sort_by;
-        self.sort_users();
-    }
+    let output = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .scroll((app.command_output_scroll, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Command Output (↑/↓ to scroll, q/Esc to close)"),
+        );
+    f.render_widget(output, area);
+}
 
-    pub fn next_user(&mut self) {
-        if !self.users.is_empty() {
-            self.selected_user = (self.selected_user + 1) % self.users.len();
-        }
-    }
+/// Full-screen list of keybindings, drawn over whatever screen is active
+fn render_help_overlay(f: &mut Frame, graph_style: GraphStyle, colors: &ThemeColors) {
+    let area = f.area();
+    f.render_widget(Clear, area);
 
-    pub fn previous_user(&mut self) {
-        if !self.users.is_empty() {
-            if self.selected_user > 0 {
-                self.selected_user -= 1;
-            } else {
-                self.selected_user = self.users.len() - 1;
-            }
+    let mut lines = Vec::new();
+    for (section, bindings) in HELP_SECTIONS {
+        lines.push(Line::from(Span::styled(
+            *section,
+            Style::default().fg(colors.accent).add_modifier(Modifier::BOLD),
+        )));
+        for (keys, action) in *bindings {
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {:<8}", keys), Style::default().fg(colors.highlight)),
+                Span::raw(*action),
+            ]));
         }
+        lines.push(Line::from(""));
     }
+    lines.push(Line::from(vec![
+        Span::styled("Debug log ", Style::default().fg(colors.accent)),
+        Span::raw(format!("{} (enable with --log-level)", config::debug_log_path().display())),
+    ]));
+    lines.push(Line::from(vec![
+        Span::styled("Chart markers ", Style::default().fg(colors.accent)),
+        Span::raw(graph_style.label()),
+    ]));
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "Press ? or Esc to close",
+        Style::default().fg(colors.muted),
+    )));
+
+    let help = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Help - server_users v{}", env!("CARGO_PKG_VERSION"))),
+        )
+        .wrap(Wrap { trim: false });
+    f.render_widget(help, area);
 }
 
-pub fn ui(f: &mut Frame, app: &App) {
-    match app.state {
-        AppState::Config => render_config_screen(f, &app.config),
-        AppState::Connecting => render_loading_screen(f, &app.loading),
-        AppState::Monitoring => render_monitoring_screen(f, app),
+/// One tab per server, colored by connection state, with the active one highlighted
+fn server_tabs(app: &App, colors: &ThemeColors) -> Line<'static> {
+    let mut spans = Vec::new();
+    for (i, server) in app.servers.iter().enumerate() {
+        let style = if i == app.active_tab {
+            Style::default().fg(colors.selection_fg).bg(colors.selection_bg).add_modifier(Modifier::BOLD)
+        } else if server.loading.error.is_some() || server.reconnect_failed {
+            colors.alert_style()
+        } else if !server.connected {
+            colors.warning_style()
+        } else {
+            Style::default()
+        };
+        spans.push(Span::styled(format!(" {}:{} ", i + 1, server.server.label()), style));
+        spans.push(Span::raw(" "));
     }
+    Line::from(spans)
 }
 
-fn render_config_screen(f: &mut Frame, config: &ConfigScreen) {
+fn render_config_screen(f: &mut Frame, config: &ConfigScreen, connecting_in_progress: bool, colors: &ThemeColors) {
+    // A field's block, with the reason it is invalid along its bottom border
+    let field_errors = config.validate_fields();
+    let field_block = |field: ConfigField| {
+        let block = Block::default().borders(Borders::ALL);
+        match field_errors.iter().find(|(f, _)| *f == field) {
+            Some((_, reason)) => block.title_bottom(Span::styled(format!(" {} ", reason), colors.alert_style())),
+            None => block,
+        }
+    };
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(2)
@@ -285,6 +3064,10 @@ fn render_config_screen(f: &mut Frame, config: &ConfigScreen) {
             Constraint::Length(3),
             Constraint::Length(3),
             Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Length(3),
             Constraint::Min(2),
             Constraint::Length(3),
         ])
@@ -292,116 +3075,278 @@ fn render_config_screen(f: &mut Frame, config: &ConfigScreen) {
 
     // Title
     let title = Paragraph::new("SSH Server Monitor - Configuration")
-        .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+        .style(Style::default().fg(colors.accent).add_modifier(Modifier::BOLD))
         .alignment(Alignment::Center)
         .block(Block::default().borders(Borders::ALL));
     f.render_widget(title, chunks[0]);
 
+    // Saved profiles, or the name being typed for a new one
+    let profile_style = if config.current_field == ConfigField::Profile || config.profile_name_input.is_some() {
+        Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default()
+    };
+    let profile_text = if let Some(ref name) = config.profile_name_input {
+        format!("Save profile as: {}_ (Enter to save, Esc to cancel)", name)
+    } else if let Some(name) = config.profiles.get(config.selected_profile) {
+        format!(
+            "Profile: ◀ {} ▶ ({}/{})  ←/→ choose, Enter load, n new, s save",
+            name,
+            config.selected_profile + 1,
+            config.profiles.len()
+        )
+    } else {
+        "Profile: none saved  n new, s save the form as a profile".to_string()
+    };
+    let profile = Paragraph::new(profile_text)
+        .style(profile_style)
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(profile, chunks[1]);
+
     // Host
     let host_style = if config.current_field == ConfigField::Host {
-        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+        Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
     } else {
         Style::default()
     };
     let host = Paragraph::new(format!("Host: {}", config.host))
         .style(host_style)
-        .block(Block::default().borders(Borders::ALL));
-    f.render_widget(host, chunks[1]);
+        .block(field_block(ConfigField::Host));
+    f.render_widget(host, chunks[2]);
+
+    // Port
+    let port_style = if config.current_field == ConfigField::Port {
+        Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default()
+    };
+    let port_display = if config.port == 0 { String::new() } else { config.port.to_string() };
+    let port = Paragraph::new(format!("Port: {}", port_display))
+        .style(port_style)
+        .block(field_block(ConfigField::Port));
+    f.render_widget(port, chunks[3]);
 
     // Username
     let username_style = if config.current_field == ConfigField::Username {
-        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+        Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
     } else {
         Style::default()
     };
     let username = Paragraph::new(format!("Username: {}", config.username))
         .style(username_style)
-        .block(Block::default().borders(Borders::ALL));
-    f.render_widget(username, chunks[2]);
+        .block(field_block(ConfigField::Username));
+    f.render_widget(username, chunks[4]);
 
-    // Use SSH Key checkbox
+    // Authentication mode
     let ssh_key_style = if config.current_field == ConfigField::UseSSHKey {
-        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+        Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
     } else {
         Style::default()
     };
-    let checkbox = if config.use_ssh_key { "[X]" } else { "[ ]" };
-    let use_ssh_key = Paragraph::new(format!("{} Use SSH Key (Space to toggle)", checkbox))
-        .style(ssh_key_style)
-        .block(Block::default().borders(Borders::ALL));
-    f.render_widget(use_ssh_key, chunks[3]);
+    let radio = |selected: bool| if selected { "(•)" } else { "( )" };
+    let use_ssh_key = Paragraph::new(format!(
+        "Auth: {} Password  {} SSH Key  {} SSH Agent (Space to cycle)",
+        radio(!config.use_ssh_key && !config.use_ssh_agent),
+        radio(config.use_ssh_key),
+        radio(config.use_ssh_agent)
+    ))
+    .style(ssh_key_style)
+    .block(Block::default().borders(Borders::ALL));
+    f.render_widget(use_ssh_key, chunks[5]);
 
-    // Password or SSH Key Path
-    if config.use_ssh_key {
+    // Password, SSH Key Path, or agent note
+    if config.use_ssh_agent {
+        let agent_style = if config.current_field == ConfigField::UseSSHAgent {
+            Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        let agent = Paragraph::new("Using SSH agent")
+            .style(agent_style)
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(agent, chunks[6]);
+    } else if config.use_ssh_key {
         let key_path_style = if config.current_field == ConfigField::SSHKeyPath {
-            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+            Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
         } else {
             Style::default()
         };
+        let keyring_width = if cfg!(feature = "keyring") { 15 } else { 0 };
+        let key_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(30), Constraint::Length(34), Constraint::Length(keyring_width)])
+            .split(chunks[6]);
         let key_path = Paragraph::new(format!("SSH Key Path: {}", config.ssh_key_path))
             .style(key_path_style)
+            .block(field_block(ConfigField::SSHKeyPath));
+        f.render_widget(key_path, key_chunks[0]);
+
+        let passphrase_style = if config.current_field == ConfigField::KeyPassphrase {
+            Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        let passphrase_display = if config.key_passphrase.is_empty() && config.current_field != ConfigField::KeyPassphrase {
+            "(none)".to_string()
+        } else {
+            "*".repeat(config.key_passphrase.len())
+        };
+        let passphrase = Paragraph::new(format!("Passphrase: {}", passphrase_display))
+            .style(passphrase_style)
             .block(Block::default().borders(Borders::ALL));
-        f.render_widget(key_path, chunks[4]);
+        f.render_widget(passphrase, key_chunks[1]);
+
+        if cfg!(feature = "keyring") {
+            let keyring_style = if config.current_field == ConfigField::UseKeyring {
+                Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let keyring = Paragraph::new(format!("[{}] Keyring", if config.use_keyring { "x" } else { " " }))
+                .style(keyring_style)
+                .block(Block::default().borders(Borders::ALL));
+            f.render_widget(keyring, key_chunks[2]);
+        }
     } else {
         let password_style = if config.current_field == ConfigField::Password {
-            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+            Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
         } else {
             Style::default()
         };
         let password_display = "*".repeat(config.password.len());
         let password = Paragraph::new(format!("Password: {}", password_display))
             .style(password_style)
-            .block(Block::default().borders(Borders::ALL));
-        f.render_widget(password, chunks[4]);
+            .block(field_block(ConfigField::Password));
+        f.render_widget(password, chunks[6]);
     }
 
+    // Stats backend
+    let backend_style = if config.current_field == ConfigField::Backend {
+        Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default()
+    };
+    let backend = Paragraph::new(format!("Stats Backend: {} (Space to toggle)", config.backend.label()))
+        .style(backend_style)
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(backend, chunks[7]);
+
+    // Jump host toggle, address and port side by side
+    let field_style = |field: ConfigField| {
+        if config.current_field == field {
+            Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
+        } else if field != ConfigField::UseJumpHost && !config.use_jump_host {
+            Style::default().fg(colors.disabled)
+        } else {
+            Style::default()
+        }
+    };
+    let jump_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(30), Constraint::Min(20), Constraint::Length(16)])
+        .split(chunks[8]);
+    let checkbox = if config.use_jump_host { "[x]" } else { "[ ]" };
+    let use_jump_host = Paragraph::new(format!("{} Use jump host (Space)", checkbox))
+        .style(field_style(ConfigField::UseJumpHost))
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(use_jump_host, jump_chunks[0]);
+    let jump_host = Paragraph::new(format!("Jump Host: {}", config.jump_host))
+        .style(field_style(ConfigField::JumpHost))
+        .block(field_block(ConfigField::JumpHost));
+    f.render_widget(jump_host, jump_chunks[1]);
+    let jump_port_display = if config.jump_port == 0 { String::new() } else { config.jump_port.to_string() };
+    let jump_port = Paragraph::new(format!("Port: {}", jump_port_display))
+        .style(field_style(ConfigField::JumpPort))
+        .block(field_block(ConfigField::JumpPort));
+    f.render_widget(jump_port, jump_chunks[2]);
+
+    // Servers added so far for multi-server mode
+    let servers_text = if config.servers.is_empty() {
+        "Servers: only the one above (Ctrl+A to add it and enter another)".to_string()
+    } else {
+        let labels: Vec<String> = config.servers.iter().map(|s| s.label()).collect();
+        format!("Servers: {}", labels.join(", "))
+    };
+    let servers = Paragraph::new(servers_text)
+        .style(Style::default().fg(colors.muted))
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(servers, chunks[9]);
+
     // Instructions
     let instructions = vec![
         Line::from(vec![
-            Span::styled("Tab/Shift+Tab", Style::default().fg(Color::Green)),
+            Span::styled("Tab/Shift+Tab", Style::default().fg(colors.good)),
             Span::raw(": Navigate fields"),
         ]),
         Line::from(vec![
-            Span::styled("Space", Style::default().fg(Color::Green)),
-            Span::raw(": Toggle SSH Key"),
+            Span::styled("Space", Style::default().fg(colors.good)),
+            Span::raw(": Cycle auth mode / toggle backend or jump host"),
         ]),
         Line::from(vec![
-            Span::styled("Enter", Style::default().fg(Color::Green)),
+            Span::styled("Enter", Style::default().fg(colors.good)),
             Span::raw(": Connect"),
         ]),
         Line::from(vec![
-            Span::styled("Esc/q", Style::default().fg(Color::Green)),
+            Span::styled("Ctrl+A/Ctrl+D", Style::default().fg(colors.good)),
+            Span::raw(": Add server to list / remove last added"),
+        ]),
+        Line::from(vec![
+            Span::styled("Ctrl+S", Style::default().fg(colors.good)),
+            Span::raw(": Save config"),
+        ]),
+        Line::from(vec![
+            Span::styled("Esc/q", Style::default().fg(colors.good)),
             Span::raw(": Quit"),
         ]),
     ];
     let help = Paragraph::new(instructions)
         .block(Block::default().borders(Borders::ALL).title("Controls"));
-    f.render_widget(help, chunks[6]);
+    f.render_widget(help, chunks[10]);
 
     // Status/Error message
     let status_text = if let Some(ref error) = config.error_message {
         vec![Line::from(Span::styled(
             format!("Error: {}", error),
-            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            colors.alert_style().add_modifier(Modifier::BOLD),
+        ))]
+    } else if config.saved_at.is_some_and(|at| at.elapsed().as_secs() < 3) {
+        vec![Line::from(Span::styled(
+            format!("Saved to {}", config::config_path().display()),
+            Style::default().fg(colors.good).add_modifier(Modifier::BOLD),
         ))]
-    } else if config.is_valid() {
+    } else if config.checking_reachability {
         vec![Line::from(Span::styled(
-            "Press Enter to connect",
-            Style::default().fg(Color::Green),
+            "Checking that the servers can be reached...",
+            colors.warning_style(),
         ))]
+    } else if connecting_in_progress {
+        vec![Line::from(Span::styled(
+            "Waiting for the previous connection attempt to finish...",
+            colors.warning_style(),
+        ))]
+    } else if config.can_connect() {
+        let count = config.servers_to_connect().len();
+        let text = if count > 1 {
+            format!("Press Enter to connect to {} servers", count)
+        } else {
+            "Press Enter to connect".to_string()
+        };
+        vec![Line::from(Span::styled(text, Style::default().fg(colors.good)))]
     } else {
         vec![Line::from(Span::styled(
-            "Fill in all required fields",
-            Style::default().fg(Color::Yellow),
+            "Fix the fields marked in red to connect",
+            colors.warning_style(),
         ))]
     };
     let status = Paragraph::new(status_text)
         .alignment(Alignment::Center)
         .block(Block::default().borders(Borders::ALL));
-    f.render_widget(status, chunks[7]);
+    f.render_widget(status, chunks[11]);
 }
 
-fn render_loading_screen(f: &mut Frame, loading: &LoadingScreen) {
+fn render_loading_screen(f: &mut Frame, app: &App, colors: &ThemeColors) {
+    let server = app.server();
+    let loading = &server.loading;
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(4)
@@ -413,21 +3358,32 @@ fn render_loading_screen(f: &mut Frame, loading: &LoadingScreen) {
         ])
         .split(f.area());
 
-    // Title
-    let title = Paragraph::new("SSH Server Monitor")
-        .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
-        .alignment(Alignment::Center);
+    // Title, with the server tabs below it in multi-server mode
+    let mut title_lines = vec![Line::from(Span::styled(
+        format!("SSH Server Monitor - {}", server.server.label()),
+        Style::default().fg(colors.accent).add_modifier(Modifier::BOLD),
+    ))];
+    if app.servers.len() > 1 {
+        title_lines.push(Line::from(""));
+        title_lines.push(server_tabs(app, colors));
+    }
+    let title = Paragraph::new(title_lines).alignment(Alignment::Center);
     f.render_widget(title, chunks[0]);
 
-    // Message
-    let message = Paragraph::new(loading.message.clone())
-        .style(Style::default().fg(Color::Yellow))
+    // Message, replaced by the error once an attempt has failed
+    let message = if let Some(ref error) = loading.error {
+        Paragraph::new(format!("Connection failed: {}", error))
+            .style(colors.alert_style().add_modifier(Modifier::BOLD))
+    } else {
+        Paragraph::new(loading.message.clone()).style(colors.warning_style())
+    };
+    let message = message
         .alignment(Alignment::Center)
         .block(Block::default().borders(Borders::ALL));
     f.render_widget(message, chunks[1]);
 
-    // Progress bar
-    let progress_width = chunks[2].width.saturating_sub(4) as u16;
+    // Progress bar, filled up to the current stage
+    let progress_width = chunks[2].width.saturating_sub(4);
     let bar_position = ((loading.progress as f64 / 100.0) * progress_width as f64) as u16;
     
     let bar_char = "█";
@@ -435,172 +3391,1174 @@ fn render_loading_screen(f: &mut Frame, loading: &LoadingScreen) {
     
     let mut bar_string = String::new();
     for i in 0..progress_width {
-        if i >= bar_position.saturating_sub(5) && i <= bar_position {
+        if i < bar_position {
             bar_string.push_str(bar_char);
         } else {
             bar_string.push_str(empty_char);
         }
     }
-    
-    let progress_bar = Paragraph::new(bar_string)
-        .style(Style::default().fg(Color::Green))
-        .alignment(Alignment::Left)
-        .block(Block::default().borders(Borders::ALL).title(format!("Progress")));
-    f.render_widget(progress_bar, chunks[2]);
+    
+    let progress_bar = Paragraph::new(bar_string)
+        .style(Style::default().fg(colors.good))
+        .alignment(Alignment::Left)
+        .block(Block::default().borders(Borders::ALL).title(format!("Progress ({}%)", loading.progress)));
+    f.render_widget(progress_bar, chunks[2]);
+
+    // Hint
+    let mut hint_text = if loading.error.is_some() {
+        "Press r to retry, Esc to edit settings".to_string()
+    } else {
+        "Press Esc to cancel".to_string()
+    };
+    if app.servers.len() > 1 {
+        hint_text.push_str(", [/] to switch server");
+    }
+    let hint = Paragraph::new(hint_text)
+        .style(Style::default().fg(colors.muted))
+        .alignment(Alignment::Center);
+    f.render_widget(hint, chunks[3]);
+}
+
+fn render_host_verification_screen(f: &mut Frame, app: &App, colors: &ThemeColors) {
+    let server = app.server();
+    let Some(host_key) = &server.host_key_prompt else {
+        return;
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(30),
+            Constraint::Length(3),
+            Constraint::Length(8),
+            Constraint::Percentage(40),
+        ])
+        .split(f.area());
+
+    let mut title_lines = vec![Line::from(Span::styled(
+        format!("SSH Server Monitor - {}", server.server.label()),
+        Style::default().fg(colors.accent).add_modifier(Modifier::BOLD),
+    ))];
+    if app.servers.len() > 1 {
+        title_lines.push(Line::from(""));
+        title_lines.push(server_tabs(app, colors));
+    }
+    let title = Paragraph::new(title_lines).alignment(Alignment::Center);
+    f.render_widget(title, chunks[1]);
+
+    let host = if host_key.port == 22 {
+        host_key.host.clone()
+    } else {
+        format!("[{}]:{}", host_key.host, host_key.port)
+    };
+    let text = vec![
+        Line::from(format!("The authenticity of host {} can't be established.", host)),
+        Line::from(format!("{} key fingerprint is:", host_key.key_type)),
+        Line::from(Span::styled(
+            host_key.fingerprint.clone(),
+            Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from("Press Y to trust it and add it to ~/.ssh/known_hosts, or N to abort."),
+    ];
+    let prompt = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::ALL).title("Unknown Host Key"));
+    f.render_widget(prompt, chunks[2]);
+}
+
+fn render_reconnecting_screen(f: &mut Frame, app: &App, colors: &ThemeColors) {
+    let server = app.server();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(4)
+        .constraints([
+            Constraint::Percentage(40),
+            Constraint::Length(4),
+            Constraint::Percentage(40),
+        ])
+        .split(f.area());
+
+    let mut title_lines = vec![Line::from(Span::styled(
+        format!("SSH Server Monitor - {}", server.server.label()),
+        Style::default().fg(colors.accent).add_modifier(Modifier::BOLD),
+    ))];
+    if app.servers.len() > 1 {
+        title_lines.push(Line::from(""));
+        title_lines.push(server_tabs(app, colors));
+    }
+    let title = Paragraph::new(title_lines).alignment(Alignment::Center);
+    f.render_widget(title, chunks[0]);
+
+    // Error and countdown to the next attempt
+    let seconds_left = server
+        .next_retry_at
+        .map(|at| at.saturating_duration_since(Instant::now()).as_secs())
+        .unwrap_or(0);
+    let progress = if server.reconnect_failed {
+        format!("Gave up after {} attempts. Press r to try again", server.reconnect_attempts)
+    } else {
+        format!(
+            "Reconnecting in {}s (attempt {} of {})",
+            seconds_left, server.reconnect_attempts, app.max_retries
+        )
+    };
+    let status = Paragraph::new(vec![
+        Line::from(Span::styled(
+            format!("Connection lost: {}", server.reconnect_error.as_deref().unwrap_or("unknown error")),
+            colors.alert_style().add_modifier(Modifier::BOLD),
+        )),
+        Line::from(Span::styled(progress, colors.warning_style())),
+    ])
+    .alignment(Alignment::Center)
+    .block(Block::default().borders(Borders::ALL));
+    f.render_widget(status, chunks[1]);
+
+    let mut hint_text = "Press Esc to give up and edit settings".to_string();
+    if app.servers.len() > 1 {
+        hint_text.push_str(", [/] to switch server");
+    }
+    let hint = Paragraph::new(hint_text)
+        .style(Style::default().fg(colors.muted))
+        .alignment(Alignment::Center);
+    f.render_widget(hint, chunks[2]);
+}
+
+fn render_process_list_screen(f: &mut Frame, app: &App, colors: &ThemeColors) {
+    let server = app.server();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(5), Constraint::Length(3)])
+        .split(f.area());
+
+    let username = server.process_list_user.as_deref().unwrap_or_default();
+
+    // Title, replaced for a moment by the outcome of a kill
+    let title = match app.visible_toast() {
+        Some(toast) => Paragraph::new(toast.message.clone()).style(
+            Style::default()
+                .fg(if toast.is_error { colors.alert } else { colors.good })
+                .add_modifier(Modifier::BOLD),
+        ),
+        None => Paragraph::new(format!("Processes for {} on {}", username, server.server.label()))
+            .style(Style::default().fg(colors.accent).add_modifier(Modifier::BOLD)),
+    };
+    f.render_widget(title.block(Block::default().borders(Borders::ALL)), chunks[0]);
+
+    // Process table, with the CPU affinity after the PID when asked for
+    let mut header_cells = vec!["PID", "CPU %", "RSS (MB)", "Command"];
+    let mut widths = vec![
+        Constraint::Length(8),
+        Constraint::Length(8),
+        Constraint::Length(10),
+        Constraint::Min(20),
+    ];
+    if app.show_process_affinity {
+        header_cells.insert(1, "CPUs");
+        widths.insert(1, Constraint::Length(12));
+    }
+    let header = Row::new(header_cells)
+        .style(Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD))
+        .height(1);
+
+    let rows: Vec<Row> = server.processes.iter().map(|process| {
+        let mut cells = vec![
+            process.pid.to_string(),
+            format!("{:.1}", process.cpu_percent),
+            format!("{:.1}", process.ram_mb),
+            process.command.clone(),
+        ];
+        if app.show_process_affinity {
+            cells.insert(1, process.cpu_affinity.clone().unwrap_or_else(|| "N/A".to_string()));
+        }
+        Row::new(cells)
+    }).collect();
+
+    let table_title = if server.processes.is_empty() {
+        "Loading processes...".to_string()
+    } else {
+        format!("{} processes", server.processes.len())
+    };
+
+    let table = Table::new(rows, widths)
+        .header(header)
+        .highlight_style(Style::default().fg(colors.selection_fg).bg(colors.selection_bg))
+        .block(Block::default().borders(Borders::ALL).title(table_title));
+
+    // The table scrolls to keep the selected process on screen
+    let mut state = TableState::default().with_selected(Some(server.selected_process));
+    f.render_stateful_widget(table, chunks[1], &mut state);
+
+    let hint = Paragraph::new("↑/↓: Select process   t: SIGTERM   k: SIGKILL   a: CPU affinity   q/Esc: Back to users")
+        .style(Style::default().fg(colors.muted))
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(hint, chunks[2]);
+
+    if let Some(kill) = &app.pending_kill {
+        render_kill_confirmation(f, kill, colors);
+    }
+}
+
+/// Everything known about one user on a single screen: CPU and RAM gauges,
+/// their CPU history, account details and their busiest processes
+fn render_user_detail_screen(f: &mut Frame, app: &App, user: &UserStats, colors: &ThemeColors) {
+    let server = app.server();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(5),
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Min(5),
+            Constraint::Length(3),
+        ])
+        .split(f.area());
+
+    let title = Paragraph::new(format!("{} on {}", user.username, server.server.label()))
+        .style(Style::default().fg(color_for_user(&user.username)).add_modifier(Modifier::BOLD))
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(title, chunks[0]);
+
+    // CPU against every core the machine has, RAM against its total
+    let gauge_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[1]);
+    let cores = server.system.core_count.max(1) as f64;
+    let cpu_ratio = (user.cpu_percent / (cores * 100.0)).clamp(0.0, 1.0);
+    let cpu_gauge = Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title("CPU"))
+        .gauge_style(Style::default().fg(colors.graph_cpu))
+        .ratio(if cpu_ratio.is_nan() { 0.0 } else { cpu_ratio })
+        .label(format!("{:.1}% of {} cores", user.cpu_percent, cores));
+    f.render_widget(cpu_gauge, gauge_chunks[0]);
+    let ram_total = server.system.ram_total_mb;
+    let ram_ratio = if ram_total > 0.0 { (user.ram_mb / ram_total).clamp(0.0, 1.0) } else { 0.0 };
+    let ram_gauge = Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title(app.config.memory_metric.label()))
+        .gauge_style(Style::default().fg(colors.graph_ram))
+        .ratio(if ram_ratio.is_nan() { 0.0 } else { ram_ratio })
+        .label(format!("{:.0} MB of {:.0} MB", user.ram_mb, ram_total));
+    f.render_widget(ram_gauge, gauge_chunks[1]);
+
+    // Scaled like the table's trend column: one core or the peak, whichever is higher
+    let readings: Vec<u64> = server
+        .cpu_history
+        .get(&user.username)
+        .map(|readings| readings.iter().map(|cpu| cpu.max(0.0).round() as u64).collect())
+        .unwrap_or_default();
+    let sparkline = Sparkline::default()
+        .block(Block::default().borders(Borders::ALL).title(format!("CPU history (last {} polls)", readings.len())))
+        .style(Style::default().fg(colors.graph_cpu))
+        .max(readings.iter().copied().max().unwrap_or(0).max(100))
+        .data(&readings);
+    f.render_widget(sparkline, chunks[2]);
+
+    let label = |text: &'static str| Span::styled(text, Style::default().fg(colors.highlight));
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let last_login = match (&server.last_login_for, &server.last_login) {
+        (Some(username), Some(login)) if *username == user.username => Span::raw(login.clone()),
+        (Some(username), None) if *username == user.username => Span::styled("never (or not in wtmp)", dim),
+        _ => Span::styled("loading...", dim),
+    };
+    let details = vec![
+        Line::from(vec![
+            label("UID: "),
+            Span::raw(user.uid.map_or_else(|| "?".to_string(), |uid| uid.to_string())),
+            label("   Sessions: "),
+            Span::raw(user.session_count.to_string()),
+            label("   Processes: "),
+            Span::raw(user.process_count.to_string()),
+        ]),
+        Line::from(vec![
+            label("Home directory: "),
+            match user.disk_usage_mb {
+                Some(mb) => Span::raw(format!("{:.0} MB", mb)),
+                None => Span::styled("not measured yet", dim),
+            },
+        ]),
+        Line::from(vec![label("Last login: "), last_login]),
+    ];
+    let details = Paragraph::new(details)
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::ALL).title("Account"));
+    f.render_widget(details, chunks[3]);
+
+    // `ps` already lists the busiest first
+    let header = Row::new(["PID", "CPU %", "RSS (MB)", "Command"])
+        .style(Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD))
+        .height(1);
+    let rows: Vec<Row> = server
+        .processes
+        .iter()
+        .take(USER_DETAIL_PROCESSES)
+        .map(|process| {
+            Row::new([
+                process.pid.to_string(),
+                format!("{:.1}", process.cpu_percent),
+                format!("{:.1}", process.ram_mb),
+                process.command.clone(),
+            ])
+        })
+        .collect();
+    let table_title = if server.processes.is_empty() {
+        "Loading processes...".to_string()
+    } else {
+        format!("Top {} processes", rows.len())
+    };
+    let table = Table::new(
+        rows,
+        [Constraint::Length(8), Constraint::Length(8), Constraint::Length(10), Constraint::Min(20)],
+    )
+    .header(header)
+    .block(Block::default().borders(Borders::ALL).title(table_title));
+    f.render_widget(table, chunks[4]);
+
+    let hint = Paragraph::new("←/→: Previous/next user   q/Esc: Back to users")
+        .style(Style::default().fg(colors.muted))
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(hint, chunks[5]);
+}
+
+/// Per-device disk throughput, with the total over time charted below
+fn render_disk_io_screen(f: &mut Frame, app: &App, colors: &ThemeColors) {
+    let server = app.server();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(12),
+            Constraint::Length(3),
+        ])
+        .split(f.area());
+
+    let title = Paragraph::new(format!("Disk I/O on {}", server.server.label()))
+        .style(Style::default().fg(colors.accent).add_modifier(Modifier::BOLD));
+    let title = if app.servers.len() > 1 {
+        title.block(Block::default().borders(Borders::ALL).title(server_tabs(app, colors)))
+    } else {
+        title.block(Block::default().borders(Borders::ALL))
+    };
+    f.render_widget(title, chunks[0]);
+
+    let header = Row::new(vec!["Device", "Read kB/s", "Write kB/s", "Util %"])
+        .style(Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD))
+        .height(1);
+    let rows: Vec<Row> = server
+        .disk_io
+        .iter()
+        .map(|disk| {
+            let util = Cell::from(format!("{:.1}", disk.util_percent));
+            let util = if disk.util_percent >= 90.0 {
+                util.style(colors.alert_style())
+            } else if disk.util_percent >= 60.0 {
+                util.style(colors.warning_style())
+            } else {
+                util
+            };
+            Row::new(vec![
+                Cell::from(disk.device.clone()),
+                Cell::from(format!("{:.1}", disk.read_kbps)),
+                Cell::from(format!("{:.1}", disk.write_kbps)),
+                util,
+            ])
+        })
+        .collect();
+    let table_title = if server.disk_counters.is_empty() {
+        "No block devices found in /proc/diskstats".to_string()
+    } else if server.disk_io.is_empty() {
+        "Waiting for a second reading...".to_string()
+    } else {
+        format!("{} devices", server.disk_io.len())
+    };
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Fill(3),
+            Constraint::Fill(2),
+            Constraint::Fill(2),
+            Constraint::Fill(2),
+        ],
+    )
+    .header(header)
+    .block(Block::default().borders(Borders::ALL).title(table_title));
+    f.render_widget(table, chunks[1]);
+
+    if !server.history.is_empty() {
+        let read_data: Vec<(f64, f64)> = server
+            .history
+            .iter()
+            .map(|h| (chart_x(&h.timestamp), h.disk_read_kbps))
+            .collect();
+        let write_data: Vec<(f64, f64)> = server
+            .history
+            .iter()
+            .map(|h| (chart_x(&h.timestamp), h.disk_write_kbps))
+            .collect();
+        let max_kbps = server
+            .history
+            .iter()
+            .map(|h| h.disk_read_kbps.max(h.disk_write_kbps))
+            .fold(0.0, f64::max)
+            .max(10.0);
+        let time_bounds = history_time_bounds(&server.history);
+
+        let datasets = vec![
+            Dataset::default()
+                .name("Read")
+                .marker(app.graph_style.marker())
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(colors.graph_read))
+                .data(&read_data),
+            Dataset::default()
+                .name("Write")
+                .marker(app.graph_style.marker())
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(colors.graph_write))
+                .data(&write_data),
+        ];
+        let chart = Chart::new(datasets)
+            .block(Block::default().title("Total Disk Throughput Over Time").borders(Borders::ALL))
+            .x_axis(
+                Axis::default()
+                    .title("Time")
+                    .style(Style::default().fg(colors.muted))
+                    .bounds([time_bounds.0, time_bounds.1])
+                    .labels(time_axis_labels(time_bounds)),
+            )
+            .y_axis(
+                Axis::default()
+                    .title("kB/s")
+                    .style(Style::default().fg(colors.muted))
+                    .bounds([0.0, max_kbps * 1.1])
+                    .labels(vec![
+                        Line::from("0"),
+                        Line::from(format!("{:.0}", max_kbps * 0.5)),
+                        Line::from(format!("{:.0}", max_kbps)),
+                    ]),
+            );
+        f.render_widget(chart, chunks[2]);
+    }
 
-    // Hint
-    let hint = Paragraph::new("Press Esc to cancel")
-        .style(Style::default().fg(Color::Gray))
-        .alignment(Alignment::Center);
+    let hint = Paragraph::new("q/Esc: Back to users")
+        .style(Style::default().fg(colors.muted))
+        .block(Block::default().borders(Borders::ALL));
     f.render_widget(hint, chunks[3]);
 }
 
-fn render_monitoring_screen(f: &mut Frame, app: &App) {
+/// The end of the remote log file, newest line at the bottom, with lines
+/// matching the highlight pattern in red
+fn render_log_tail_screen(f: &mut Frame, app: &App, colors: &ThemeColors) {
+    let server = app.server();
     let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(5), Constraint::Length(3)])
+        .split(f.area());
+
+    let title = Paragraph::new(format!("{} on {}", app.log_path, server.server.label()))
+        .style(Style::default().fg(colors.accent).add_modifier(Modifier::BOLD));
+    let title = if app.servers.len() > 1 {
+        title.block(Block::default().borders(Borders::ALL).title(server_tabs(app, colors)))
+    } else {
+        title.block(Block::default().borders(Borders::ALL))
+    };
+    f.render_widget(title, chunks[0]);
+
+    let lines: Vec<Line> = server
+        .log_lines
+        .iter()
+        .map(|line| {
+            if app.log_highlight.is_match(line) {
+                Line::from(Span::styled(line.clone(), colors.alert_style()))
+            } else {
+                Line::from(line.clone())
+            }
+        })
+        .collect();
+    let block_title = match &server.log_error {
+        Some(e) => format!("Failed to read log: {}", e),
+        None if server.log_lines.is_empty() => "Loading...".to_string(),
+        None => format!("Last {} lines", server.log_lines.len()),
+    };
+    // Lines don't wrap, so the newest one sits on the bottom row until scrolled back
+    let visible = chunks[1].height.saturating_sub(2) as usize;
+    let top = lines.len().saturating_sub(visible).saturating_sub(server.log_scroll);
+    let log = Paragraph::new(lines)
+        .scroll((top as u16, 0))
+        .block(Block::default().borders(Borders::ALL).title(block_title));
+    f.render_widget(log, chunks[1]);
+
+    let hint = Paragraph::new("↑/↓ PgUp/PgDn: Scroll  q/Esc: Back to users")
+        .style(Style::default().fg(colors.muted))
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(hint, chunks[2]);
+}
+
+/// "Kill PID 1234 (proc_name)? [y/N]" box over the process list
+fn render_kill_confirmation(f: &mut Frame, kill: &PendingKill, colors: &ThemeColors) {
+    let popup = centered_rect(60, 100, f.area());
+    let area = Rect {
+        y: popup.y + popup.height.saturating_sub(5) / 2,
+        height: 5.min(popup.height),
+        ..popup
+    };
+    f.render_widget(Clear, area);
+    let text = vec![
+        Line::from(format!("Kill PID {} ({})? [y/N]", kill.pid, kill.command)),
+        Line::from(Span::styled(
+            format!("Sends {}", kill.signal.name()),
+            Style::default().fg(colors.muted),
+        )),
+    ];
+    let dialog = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD))
+        .block(Block::default().borders(Borders::ALL).title("Confirm"));
+    f.render_widget(dialog, area);
+}
+
+/// Title bar, middle section and charts of the monitoring screen
+fn monitoring_chunks(area: Rect) -> std::rc::Rc<[Rect]> {
+    Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(3),
             Constraint::Min(10),
+            Constraint::Length(3),
             Constraint::Length(12),
         ])
-        .split(f.area());
-
-    // Title
-    let title = Paragraph::new("SSH Server Monitor - User CPU & RAM Usage")
-        .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
-        .block(Block::default().borders(Borders::ALL));
-    f.render_widget(title, chunks[0]);
+        .split(area)
+}
 
-    // Middle section: split into table and current stats
-    let middle_chunks = Layout::default()
+/// User table on the left of the middle section, current stats on the right
+fn middle_chunks(area: Rect) -> std::rc::Rc<[Rect]> {
+    Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
-        .split(chunks[1]);
+        .split(monitoring_chunks(area)[1])
+}
 
-    // User table
-    let cpu_header = if app.sort_by == SortBy::Cpu {
-        format!("CPU % ▼")
+/// Where the filter bar (when shown) and the user table are drawn. Shared
+/// with mouse handling so clicks line up with the rendered rows.
+fn user_table_areas(app: &App, area: Rect) -> (Option<Rect>, Rect) {
+    let table_area = middle_chunks(area)[0];
+    if app.filter_editing || !app.user_filter.is_empty() {
+        let table_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)])
+            .split(table_area);
+        (Some(table_chunks[0]), table_chunks[1])
     } else {
-        "CPU %".to_string()
+        (None, table_area)
+    }
+}
+
+fn render_monitoring_screen(f: &mut Frame, app: &App, colors: &ThemeColors) {
+    let server = app.server();
+    let chunks = monitoring_chunks(f.area());
+
+    // Title with heartbeat dot that flashes whenever new data arrives. Once
+    // connected it names the server the way the server names itself.
+    let title_text = match server.host_info {
+        Some(ref info) => format!("{} ({}) - User CPU & RAM Usage", info.hostname, info.os),
+        None => format!("SSH Server Monitor - {}", server.server.label()),
     };
-    let ram_header = if app.sort_by == SortBy::Ram {
-        format!("RAM (MB) ▼")
+    let mut title_spans = vec![
+        Span::styled("● ", server.heartbeat_style(app.frame, app.ui_poll_ms, colors)),
+        Span::styled(title_text, Style::default().fg(colors.accent).add_modifier(Modifier::BOLD)),
+    ];
+    if let Some(ms) = server.last_poll_duration_ms {
+        title_spans.push(Span::styled(
+            format!("  ⚡ {}ms", ms),
+            Style::default().fg(poll_duration_color(ms, colors)),
+        ));
+    }
+    if let Some(baseline_at) = server.baseline_at {
+        title_spans.push(Span::styled(
+            format!("  Δ since {}", baseline_at.format("%H:%M:%S")),
+            Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if app.paused {
+        title_spans.push(Span::styled(
+            "  ⏸ PAUSED",
+            colors.warning_style().add_modifier(Modifier::BOLD),
+        ));
+    }
+    let title = Paragraph::new(Line::from(title_spans));
+    let title = if app.servers.len() > 1 {
+        title.block(Block::default().borders(Borders::ALL).title(server_tabs(app, colors)))
     } else {
-        "RAM (MB)".to_string()
+        title.block(Block::default().borders(Borders::ALL))
     };
-    
-    let header = Row::new(vec!["User", &cpu_header, &ram_header, "Last Updated"])
-        .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+
+    // Load gauge on the right of the title bar: 1 minute load relative to core count
+    if app.show_load_gauge && server.system.core_count > 0 {
+        let title_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(0), Constraint::Length(44)])
+            .split(chunks[0]);
+        f.render_widget(title, title_chunks[0]);
+
+        let [load_1, _, load_15] = server.system.load_average;
+        let ratio = load_1 / server.system.core_count as f64;
+        let color = if ratio < 0.7 {
+            colors.good
+        } else if ratio <= 1.0 {
+            colors.warning
+        } else {
+            colors.alert
+        };
+        let trend = if load_1 > load_15 { "↑" } else if load_1 < load_15 { "↓" } else { "→" };
+
+        let gauge = Gauge::default()
+            .block(Block::default().borders(Borders::ALL).title("Load / Cores"))
+            .gauge_style(Style::default().fg(color))
+            .ratio(ratio.clamp(0.0, 1.0))
+            .label(format!(
+                "{:.2}/{} ({:.0}%) 15m {:.2}{}",
+                load_1,
+                server.system.core_count,
+                ratio * 100.0,
+                load_15,
+                trend
+            ));
+        f.render_widget(gauge, title_chunks[1]);
+    } else {
+        f.render_widget(title, chunks[0]);
+    }
+
+    // Middle section: split into table and current stats
+    let middle_chunks = middle_chunks(f.area());
+
+    // User table
+    let columns = app.visible_columns();
+
+    let cursor = app.cursor_column();
+    let header_cells = columns.iter().map(|column| {
+        let mut title = column.title(app.config.memory_metric);
+        // Next to the trend, the plain figure is the current reading
+        if *column == Column::Cpu && app.show_cpu_trend_column {
+            title = "CPU now".to_string();
+        }
+        if column.sort_by() == Some(app.sort_by.clone()) {
+            title.push_str(" ▼");
+        }
+        if *column == cursor {
+            Cell::from(title).style(Style::default().add_modifier(Modifier::REVERSED))
+        } else {
+            Cell::from(title)
+        }
+    });
+
+    let header = Row::new(header_cells)
+        .style(Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD))
         .height(1);
 
-    let rows: Vec<Row> = app.users.iter().enumerate().map(|(i, user)| {
-        let style = if i == app.selected_user {
-            Style::default().fg(Color::Black).bg(Color::LightCyan)
+    let visible_users = app.visible_users();
+    let disk_stale = server.disk_usage_is_stale(app.disk_interval_secs);
+    let now = Local::now();
+    let rows: Vec<Row> = visible_users.iter().enumerate().map(|(i, user)| {
+        let style = if i == server.selected_user {
+            Style::default().fg(colors.selection_fg).bg(colors.selection_bg)
         } else {
             Style::default()
         };
-        
-        Row::new(vec![
-            user.username.clone(),
-            format!("{:.2}", user.cpu_percent),
-            format!("{:.2}", user.ram_mb),
-            user.last_updated.format("%H:%M:%S").to_string(),
-        ])
-        .style(style)
+        let previous = server.prev_stats.get(&user.username);
+        let baseline = server.baseline.as_ref();
+
+        let cells = columns.iter().map(|column| match column {
+            Column::User => {
+                if i == server.selected_user {
+                    Cell::from(user.username.clone())
+                } else if user.session_count > 0 {
+                    // Logged in right now
+                    Cell::from(user.username.clone()).style(Style::default().fg(colors.logged_in))
+                } else {
+                    Cell::from(user.username.clone()).style(Style::default().fg(color_for_user(&user.username)))
+                }
+            }
+            Column::Cpu if baseline.is_some() => baseline_cell(
+                user.cpu_percent,
+                baseline.and_then(|b| b.get(&user.username)).map(|b| b.cpu_percent),
+                "%",
+                colors,
+            ),
+            Column::Cpu => {
+                let arrow = previous.map_or("", |p| delta_indicator(user.cpu_percent, p.cpu_percent, CPU_DELTA_THRESHOLD));
+                cell_with_delta(format!("{:.2}", user.cpu_percent), arrow, colors)
+            }
+            Column::CpuUser => Cell::from(format!("{:.2}", user.cpu_user_percent)),
+            Column::CpuSys => Cell::from(format!("{:.2}", user.cpu_sys_percent)),
+            Column::CpuTrend => {
+                Cell::from(server.cpu_sparkline(&user.username)).style(Style::default().fg(colors.accent))
+            }
+            Column::Ram if baseline.is_some() => baseline_cell(
+                user.ram_mb,
+                baseline.and_then(|b| b.get(&user.username)).map(|b| b.ram_mb),
+                " MB",
+                colors,
+            ),
+            Column::Ram => {
+                let arrow = previous.map_or("", |p| delta_indicator(user.ram_mb, p.ram_mb, RAM_DELTA_THRESHOLD_MB));
+                cell_with_delta(format!("{:.2}", user.ram_mb), arrow, colors)
+            }
+            Column::RamPercent => match server.ram_percent(user) {
+                Some(percent) => Cell::from(format!("{:.1}", percent)),
+                None => Cell::from("N/A"),
+            },
+            Column::Procs => Cell::from(user.process_count.to_string()),
+            Column::Network => Cell::from(format!("↑{:.1} ↓{:.1}", user.network_tx_kbps, user.network_rx_kbps)),
+            Column::Sessions => {
+                if user.session_count == 0 {
+                    Cell::from("0").style(Style::default().add_modifier(Modifier::DIM))
+                } else {
+                    Cell::from(user.session_count.to_string())
+                }
+            }
+            Column::Disk => {
+                let text = match user.disk_usage_mb {
+                    Some(mb) => format!("{:.0}", mb),
+                    None => "-".to_string(),
+                };
+                if user.disk_usage_mb.is_none() || disk_stale {
+                    Cell::from(text).style(Style::default().add_modifier(Modifier::DIM))
+                } else {
+                    Cell::from(text)
+                }
+            }
+            Column::Swap => Cell::from(format!("{:.2}", user.swap_mb)),
+            Column::IoWait => Cell::from(format!("{:.1}", user.io_wait_percent)),
+            // Root stands out in every theme; system accounts fade back
+            Column::Uid => match user.uid {
+                Some(0) => Cell::from("0").style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+                Some(uid) if uid < 1000 => {
+                    Cell::from(uid.to_string()).style(Style::default().add_modifier(Modifier::DIM))
+                }
+                Some(uid) => Cell::from(uid.to_string()),
+                None => Cell::from("?").style(Style::default().add_modifier(Modifier::DIM)),
+            },
+            Column::TopProcess => Cell::from(user.top_process.chars().take(TOP_PROCESS_WIDTH).collect::<String>()),
+            Column::LastUpdated => {
+                // Rows fall behind when the connection is slow or the worker is stuck
+                let staleness_secs = (now - user.last_updated).num_seconds().max(0);
+                let text = format!("{} ({}s)", user.last_updated.format("%H:%M:%S"), staleness_secs);
+                if i == server.selected_user {
+                    Cell::from(text)
+                } else {
+                    Cell::from(text).style(Style::default().fg(staleness_color(staleness_secs, colors)))
+                }
+            }
+        });
+
+        Row::new(cells).style(style)
     }).collect();
 
-    let table = Table::new(
-        rows,
-        [
-            Constraint::Percentage(30),
-            Constraint::Percentage(20),
-            Constraint::Percentage(25),
-            Constraint::Percentage(25),
-        ],
-    )
-    .header(header)
-    .block(Block::default().borders(Borders::ALL).title("Users"));
+    let widths: Vec<Constraint> = columns.iter().map(|column| column.width()).collect();
+
+    let mut table_title = if app.user_filter.is_empty() {
+        "Users".to_string()
+    } else {
+        format!("Users (filter \"{}\": {}/{})", app.user_filter, visible_users.len(), server.users.len())
+    };
+    if !server.excluded_users.is_empty() {
+        table_title.push_str(&format!(" [{} excluded, Ctrl+E to show]", server.excluded_users.len()));
+    }
+
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(table_title));
 
-    f.render_widget(table, middle_chunks[0]);
+    // Filter bar above the table while a filter is being typed or applied
+    let (filter_area, table_area) = user_table_areas(app, f.area());
+    if let Some(filter_area) = filter_area {
+        let cursor = if app.filter_editing { "_" } else { "" };
+        let filter_style = if app.filter_editing {
+            Style::default().fg(colors.highlight).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        let filter = Paragraph::new(format!("/{}{}", app.user_filter, cursor))
+            .style(filter_style)
+            .block(Block::default().borders(Borders::ALL).title("Filter (Esc to clear)"));
+        f.render_widget(filter, filter_area);
+    }
+    // Rendered with the selection so the table scrolls to keep it in view
+    let mut table_state = TableState::default().with_selected(Some(server.selected_user));
+    f.render_stateful_widget(table, table_area, &mut table_state);
+
+    // Scrollbar over the right border once the users don't all fit, below
+    // the header row
+    let visible_rows = table_area.height.saturating_sub(3) as usize;
+    if visible_users.len() > visible_rows {
+        let mut scrollbar_state = ScrollbarState::new(visible_users.len())
+            .viewport_content_length(visible_rows)
+            .position(server.selected_user);
+        let scrollbar_area = Rect {
+            y: table_area.y + 1,
+            height: table_area.height.saturating_sub(1),
+            ..table_area
+        };
+        f.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight).begin_symbol(None).end_symbol(None),
+            scrollbar_area.inner(Margin { horizontal: 0, vertical: 1 }),
+            &mut scrollbar_state,
+        );
+    }
 
     // Current stats summary
-    let cpu_total: f64 = app.users.iter().map(|u| u.cpu_percent).sum();
-    let ram_total: f64 = app.users.iter().map(|u| u.ram_mb).sum();
+    let cpu_total: f64 = server.users.iter().map(|u| u.cpu_percent).sum();
+    let ram_total: f64 = server.users.iter().map(|u| u.ram_mb).sum();
+    let swap_total: f64 = server.users.iter().map(|u| u.swap_mb).sum();
     
-    let stats_text = vec![
+    let mut stats_text = vec![
+        Line::from(vec![
+            Span::styled("Host: ", Style::default().fg(colors.highlight)),
+            Span::raw(server.server.label()),
+        ]),
+        Line::from(vec![
+            Span::styled("Uptime: ", Style::default().fg(colors.highlight)),
+            Span::raw(server.uptime().unwrap_or_else(|| "unknown".to_string())),
+        ]),
+        Line::from(vec![
+            Span::styled("Since: ", Style::default().fg(colors.highlight)),
+            Span::raw(
+                server
+                    .boot_time
+                    .map(|boot| boot.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "unknown".to_string()),
+            ),
+        ]),
+        Line::from(""),
         Line::from(vec![
-            Span::styled("Total Users: ", Style::default().fg(Color::Yellow)),
-            Span::raw(format!("{}", app.users.len())),
+            Span::styled("Total Users: ", Style::default().fg(colors.highlight)),
+            Span::raw(format!("{}", server.users.len())),
         ]),
         Line::from(""),
         Line::from(vec![
-            Span::styled("Total CPU: ", Style::default().fg(Color::Yellow)),
+            Span::styled("Total CPU: ", Style::default().fg(colors.highlight)),
             Span::raw(format!("{:.2}%", cpu_total)),
         ]),
         Line::from(""),
         Line::from(vec![
-            Span::styled("Total RAM: ", Style::default().fg(Color::Yellow)),
+            Span::styled("Total RAM: ", Style::default().fg(colors.highlight)),
             Span::raw(format!("{:.2} MB", ram_total)),
         ]),
         Line::from(""),
+        Line::from(vec![
+            Span::styled("Total Swap: ", Style::default().fg(colors.highlight)),
+            Span::raw(format!("{:.2} MB", swap_total)),
+        ]),
         Line::from(""),
+    ];
+
+    if server.only_own_processes_visible() {
+        stats_text.push(Line::from(Span::styled(
+            "Only your own processes are visible - elevated access may be required",
+            colors.warning_style(),
+        )));
+        stats_text.push(Line::from(""));
+    }
+
+    // How the session was set up, with the negotiated cipher flagged when it
+    // is deprecated or slow
+    if let Some(ref info) = server.connection_info {
+        let algorithms = &info.algorithms;
+        stats_text.push(Line::from(Span::styled(
+            "Connection",
+            Style::default().fg(colors.accent).add_modifier(Modifier::BOLD),
+        )));
+        let route = match &info.jump {
+            Some(jump) => format!("{} via {}", format_address(&info.host, info.port), jump),
+            None => format_address(&info.host, info.port),
+        };
+        stats_text.push(Line::from(vec![
+            Span::styled("Host: ", Style::default().fg(colors.highlight)),
+            Span::raw(route),
+        ]));
+        stats_text.push(Line::from(vec![
+            Span::styled("Auth: ", Style::default().fg(colors.highlight)),
+            Span::raw(info.auth),
+        ]));
+        if let Some(ms) = info.latency_ms {
+            stats_text.push(Line::from(vec![
+                Span::styled("Latency: ", Style::default().fg(colors.highlight)),
+                Span::raw(format!("{} ms", ms)),
+            ]));
+        }
+        if let Some(ref version) = info.server_version {
+            stats_text.push(Line::from(vec![
+                Span::styled("Server: ", Style::default().fg(colors.highlight)),
+                Span::raw(version.clone()),
+            ]));
+        }
+
+        let cipher_style = if Algorithms::is_weak(&algorithms.cipher) {
+            colors.alert_style().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(colors.good)
+        };
+        stats_text.push(Line::from(vec![
+            Span::styled("Cipher: ", Style::default().fg(colors.highlight)),
+            Span::styled(algorithms.cipher.clone(), cipher_style),
+        ]));
+        stats_text.push(Line::from(vec![
+            Span::styled("Transferred: ", Style::default().fg(colors.highlight)),
+            Span::raw(format_bytes(server.traffic.total() as f64)),
+        ]));
+
+        let weak = algorithms.weak();
+        if app.config.warn_weak_algorithms && !weak.is_empty() {
+            stats_text.push(Line::from(Span::styled(
+                format!("⚠ Weak algorithms: {}", weak.join(", ")),
+                colors.alert_style(),
+            )));
+        }
+    }
+
+    // Users currently over an alert threshold
+    let label = server.server.label();
+    for alert in app.alerts.iter().filter(|alert| alert.server == label) {
+        stats_text.push(Line::from(Span::styled(
+            format!(
+                "⚠ {} {} (since {})",
+                alert.username,
+                alert.kind.message(alert.value, alert.threshold),
+                alert.triggered_at.format("%H:%M:%S")
+            ),
+            colors.alert_style().add_modifier(Modifier::BOLD | Modifier::SLOW_BLINK),
+        )));
+    }
+
+    let refresh_status = if app.paused {
+        format!("{}s (paused)", app.refresh_interval_secs)
+    } else if server.refresh_requested {
+        format!("{}s (Refreshing...)", app.refresh_interval_secs)
+    } else {
+        format!(
+            "{}s (next in {}s)",
+            app.refresh_interval_secs,
+            app.seconds_until_refresh()
+        )
+    };
+    stats_text.push(Line::from(vec![
+        Span::styled("Refresh: ", Style::default().fg(colors.highlight)),
+        Span::raw(refresh_status),
+    ]));
+    if app.refresh_interval_secs < WAN_WARNING_INTERVAL_SECS && !server.server.is_local_network() {
+        stats_text.push(Line::from(Span::styled(
+            format!(
+                "Intervals under {}s add noticeable load over a remote link",
+                WAN_WARNING_INTERVAL_SECS
+            ),
+            colors.warning_style(),
+        )));
+    }
+
+    if app.show_traffic {
+        stats_text.push(Line::from(vec![
+            Span::styled("Monitor traffic: ", Style::default().fg(colors.highlight)),
+            Span::raw(format!(
+                "{} ({}/h)",
+                format_bytes(server.traffic.total() as f64),
+                format_bytes(server.traffic_per_hour())
+            )),
+        ]));
+    }
+
+    if let Some(toast) = app.visible_toast() {
+        stats_text.push(Line::from(""));
+        stats_text.push(Line::from(Span::styled(
+            toast.message.clone(),
+            Style::default()
+                .fg(if toast.is_error { colors.alert } else { colors.good })
+                .add_modifier(Modifier::BOLD),
+        )));
+    }
+
+    let summary_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(5),
+            Constraint::Min(0),
+            Constraint::Length(PROCESS_PREVIEW_LEN as u16 + 3),
+            Constraint::Length(3),
+        ])
+        .split(middle_chunks[1]);
+
+    // Machine-wide figures
+    let system = &server.system;
+    let gib = |mb: f64| mb / 1024.0;
+    let system_text = vec![
+        Line::from(vec![
+            Span::styled("Load: ", Style::default().fg(colors.highlight)),
+            Span::raw(format!(
+                "{:.2} (1m)  {:.2} (5m)  {:.2} (15m)",
+                system.load_average[0], system.load_average[1], system.load_average[2]
+            )),
+        ]),
         Line::from(vec![
-            Span::styled("Controls:", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Span::styled("Cores: ", Style::default().fg(colors.highlight)),
+            Span::raw(system.core_count.to_string()),
+        ]),
+        Line::from(vec![
+            Span::styled("RAM: ", Style::default().fg(colors.highlight)),
+            Span::raw(format!(
+                "{:.1} GiB total, {:.1} used, {:.1} free",
+                gib(system.ram_total_mb),
+                gib(system.ram_used_mb),
+                gib(system.ram_free_mb)
+            )),
         ]),
-        Line::from("↑/↓: Select user"),
-        Line::from("c: Sort by CPU"),
-        Line::from("r: Sort by RAM"),
-        Line::from("q/Esc: Back"),
     ];
+    let system_panel = Paragraph::new(system_text)
+        .block(Block::default().borders(Borders::ALL).title("System"));
+    f.render_widget(system_panel, summary_chunks[0]);
 
     let stats = Paragraph::new(stats_text)
-        .block(Block::default().borders(Borders::ALL).title("Summary"));
-    f.render_widget(stats, middle_chunks[1]);
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::ALL).title("Summary (? for help)"));
+    f.render_widget(stats, summary_chunks[1]);
+
+    // Full command line of the selected user's top process, scrolled horizontally
+    // so long argument lists are cut at the panel edge instead of wrapping
+    if let Some(user) = app.user_at_selection() {
+        let command = Paragraph::new(user.top_command.as_str())
+            .scroll((0, server.command_scroll.min(u16::MAX as usize) as u16))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Top Process - {}", user.username)),
+            );
+        f.render_widget(command, summary_chunks[3]);
+
+        render_process_preview(f, server, &user.username, summary_chunks[2], colors);
+    } else {
+        for (area, title) in [(summary_chunks[2], "Processes"), (summary_chunks[3], "Top Process")] {
+            let placeholder = Paragraph::new("No user selected")
+                .style(Style::default().add_modifier(Modifier::DIM))
+                .block(Block::default().borders(Borders::ALL).title(title));
+            f.render_widget(placeholder, area);
+        }
+    }
 
-    // Historical graphs
+    // Machine-wide CPU from /proc/stat, which unlike the per-user sums can't go over 100%
+    let system_cpu = server.system_cpu_percent.unwrap_or(0.0).clamp(0.0, 100.0);
+    let system_cpu_color = if system_cpu < 50.0 {
+        colors.good
+    } else if system_cpu < 80.0 {
+        colors.warning
+    } else {
+        colors.alert
+    };
+    let system_cpu_label = match server.system_cpu_percent {
+        Some(_) => format!("{:.1}%", system_cpu),
+        None => "waiting for a second reading...".to_string(),
+    };
+    let system_cpu_gauge = Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title("System CPU"))
+        .gauge_style(Style::default().fg(system_cpu_color))
+        .ratio(system_cpu / 100.0)
+        .label(system_cpu_label);
+    f.render_widget(system_cpu_gauge, chunks[2]);
+
+    // Historical graphs, with a fourth panel for swap once any is in use
+    let show_swap_chart = server.history.iter().any(|h| h.swap_total > 0.0);
+    let graph_constraints = if show_swap_chart {
+        vec![Constraint::Percentage(25); 4]
+    } else {
+        vec![
+            Constraint::Percentage(34),
+            Constraint::Percentage(33),
+            Constraint::Percentage(33),
+        ]
+    };
     let graph_chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(chunks[2]);
+        .constraints(graph_constraints)
+        .split(chunks[3]);
+
+    // The x axis runs from the oldest to the newest sample
+    let time_bounds = history_time_bounds(&server.history);
+    let time_labels = time_axis_labels(time_bounds);
 
-    // CPU graph with total only
-    if !app.history.is_empty() {
-        // Total CPU data
-        let cpu_total_data: Vec<(f64, f64)> = app
+    // CPU graph with the total, or user time stacked under user + kernel time
+    // while the split columns are shown
+    if !server.history.is_empty() {
+        let cpu_total_data: Vec<(f64, f64)> = server
             .history
             .iter()
-            .enumerate()
-            .map(|(i, h)| (i as f64, h.cpu_total))
+            .map(|h| (chart_x(&h.timestamp), h.cpu_total))
+            .collect();
+        let cpu_user_data: Vec<(f64, f64)> = server
+            .history
+            .iter()
+            .map(|h| (chart_x(&h.timestamp), h.cpu_user_total))
+            .collect();
+        let cpu_stacked_data: Vec<(f64, f64)> = server
+            .history
+            .iter()
+            .map(|h| (chart_x(&h.timestamp), h.cpu_user_total + h.cpu_sys_total))
             .collect();
 
-        let max_cpu = app
+        let cpu_peak = server
             .history
             .iter()
-            .map(|h| h.cpu_total)
-            .fold(0.0, f64::max)
-            .max(10.0);
+            .map(|h| {
+                if app.show_cpu_split_columns {
+                    h.cpu_user_total + h.cpu_sys_total
+                } else {
+                    h.cpu_total
+                }
+            })
+            .fold(0.0, f64::max);
+        let max_cpu = cpu_peak.max(10.0);
+        let cpu_peak_data = highwater_points(time_bounds, cpu_peak);
 
-        let datasets = vec![
-            Dataset::default()
-                .name("Total")
-                .marker(symbols::Marker::Braille)
-                .graph_type(GraphType::Line)
-                .style(Style::default().fg(Color::Green))
-                .data(&cpu_total_data)
-        ];
+        let mut datasets = if app.show_cpu_split_columns {
+            vec![
+                Dataset::default()
+                    .name("User")
+                    .marker(app.graph_style.marker())
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(colors.graph_cpu))
+                    .data(&cpu_user_data),
+                Dataset::default()
+                    .name("User+Sys")
+                    .marker(app.graph_style.marker())
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(colors.graph_cpu_sys))
+                    .data(&cpu_stacked_data),
+            ]
+        } else {
+            vec![
+                Dataset::default()
+                    .name("Total")
+                    .marker(app.graph_style.marker())
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(colors.graph_cpu))
+                    .data(&cpu_total_data),
+            ]
+        };
+        if app.show_highwater {
+            datasets.push(highwater_dataset(&cpu_peak_data, colors));
+        }
 
         let cpu_chart = Chart::new(datasets)
             .block(Block::default().title("CPU Usage Over Time").borders(Borders::ALL))
             .x_axis(
                 Axis::default()
                     .title("Time")
-                    .style(Style::default().fg(Color::Gray))
-                    .bounds([0.0, MAX_HISTORY as f64]),
+                    .style(Style::default().fg(colors.muted))
+                    .bounds([time_bounds.0, time_bounds.1])
+                    .labels(time_labels.clone()),
             )
             .y_axis(
                 Axis::default()
                     .title("CPU %")
-                    .style(Style::default().fg(Color::Gray))
+                    .style(Style::default().fg(colors.muted))
                     .bounds([0.0, max_cpu * 1.1])
                     .labels(vec![
                         Line::from("0"),
@@ -614,36 +4572,36 @@ fn render_monitoring_screen(f: &mut Frame, app: &App) {
     }
 
     // RAM graph with total only
-    if !app.history.is_empty() {
+    if !server.history.is_empty() {
         // Total RAM data
-        let ram_total_data: Vec<(f64, f64)> = app
+        let ram_total_data: Vec<(f64, f64)> = server
             .history
             .iter()
-            .enumerate()
-            .map(|(i, h)| (i as f64, h.ram_total))
+            .map(|h| (chart_x(&h.timestamp), h.ram_total))
             .collect();
 
-        let max_ram = if app.total_ram_mb > 0.0 {
-            app.total_ram_mb
+        let ram_peak = server.history.iter().map(|h| h.ram_total).fold(0.0, f64::max);
+        let max_ram = if server.system.ram_total_mb > 0.0 {
+            server.system.ram_total_mb
         } else {
-            app.history
-                .iter()
-                .map(|h| h.ram_total)
-                .fold(0.0, f64::max)
-                .max(100.0)
+            ram_peak.max(100.0)
         };
+        let ram_peak_data = highwater_points(time_bounds, ram_peak);
 
-        let datasets = vec![
+        let mut datasets = vec![
             Dataset::default()
                 .name("Total Used")
-                .marker(symbols::Marker::Braille)
+                .marker(app.graph_style.marker())
                 .graph_type(GraphType::Line)
-                .style(Style::default().fg(Color::Magenta))
+                .style(Style::default().fg(colors.graph_ram))
                 .data(&ram_total_data)
         ];
+        if app.show_highwater {
+            datasets.push(highwater_dataset(&ram_peak_data, colors));
+        }
 
-        let ram_title = if app.total_ram_mb > 0.0 {
-            format!("RAM Usage Over Time - Max: {:.0} MB", app.total_ram_mb)
+        let ram_title = if server.system.ram_total_mb > 0.0 {
+            format!("RAM Usage Over Time - Max: {:.0} MB", server.system.ram_total_mb)
         } else {
             "RAM Usage Over Time".to_string()
         };
@@ -653,13 +4611,14 @@ fn render_monitoring_screen(f: &mut Frame, app: &App) {
             .x_axis(
                 Axis::default()
                     .title("Time")
-                    .style(Style::default().fg(Color::Gray))
-                    .bounds([0.0, MAX_HISTORY as f64]),
+                    .style(Style::default().fg(colors.muted))
+                    .bounds([time_bounds.0, time_bounds.1])
+                    .labels(time_labels.clone()),
             )
             .y_axis(
                 Axis::default()
                     .title("RAM (MB)")
-                    .style(Style::default().fg(Color::Gray))
+                    .style(Style::default().fg(colors.muted))
                     .bounds([0.0, max_ram * 1.1])
                     .labels(vec![
                         Line::from("0"),
@@ -671,4 +4630,265 @@ fn render_monitoring_screen(f: &mut Frame, app: &App) {
             );
         f.render_widget(ram_chart, graph_chunks[1]);
     }
+
+    // Load graph, scaled to the core count so a busy but healthy machine
+    // doesn't look pegged
+    if !server.history.is_empty() {
+        let load_data: Vec<(f64, f64)> = server
+            .history
+            .iter()
+            .map(|h| (chart_x(&h.timestamp), h.load_avg_1min))
+            .collect();
+
+        let max_seen = server
+            .history
+            .iter()
+            .map(|h| h.load_avg_1min)
+            .fold(0.0, f64::max);
+        let max_load = (server.system.core_count as f64).max(max_seen * 1.1).max(1.0);
+        let load_peak_data = highwater_points(time_bounds, max_seen);
+
+        let mut datasets = vec![
+            Dataset::default()
+                .name("1m")
+                .marker(app.graph_style.marker())
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(colors.graph_load))
+                .data(&load_data)
+        ];
+        if app.show_highwater {
+            datasets.push(highwater_dataset(&load_peak_data, colors));
+        }
+
+        let load_chart = Chart::new(datasets)
+            .block(Block::default().title("Load Average").borders(Borders::ALL))
+            .x_axis(
+                Axis::default()
+                    .title("Time")
+                    .style(Style::default().fg(colors.muted))
+                    .bounds([time_bounds.0, time_bounds.1])
+                    .labels(time_labels.clone()),
+            )
+            .y_axis(
+                Axis::default()
+                    .title("Load")
+                    .style(Style::default().fg(colors.muted))
+                    .bounds([0.0, max_load])
+                    .labels(vec![
+                        Line::from("0"),
+                        Line::from(format!("{:.1}", max_load * 0.5)),
+                        Line::from(format!("{:.1}", max_load)),
+                    ]),
+            );
+        f.render_widget(load_chart, graph_chunks[2]);
+    }
+
+    // Swap graph
+    if show_swap_chart {
+        let swap_total_data: Vec<(f64, f64)> = server
+            .history
+            .iter()
+            .map(|h| (chart_x(&h.timestamp), h.swap_total))
+            .collect();
+
+        let swap_peak = server.history.iter().map(|h| h.swap_total).fold(0.0, f64::max);
+        let max_swap = swap_peak.max(10.0);
+        let swap_peak_data = highwater_points(time_bounds, swap_peak);
+
+        let mut datasets = vec![
+            Dataset::default()
+                .name("Total Swap")
+                .marker(app.graph_style.marker())
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(colors.graph_swap))
+                .data(&swap_total_data)
+        ];
+        if app.show_highwater {
+            datasets.push(highwater_dataset(&swap_peak_data, colors));
+        }
+
+        let swap_chart = Chart::new(datasets)
+            .block(Block::default().title("Swap Usage Over Time").borders(Borders::ALL))
+            .x_axis(
+                Axis::default()
+                    .title("Time")
+                    .style(Style::default().fg(colors.muted))
+                    .bounds([time_bounds.0, time_bounds.1])
+                    .labels(time_labels.clone()),
+            )
+            .y_axis(
+                Axis::default()
+                    .title("Swap (MB)")
+                    .style(Style::default().fg(colors.muted))
+                    .bounds([0.0, max_swap * 1.1])
+                    .labels(vec![
+                        Line::from("0"),
+                        Line::from(format!("{:.0}", max_swap * 0.25)),
+                        Line::from(format!("{:.0}", max_swap * 0.5)),
+                        Line::from(format!("{:.0}", max_swap * 0.75)),
+                        Line::from(format!("{:.0}", max_swap)),
+                    ]),
+            );
+        f.render_widget(swap_chart, graph_chunks[3]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app_with_users(users: Vec<UserStats>) -> App {
+        let mut app = App::new();
+        app.start_servers(vec![ConfigScreen::new().server_config()]);
+        app.server_mut().users = users;
+        app
+    }
+
+    fn usernames(app: &App) -> Vec<&str> {
+        app.server().users.iter().map(|u| u.username.as_str()).collect()
+    }
+
+    #[test]
+    fn sorts_by_cpu_descending() {
+        let mut app = app_with_users(vec![
+            UserStats::test_fixture("alice", 10.0, 300.0),
+            UserStats::test_fixture("bob", 90.0, 100.0),
+            UserStats::test_fixture("carol", 50.0, 200.0),
+        ]);
+        app.set_sort(SortBy::Cpu);
+        assert_eq!(usernames(&app), ["bob", "carol", "alice"]);
+    }
+
+    #[test]
+    fn sorts_by_ram_descending() {
+        let mut app = app_with_users(vec![
+            UserStats::test_fixture("alice", 10.0, 300.0),
+            UserStats::test_fixture("bob", 90.0, 100.0),
+            UserStats::test_fixture("carol", 50.0, 200.0),
+        ]);
+        app.set_sort(SortBy::Ram);
+        assert_eq!(usernames(&app), ["alice", "carol", "bob"]);
+    }
+
+    #[test]
+    fn nan_sorts_last() {
+        let mut app = app_with_users(vec![
+            UserStats::test_fixture("broken", f64::NAN, f64::NAN),
+            UserStats::test_fixture("idle", 0.0, 0.0),
+            UserStats::test_fixture("busy", 75.0, 500.0),
+        ]);
+        app.set_sort(SortBy::Cpu);
+        assert_eq!(usernames(&app), ["busy", "idle", "broken"]);
+        app.set_sort(SortBy::Ram);
+        assert_eq!(usernames(&app), ["busy", "idle", "broken"]);
+    }
+
+    #[test]
+    fn disk_sorts_nan_then_unknown_last() {
+        let mut users = vec![
+            UserStats::test_fixture("unknown", 0.0, 0.0),
+            UserStats::test_fixture("broken", 0.0, 0.0),
+            UserStats::test_fixture("small", 0.0, 0.0),
+            UserStats::test_fixture("large", 0.0, 0.0),
+        ];
+        users[1].disk_usage_mb = Some(f64::NAN);
+        users[2].disk_usage_mb = Some(10.0);
+        users[3].disk_usage_mb = Some(500.0);
+        let mut app = app_with_users(users);
+        app.set_sort(SortBy::Disk);
+        assert_eq!(usernames(&app), ["large", "small", "broken", "unknown"]);
+    }
+
+    #[test]
+    fn equal_values_keep_their_order() {
+        let mut app = app_with_users(vec![
+            UserStats::test_fixture("first", 20.0, 100.0),
+            UserStats::test_fixture("second", 20.0, 100.0),
+            UserStats::test_fixture("third", 20.0, 100.0),
+        ]);
+        app.set_sort(SortBy::Cpu);
+        assert_eq!(usernames(&app), ["first", "second", "third"]);
+        app.set_sort(SortBy::Ram);
+        assert_eq!(usernames(&app), ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn poll_keeps_selection_inside_filtered_users() {
+        let mut app = app_with_users(Vec::new());
+        app.user_filter = "a".to_string();
+        let poll = |users: Vec<UserStats>| Snapshot {
+            users,
+            system: SystemStats::default(),
+            traffic: Traffic::default(),
+            network: HashMap::new(),
+            disks: HashMap::new(),
+            poll_duration: Duration::ZERO,
+            cpu_ticks: HashMap::new(),
+            clock_ticks_per_sec: 100,
+            io_bytes: HashMap::new(),
+        };
+        let generation = app.generation;
+        app.apply_snapshot(
+            generation,
+            0,
+            poll(vec![
+                UserStats::test_fixture("alice", 30.0, 100.0),
+                UserStats::test_fixture("anna", 20.0, 100.0),
+                UserStats::test_fixture("bob", 10.0, 100.0),
+            ]),
+        );
+        app.next_user();
+        assert_eq!(app.user_at_selection().map(|u| u.username.as_str()), Some("anna"));
+
+        // anna leaves; bob still keeps the unfiltered list at two users
+        app.apply_snapshot(
+            generation,
+            0,
+            poll(vec![
+                UserStats::test_fixture("alice", 30.0, 100.0),
+                UserStats::test_fixture("bob", 10.0, 100.0),
+            ]),
+        );
+        assert_eq!(app.user_at_selection().map(|u| u.username.as_str()), Some("alice"));
+    }
+
+    #[test]
+    fn sorting_no_users_is_a_no_op() {
+        let mut app = app_with_users(Vec::new());
+        app.set_sort(SortBy::Cpu);
+        app.set_sort(SortBy::Ram);
+        assert!(app.server().users.is_empty());
+    }
+
+    #[test]
+    fn filter_without_wildcards_matches_substrings() {
+        assert!(matches_filter("alice", "lic"));
+        assert!(matches_filter("alice", ""));
+        assert!(!matches_filter("alice", "bob"));
+    }
+
+    #[test]
+    fn filter_globs_match_the_whole_name() {
+        assert!(matches_filter("www-data", "*data"));
+        assert!(matches_filter("build01", "build*"));
+        assert!(matches_filter("svc_backup", "svc*up"));
+        assert!(matches_filter("user7", "user?"));
+        assert!(!matches_filter("user17", "user?"));
+        assert!(!matches_filter("www-data", "data*"));
+        assert!(!matches_filter("alice", "a*z"));
+    }
+
+    #[test]
+    fn filter_trailing_star_matches_nothing_more() {
+        assert!(matches_filter("alice", "alice*"));
+        assert!(matches_filter("alice", "alice**"));
+        assert!(matches_filter("alice", "*"));
+    }
+
+    #[test]
+    fn filter_ignores_case() {
+        assert!(matches_filter("Alice", "aLi"));
+        assert!(matches_filter("ALICE", "a*E"));
+        assert!(matches_filter("alice", "A?ICE"));
+    }
 }